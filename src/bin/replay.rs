@@ -0,0 +1,91 @@
+use net_battle_server::net::{load_recording, RecordedEvent};
+use net_battle_server::{logger, LuaPluginInterface, ServerBuilder, ServerConfig};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::path::PathBuf;
+
+fn main() {
+  logger::init();
+
+  let matches = clap::Command::new("OpenNetBattle Server Replay")
+    .about("Feeds a recording made with --record-packets into a fresh server")
+    .arg(
+      clap::Arg::new("recording")
+        .help("File created by --record-packets")
+        .value_name("FILE")
+        .required(true),
+    )
+    .arg(
+      clap::Arg::new("root")
+        .long("root")
+        .help("Folder containing the areas, assets, and scripts folders used while recording")
+        .value_name("DIR")
+        .default_value(".")
+        .takes_value(true),
+    )
+    .get_matches();
+
+  let recording_path = PathBuf::from(matches.value_of("recording").unwrap());
+  let root_dir = PathBuf::from(matches.value_of("root").unwrap());
+
+  let mut events = match load_recording(&recording_path) {
+    Ok(events) => events,
+    Err(err) => {
+      eprintln!("Failed to load {}: {}", recording_path.display(), err);
+      std::process::exit(1);
+    }
+  };
+
+  remap_addresses(&mut events);
+
+  let config = ServerConfig {
+    public_ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+    areas_dir: root_dir.join("areas"),
+    assets_dir: root_dir.join("assets"),
+    ban_list_path: root_dir.join("bans.toml"),
+    admin_console: false,
+    ..ServerConfig::default()
+  };
+
+  let mut server = ServerBuilder::new(config)
+    .plugin_interface(Box::new(LuaPluginInterface::new(root_dir.join("scripts"))))
+    .socket(UdpSocket::bind("127.0.0.1:0").unwrap())
+    .build();
+
+  let replay_handle = server.create_replay_handle();
+
+  std::thread::spawn(move || {
+    for event in events {
+      if !replay_handle.replay(event) {
+        return;
+      }
+    }
+
+    replay_handle.shutdown();
+  });
+
+  if let Err(err) = server.start() {
+    panic!("{}", err);
+  }
+}
+
+/// Gives each recorded ip its own loopback address, replies to replayed clients never leave the machine
+fn remap_addresses(events: &mut [RecordedEvent]) {
+  let mut ip_map = HashMap::<IpAddr, IpAddr>::new();
+
+  for event in events {
+    let socket_address = match event {
+      RecordedEvent::Packet { socket_address, .. }
+      | RecordedEvent::ResumeToken { socket_address, .. } => socket_address,
+      _ => continue,
+    };
+
+    let next_index = ip_map.len() as u32 + 1;
+
+    let ip = *ip_map
+      .entry(socket_address.ip())
+      .or_insert_with(|| IpAddr::V4(Ipv4Addr::from(u32::from(Ipv4Addr::LOCALHOST) + next_index)));
+
+    *socket_address = SocketAddr::new(ip, socket_address.port());
+  }
+}
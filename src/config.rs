@@ -0,0 +1,457 @@
+use net_battle_server::packets::NetworkConditions;
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+/// Settings loaded through `--config`, any field left out falls back to the command line defaults
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+  pub public_ip: Option<IpAddr>,
+  pub public_ip_resolver: Option<String>,
+  /// in seconds
+  pub public_ip_timeout: Option<f32>,
+  /// same as --bind, an ip address with an optional port
+  pub bind: Option<String>,
+  pub dual_stack: Option<bool>,
+  pub port: Option<u16>,
+  /// relative paths are relative to the working directory, not the config file
+  pub root: Option<PathBuf>,
+  pub areas_dir: Option<PathBuf>,
+  pub assets_dir: Option<PathBuf>,
+  pub scripts_dir: Option<PathBuf>,
+  /// overrides root
+  pub ban_list: Option<PathBuf>,
+  pub allow_list_only: Option<bool>,
+  pub maintenance: Option<bool>,
+  pub maintenance_message: Option<String>,
+  /// identities allowed to log in during maintenance
+  pub maintenance_exemptions: Option<Vec<String>>,
+  pub admin_console: Option<bool>,
+  pub compress_assets: Option<bool>,
+  pub log_connections: Option<bool>,
+  pub log_packets: Option<bool>,
+  pub max_payload_size: Option<u16>,
+  pub resend_budget: Option<isize>,
+  /// in KiB per second, same as --outgoing-rate-limit
+  pub outgoing_rate_limit: Option<usize>,
+  /// packets per second
+  pub packet_rate_limit: Option<u32>,
+  /// in seconds
+  pub flood_block_duration: Option<f32>,
+  pub max_connections: Option<usize>,
+  pub encryption: Option<bool>,
+  pub require_encryption: Option<bool>,
+  /// same as incoming_conditions.drop_rate, takes priority
+  pub receiving_drop_rate: Option<f32>,
+  pub incoming_conditions: Option<NetworkConditions>,
+  pub outgoing_conditions: Option<NetworkConditions>,
+  /// in KiB, same as --player-asset-limit
+  pub player_asset_limit: Option<usize>,
+  pub avatar_dimensions_limit: Option<u32>,
+  pub custom_emotes_path: Option<String>,
+  pub max_idle_packet_duration: Option<f32>,
+  pub max_silence_duration: Option<f32>,
+  /// 0 kicks silent players immediately
+  pub resume_grace_period: Option<f32>,
+  pub heartbeat_rate: Option<f32>,
+  /// ticks per second
+  pub tick_rate: Option<f64>,
+  pub max_players: Option<usize>,
+  pub login_queue_size: Option<usize>,
+  /// seconds between queue position updates
+  pub login_queue_update_rate: Option<f32>,
+  pub shutdown_kick_reason: Option<String>,
+  /// in seconds
+  pub shutdown_timeout: Option<f32>,
+  pub record_packets: Option<PathBuf>,
+}
+
+impl ConfigFile {
+  pub fn load(path: &Path) -> Result<ConfigFile, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|error| ConfigError::Read {
+      path: path.to_path_buf(),
+      error,
+    })?;
+
+    let config_file: ConfigFile = toml::from_str(&text).map_err(|error| ConfigError::Parse {
+      path: path.to_path_buf(),
+      error,
+    })?;
+
+    config_file
+      .validate()
+      .map_err(|(field, message)| ConfigError::Invalid {
+        path: path.to_path_buf(),
+        field,
+        message,
+      })?;
+
+    Ok(config_file)
+  }
+
+  fn validate(&self) -> Result<(), (&'static str, String)> {
+    fn check<T: Copy>(
+      field: &'static str,
+      value: Option<T>,
+      validator: fn(T) -> Result<(), String>,
+    ) -> Result<(), (&'static str, String)> {
+      match value {
+        Some(value) => validator(value).map_err(|message| (field, message)),
+        None => Ok(()),
+      }
+    }
+
+    if let Some(bind) = &self.bind {
+      parse_bind_address(bind).map_err(|message| ("bind", message))?;
+    }
+
+    check(
+      "public_ip_timeout",
+      self.public_ip_timeout,
+      validate_duration,
+    )?;
+    check("port", self.port, validate_port)?;
+    check(
+      "max_payload_size",
+      self.max_payload_size,
+      validate_max_payload_size,
+    )?;
+    check("resend_budget", self.resend_budget, validate_resend_budget)?;
+    check(
+      "flood_block_duration",
+      self.flood_block_duration,
+      validate_duration,
+    )?;
+    check(
+      "max_connections",
+      self.max_connections,
+      validate_max_players,
+    )?;
+    check(
+      "receiving_drop_rate",
+      self.receiving_drop_rate,
+      validate_drop_rate,
+    )?;
+
+    if let Some(conditions) = &self.incoming_conditions {
+      validate_network_conditions(conditions)
+        .map_err(|message| ("incoming_conditions", message))?;
+    }
+
+    if let Some(conditions) = &self.outgoing_conditions {
+      validate_network_conditions(conditions)
+        .map_err(|message| ("outgoing_conditions", message))?;
+    }
+
+    check(
+      "max_idle_packet_duration",
+      self.max_idle_packet_duration,
+      validate_duration,
+    )?;
+    check(
+      "max_silence_duration",
+      self.max_silence_duration,
+      validate_duration,
+    )?;
+    check(
+      "resume_grace_period",
+      self.resume_grace_period,
+      validate_optional_duration,
+    )?;
+    check("heartbeat_rate", self.heartbeat_rate, validate_duration)?;
+    check("tick_rate", self.tick_rate, validate_tick_rate)?;
+    check("max_players", self.max_players, validate_max_players)?;
+    check(
+      "login_queue_update_rate",
+      self.login_queue_update_rate,
+      validate_duration,
+    )?;
+    check("shutdown_timeout", self.shutdown_timeout, validate_duration)?;
+
+    if let Some(path) = &self.custom_emotes_path {
+      validate_custom_emotes_path(path).map_err(|message| ("custom_emotes_path", message))?;
+    }
+
+    Ok(())
+  }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+  Read {
+    path: PathBuf,
+    error: std::io::Error,
+  },
+  Parse {
+    path: PathBuf,
+    error: toml::de::Error,
+  },
+  Invalid {
+    path: PathBuf,
+    field: &'static str,
+    message: String,
+  },
+}
+
+impl std::fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ConfigError::Read { path, error } => {
+        write!(f, "Failed to read config {}: {}", path.display(), error)
+      }
+      ConfigError::Parse { path, error } => {
+        write!(f, "Failed to parse config {}: {}", path.display(), error)
+      }
+      ConfigError::Invalid {
+        path,
+        field,
+        message,
+      } => {
+        write!(
+          f,
+          "Invalid {} in config {}: {}",
+          field,
+          path.display(),
+          message
+        )
+      }
+    }
+  }
+}
+
+impl std::error::Error for ConfigError {}
+
+// validators shared by the command line and the config file
+
+pub fn validate_port(port: u16) -> Result<(), String> {
+  if port != 0 {
+    Ok(())
+  } else {
+    Err(String::from("PORT must be > 0 and < 65535"))
+  }
+}
+
+/// Accepts `ip`, `ip:port`, or `[ipv6]:port`
+pub fn parse_bind_address(value: &str) -> Result<(IpAddr, Option<u16>), String> {
+  if let Ok(socket_address) = value.parse::<SocketAddr>() {
+    validate_port(socket_address.port())?;
+
+    return Ok((socket_address.ip(), Some(socket_address.port())));
+  }
+
+  let ip_str = value.trim_start_matches('[').trim_end_matches(']');
+
+  match ip_str.parse::<IpAddr>() {
+    Ok(ip) => Ok((ip, None)),
+    Err(_) => Err(String::from(
+      "ADDRESS must be an IPv4 or IPv6 address with an optional port",
+    )),
+  }
+}
+
+pub fn validate_max_payload_size(max_payload_size: u16) -> Result<(), String> {
+  // max size defined by NetPlayConfig::MAX_BUFFER_LEN
+  if (100..=10240).contains(&max_payload_size) {
+    Ok(())
+  } else {
+    Err(String::from("Invalid payload size"))
+  }
+}
+
+pub fn validate_resend_budget(resend_budget: isize) -> Result<(), String> {
+  if resend_budget < 0 {
+    Err(String::from("Invalid size"))
+  } else {
+    Ok(())
+  }
+}
+
+pub fn validate_drop_rate(drop_rate: f32) -> Result<(), String> {
+  if !(0.0..=100.0).contains(&drop_rate) {
+    Err(String::from("PERCENTAGE must be between 0.0 and 100.0"))
+  } else {
+    Ok(())
+  }
+}
+
+/// Accepts comma separated `key=value` pairs using the same keys as the config file
+pub fn parse_network_conditions(value: &str) -> Result<NetworkConditions, String> {
+  let toml_text = value.replace(',', "\n");
+
+  let conditions = toml::from_str(&toml_text).map_err(|_| {
+    String::from(
+      "CONDITIONS must be comma separated key=value pairs, such as \"drop_rate=5,latency=0.1\"",
+    )
+  })?;
+
+  validate_network_conditions(&conditions)?;
+
+  Ok(conditions)
+}
+
+pub fn validate_network_conditions(conditions: &NetworkConditions) -> Result<(), String> {
+  validate_drop_rate(conditions.drop_rate)?;
+  validate_drop_rate(conditions.duplicate_rate)?;
+  validate_drop_rate(conditions.reorder_rate)?;
+
+  if conditions.latency >= 0.0
+    && conditions.latency.is_finite()
+    && conditions.jitter >= 0.0
+    && conditions.jitter.is_finite()
+  {
+    Ok(())
+  } else {
+    Err(String::from(
+      "latency and jitter must be 0.0 or more seconds",
+    ))
+  }
+}
+
+pub fn validate_duration(duration: f32) -> Result<(), String> {
+  if duration > 0.0 && duration.is_finite() {
+    Ok(())
+  } else {
+    Err(String::from("SECONDS must be greater than 0.0"))
+  }
+}
+
+/// Accepts 0.0 for durations that can be disabled
+pub fn validate_optional_duration(duration: f32) -> Result<(), String> {
+  if duration >= 0.0 && duration.is_finite() {
+    Ok(())
+  } else {
+    Err(String::from("SECONDS must be 0.0 or greater"))
+  }
+}
+
+pub fn validate_tick_rate(tick_rate: f64) -> Result<(), String> {
+  if (1.0..=1000.0).contains(&tick_rate) {
+    Ok(())
+  } else {
+    Err(String::from(
+      "TICKS_PER_SECOND must be between 1.0 and 1000.0",
+    ))
+  }
+}
+
+pub fn validate_max_players(max_players: usize) -> Result<(), String> {
+  if max_players > 0 {
+    Ok(())
+  } else {
+    Err(String::from("COUNT must be greater than 0"))
+  }
+}
+
+pub fn validate_custom_emotes_path(path: &str) -> Result<(), String> {
+  if path.starts_with("/server/assets/") {
+    Ok(())
+  } else {
+    Err(String::from(
+      "ASSET_PATH must start with \"/server/assets/\"",
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn validation() {
+    let config_file: ConfigFile = toml::from_str(
+      r#"
+        port = 8766
+        log_packets = true
+        max_silence_duration = 10.0
+        custom_emotes_path = "/server/assets/emotes.png"
+      "#,
+    )
+    .unwrap();
+
+    assert!(config_file.validate().is_ok());
+    assert_eq!(config_file.port, Some(8766));
+    assert_eq!(config_file.max_silence_duration, Some(10.0));
+
+    let config_file: ConfigFile = toml::from_str("max_payload_size = 50").unwrap();
+
+    assert_eq!(
+      config_file.validate().map_err(|(field, _)| field),
+      Err("max_payload_size"),
+      "payload size below the client minimum should be rejected"
+    );
+
+    let config_file: ConfigFile = toml::from_str("bind = \"localhost\"").unwrap();
+
+    assert_eq!(
+      config_file.validate().map_err(|(field, _)| field),
+      Err("bind"),
+      "bind should only accept ip addresses"
+    );
+
+    assert!(
+      toml::from_str::<ConfigFile>("prot = 8765").is_err(),
+      "unknown keys should be rejected"
+    );
+  }
+
+  #[test]
+  fn bind_addresses() {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    assert_eq!(
+      parse_bind_address("0.0.0.0"),
+      Ok((IpAddr::from(Ipv4Addr::UNSPECIFIED), None))
+    );
+    assert_eq!(
+      parse_bind_address("192.168.1.2:9000"),
+      Ok((IpAddr::from([192, 168, 1, 2]), Some(9000)))
+    );
+    assert_eq!(
+      parse_bind_address("::"),
+      Ok((IpAddr::from(Ipv6Addr::UNSPECIFIED), None))
+    );
+    assert_eq!(
+      parse_bind_address("[::1]"),
+      Ok((IpAddr::from(Ipv6Addr::LOCALHOST), None))
+    );
+    assert_eq!(
+      parse_bind_address("[::1]:9000"),
+      Ok((IpAddr::from(Ipv6Addr::LOCALHOST), Some(9000)))
+    );
+    assert!(parse_bind_address("[::1]:0").is_err());
+    assert!(parse_bind_address("example.com:9000").is_err());
+  }
+
+  #[test]
+  fn network_conditions() {
+    assert_eq!(
+      parse_network_conditions("drop_rate=5,latency=0.1"),
+      Ok(NetworkConditions {
+        drop_rate: 5.0,
+        latency: 0.1,
+        ..Default::default()
+      })
+    );
+    assert_eq!(
+      parse_network_conditions(""),
+      Ok(NetworkConditions::default())
+    );
+    assert!(parse_network_conditions("drop_rate=150").is_err());
+    assert!(parse_network_conditions("latency=-1").is_err());
+    assert!(parse_network_conditions("lag=0.1").is_err());
+
+    let config_file: ConfigFile = toml::from_str(
+      r#"
+        [outgoing_conditions]
+        duplicate_rate = 110
+      "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+      config_file.validate().map_err(|(field, _)| field),
+      Err("outgoing_conditions")
+    );
+  }
+}
@@ -35,8 +35,19 @@ pub fn normalize_path(path: &std::path::Path) -> std::path::PathBuf {
 
 // use is_global when it's stabilized https://github.com/rust-lang/rust/issues/27709
 fn is_internal_ip(ip: IpAddr) -> bool {
-  match ip {
-    IpAddr::V6(ipv6) => ipv6.is_loopback(),
+  match canonical_ip(ip) {
+    IpAddr::V6(ipv6) => {
+      let first_segment = ipv6.segments()[0];
+
+      ipv6.is_loopback()
+        || ipv6.is_unspecified()
+        // unique local fc00::/7
+        || (first_segment & 0xfe00) == 0xfc00
+        // link local fe80::/10
+        || (first_segment & 0xffc0) == 0xfe80
+        // documentation 2001:db8::/32
+        || (first_segment == 0x2001 && ipv6.segments()[1] == 0x0db8)
+    }
     IpAddr::V4(ipv4) => {
       ipv4.is_private()
         || ipv4.is_loopback()
@@ -47,8 +58,48 @@ fn is_internal_ip(ip: IpAddr) -> bool {
   }
 }
 
+/* \brief converts IPv4-mapped IPv6 addresses (from dual stack sockets) back into IPv4 addresses */
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+  match ip {
+    IpAddr::V6(ipv6) => match ipv6.to_ipv4_mapped() {
+      Some(ipv4) => IpAddr::V4(ipv4),
+      None => IpAddr::V6(ipv6),
+    },
+    ip => ip,
+  }
+}
+
+pub fn canonical_socket_addr(address: SocketAddr) -> SocketAddr {
+  SocketAddr::new(canonical_ip(address.ip()), address.port())
+}
+
+/* \brief converts an address to one sendable through a socket bound to local_address */
+pub fn match_socket_family(address: SocketAddr, local_address: SocketAddr) -> Option<SocketAddr> {
+  match (canonical_ip(address.ip()), local_address.ip()) {
+    (IpAddr::V4(ipv4), IpAddr::V6(_)) => Some(SocketAddr::new(
+      IpAddr::V6(ipv4.to_ipv6_mapped()),
+      address.port(),
+    )),
+    (IpAddr::V6(_), IpAddr::V4(_)) => None,
+    (ip, _) => Some(SocketAddr::new(ip, address.port())),
+  }
+}
+
+/* \brief "[::1]" -> "::1", other addresses are returned as is */
+pub fn strip_ipv6_brackets(address: &str) -> &str {
+  let stripped = address
+    .strip_prefix('[')
+    .and_then(|address| address.strip_suffix(']'));
+
+  match stripped {
+    Some(ipv6) if ipv6.parse::<std::net::Ipv6Addr>().is_ok() => ipv6,
+    _ => address,
+  }
+}
+
 /* \brief makes a localhost ip useable for pvp */
 pub fn use_public_ip(address: SocketAddr, public_ip: IpAddr) -> SocketAddr {
+  let address = canonical_socket_addr(address);
   let ip = address.ip();
 
   if is_internal_ip(ip) {
@@ -57,5 +108,3 @@ pub fn use_public_ip(address: SocketAddr, public_ip: IpAddr) -> SocketAddr {
 
   address
 }
-
-pub mod iterators;
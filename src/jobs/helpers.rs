@@ -1,7 +1,15 @@
 pub async fn resolve_socket_addr(address: &str, port: u16) -> Option<std::net::SocketAddr> {
+  resolve_socket_addrs(address, port).await?.next()
+}
+
+pub async fn resolve_socket_addrs(
+  address: &str,
+  port: u16,
+) -> Option<impl Iterator<Item = std::net::SocketAddr>> {
+  use crate::helpers::strip_ipv6_brackets;
   use async_std::net::ToSocketAddrs;
-  let address_port_pair = (address, port);
-  let mut socket_addrs = address_port_pair.to_socket_addrs().await.ok()?;
 
-  socket_addrs.next()
+  let address_port_pair = (strip_ipv6_brackets(address), port);
+
+  address_port_pair.to_socket_addrs().await.ok()
 }
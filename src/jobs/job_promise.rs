@@ -5,7 +5,10 @@ pub enum PromiseValue {
   HttpResponse(HttpResponse),
   Bytes(Vec<u8>),
   Success(bool),
-  ServerInfo { max_message_size: u16 },
+  ServerInfo {
+    max_message_size: u16,
+    maintenance_message: Option<String>,
+  },
   None,
 }
 
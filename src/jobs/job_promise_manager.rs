@@ -22,6 +22,10 @@ impl JobPromiseManager {
     self.promises.get_mut(&id)
   }
 
+  pub fn has_pending_promises(&self) -> bool {
+    self.promises.values().any(|promise| promise.is_pending())
+  }
+
   pub fn add_promise(&mut self, promise: JobPromise) -> usize {
     let id = self.next_id;
 
@@ -1,9 +1,24 @@
 pub fn message_server(socket: std::net::UdpSocket, address: String, port: u16, data: Vec<u8>) {
   async_std::task::spawn(async move {
     use super::helpers::*;
+    use crate::helpers::match_socket_family;
     use crate::packets::bytes::*;
 
-    let socket_addr = if let Some(socket_addr) = resolve_socket_addr(address.as_str(), port).await {
+    let local_addr = if let Ok(local_addr) = socket.local_addr() {
+      local_addr
+    } else {
+      return;
+    };
+
+    // the server socket may be IPv4 or IPv6, find an address we can send to
+    let socket_addr =
+      resolve_socket_addrs(address.as_str(), port)
+        .await
+        .and_then(|mut socket_addrs| {
+          socket_addrs.find_map(|socket_addr| match_socket_family(socket_addr, local_addr))
+        });
+
+    let socket_addr = if let Some(socket_addr) = socket_addr {
       socket_addr
     } else {
       return;
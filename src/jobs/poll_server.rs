@@ -18,7 +18,13 @@ pub fn poll_server(address: String, port: u16) -> JobPromise {
       return;
     };
 
-    let socket = if let Ok(socket) = UdpSocket::bind("0.0.0.0:0").await {
+    let bind_address = if socket_addr.is_ipv4() {
+      "0.0.0.0:0"
+    } else {
+      "[::]:0"
+    };
+
+    let socket = if let Ok(socket) = UdpSocket::bind(bind_address).await {
       socket
     } else {
       thread_promise.set_value(PromiseValue::None);
@@ -46,8 +52,8 @@ pub fn poll_server(address: String, port: u16) -> JobPromise {
 
       futures::select! {
         result = response_fut => {
-          if let Some(max_message_size) = result {
-            thread_promise.set_value(PromiseValue::ServerInfo { max_message_size });
+          if let Some(server_info) = result {
+            thread_promise.set_value(server_info);
             return;
           }
         },
@@ -63,7 +69,7 @@ pub fn poll_server(address: String, port: u16) -> JobPromise {
   promise
 }
 
-async fn get_response(socket: &UdpSocket) -> Option<u16> {
+async fn get_response(socket: &UdpSocket) -> Option<PromiseValue> {
   use crate::packets::bytes::*;
 
   // max size defined by NetPlayConfig::MAX_BUFFER_LEN
@@ -104,7 +110,13 @@ async fn get_response(socket: &UdpSocket) -> Option<u16> {
     let header_size = 1 + 2;
     let max_message_size = max_payload_size - header_size;
 
-    return Some(max_message_size);
+    let maintenance = read_bool(slice)?;
+    let maintenance_message = read_string_u16(slice)?;
+
+    return Some(PromiseValue::ServerInfo {
+      max_message_size,
+      maintenance_message: maintenance.then_some(maintenance_message),
+    });
   }
 
   None
@@ -0,0 +1,14 @@
+//! Scriptable OpenNetBattle server, exposed as a library for embedding the server or testing against it.
+//!
+//! Use [`ServerBuilder`] to create a [`Server`] with custom [`PluginInterface`]s or a pre-bound socket.
+
+mod helpers;
+mod jobs;
+pub mod logger;
+pub mod net;
+pub mod packets;
+pub mod plugins;
+mod threads;
+
+pub use net::{ManualClock, Net, ReplayHandle, Server, ServerBuilder, ServerConfig};
+pub use plugins::{LuaPluginInterface, PluginInterface};
@@ -1,19 +1,23 @@
-mod helpers;
-mod jobs;
-mod logger;
-mod net;
-mod packets;
-mod plugins;
-mod threads;
-
-use helpers::unwrap_and_parse_or_default;
-use plugins::LuaPluginInterface;
-use std::net::IpAddr;
+mod config;
+
+use config::ConfigFile;
+use log::*;
+use net_battle_server::packets::NetworkConditions;
+use net_battle_server::{logger, LuaPluginInterface, ServerBuilder, ServerConfig};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
 
 fn main() {
   logger::init();
 
   let matches = clap::Command::new("OpenNetBattle Server")
+    .arg(
+      clap::Arg::new("config")
+        .long("config")
+        .help("Loads settings from a TOML file, command line options take priority")
+        .value_name("PATH")
+        .takes_value(true),
+    )
     .arg(
       clap::Arg::new("port")
         .short('p')
@@ -22,47 +26,195 @@ fn main() {
         .default_value("8765")
         .takes_value(true)
         .validator(|value| {
-          let error_message = "PORT must be > 0 and < 65535";
           let port = value
             .parse::<u16>()
-            .map_err(|_| String::from(error_message))?;
+            .map_err(|_| String::from("PORT must be > 0 and < 65535"))?;
+
+          config::validate_port(port)
+        }),
+    )
+    .arg(
+      clap::Arg::new("public_ip")
+        .long("public-ip")
+        .help("Address shared with clients for PvP, skips looking up the public ip")
+        .value_name("IP")
+        .takes_value(true)
+        .validator(|value| match value.parse::<IpAddr>() {
+          Ok(_) => Ok(()),
+          Err(_) => Err(String::from("IP must be an IPv4 or IPv6 address")),
+        }),
+    )
+    .arg(
+      clap::Arg::new("public_ip_resolver")
+        .long("public-ip-resolver")
+        .help("URL responding with the public ip as plain text, used when the public ip isn't set")
+        .value_name("URL")
+        .default_value("http://checkip.amazonaws.com")
+        .takes_value(true),
+    )
+    .arg(
+      clap::Arg::new("public_ip_timeout")
+        .long("public-ip-timeout")
+        .help("Time to wait for the public ip resolver before falling back to 127.0.0.1")
+        .value_name("SECONDS")
+        .default_value("5.0")
+        .takes_value(true)
+        .validator(|value| {
+          let duration = value
+            .parse::<f32>()
+            .map_err(|_| String::from("SECONDS must be greater than 0.0"))?;
 
-          if port != 0 {
-            Ok(())
-          } else {
-            Err(String::from(error_message))
-          }
+          config::validate_duration(duration)
         }),
     )
+    .arg(
+      clap::Arg::new("bind")
+        .long("bind")
+        .help("Address to listen on, accepts IPv4 and IPv6 addresses with an optional port")
+        .value_name("ADDRESS")
+        .takes_value(true)
+        .validator(|value| config::parse_bind_address(value).map(|_| ())),
+    )
+    .arg(
+      clap::Arg::new("dual_stack")
+        .long("dual-stack")
+        .help("Accepts IPv4 and IPv6 clients on the same socket (requires an IPv6 bind address)")
+        .overrides_with("no_dual_stack"),
+    )
+    .arg(
+      clap::Arg::new("no_dual_stack")
+        .long("no-dual-stack")
+        .help("Overrides dual_stack = true from the config file")
+        .overrides_with("dual_stack"),
+    )
+    .arg(
+      clap::Arg::new("root")
+        .long("root")
+        .help("Folder containing the areas, assets, and scripts folders")
+        .value_name("DIR")
+        .takes_value(true),
+    )
+    .arg(
+      clap::Arg::new("areas_dir")
+        .long("areas-dir")
+        .help("Overrides the areas folder, defaults to ROOT/areas")
+        .value_name("DIR")
+        .takes_value(true),
+    )
+    .arg(
+      clap::Arg::new("assets_dir")
+        .long("assets-dir")
+        .help("Overrides the assets folder shared as /server/assets/, defaults to ROOT/assets")
+        .value_name("DIR")
+        .takes_value(true),
+    )
+    .arg(
+      clap::Arg::new("scripts_dir")
+        .long("scripts-dir")
+        .help("Overrides the scripts folder, defaults to ROOT/scripts")
+        .value_name("DIR")
+        .takes_value(true),
+    )
+    .arg(
+      clap::Arg::new("ban_list")
+        .long("ban-list")
+        .help("File storing bans and the allow list, defaults to ROOT/bans.toml")
+        .value_name("FILE")
+        .takes_value(true),
+    )
+    .arg(
+      clap::Arg::new("allow_list_only")
+        .long("allow-list-only")
+        .help("Only accepts logins from identities and addresses on the allow list")
+        .overrides_with("no_allow_list_only"),
+    )
+    .arg(
+      clap::Arg::new("no_allow_list_only")
+        .long("no-allow-list-only")
+        .help("Overrides allow_list_only = true from the config file")
+        .overrides_with("allow_list_only"),
+    )
+    .arg(
+      clap::Arg::new("maintenance")
+        .long("maintenance")
+        .help("Starts in maintenance mode, refusing new logins from identities without an exemption")
+        .overrides_with("no_maintenance"),
+    )
+    .arg(
+      clap::Arg::new("no_maintenance")
+        .long("no-maintenance")
+        .help("Overrides maintenance = true from the config file")
+        .overrides_with("maintenance"),
+    )
+    .arg(
+      clap::Arg::new("maintenance_message")
+        .long("maintenance-message")
+        .help("Reason given to players refused during maintenance")
+        .value_name("MESSAGE")
+        .default_value("Server is under maintenance")
+        .takes_value(true),
+    )
+    .arg(
+      clap::Arg::new("no_admin_console")
+        .long("no-admin-console")
+        .help("Stops reading admin commands such as \"maintenance on\" from stdin")
+        .overrides_with("admin_console"),
+    )
+    .arg(
+      clap::Arg::new("admin_console")
+        .long("admin-console")
+        .help("Overrides admin_console = false from the config file")
+        .overrides_with("no_admin_console"),
+    )
+    .arg(
+      clap::Arg::new("no_asset_compression")
+        .long("no-asset-compression")
+        .help("Sends text and data assets uncompressed, even to clients that support compression")
+        .overrides_with("asset_compression"),
+    )
+    .arg(
+      clap::Arg::new("asset_compression")
+        .long("asset-compression")
+        .help("Overrides compress_assets = false from the config file")
+        .overrides_with("no_asset_compression"),
+    )
     .arg(
       clap::Arg::new("log_connections")
         .long("log-connections")
-        .help("Logs connects and disconnects"),
+        .help("Logs connects and disconnects")
+        .overrides_with("no_log_connections"),
+    )
+    .arg(
+      clap::Arg::new("no_log_connections")
+        .long("no-log-connections")
+        .help("Overrides log_connections = true from the config file")
+        .overrides_with("log_connections"),
     )
     .arg(
       clap::Arg::new("log_packets")
         .long("log-packets")
-        .help("Logs received packets (useful for debugging)"),
+        .help("Logs received packets (useful for debugging)")
+        .overrides_with("no_log_packets"),
+    )
+    .arg(
+      clap::Arg::new("no_log_packets")
+        .long("no-log-packets")
+        .help("Overrides log_packets = true from the config file")
+        .overrides_with("log_packets"),
     )
     .arg(
       clap::Arg::new("max_payload_size")
         .long("max-payload-size")
-        .help("Maximum data size a packet can carry, excluding UDP headers (reduce for lower packet drop rate)")
+        .help("Upper bound for the data size a packet can carry, excluding UDP headers. Each client is probed for the largest size reaching them")
         .value_name("SIZE_IN_BYTES")
         .default_value("1400")
         .takes_value(true)
         .validator(|value| {
-          let error_message = "Invalid payload size";
           let max_payload_size = value
             .parse::<u16>()
-            .map_err(|_| String::from(error_message))?;
+            .map_err(|_| String::from("Invalid payload size"))?;
 
-          // max size defined by NetPlayConfig::MAX_BUFFER_LEN
-          if (100..=10240).contains(&max_payload_size) {
-            Ok(())
-          } else {
-            Err(String::from(error_message))
-          }
+          config::validate_max_payload_size(max_payload_size)
         }),
     )
     .arg(
@@ -73,36 +225,122 @@ fn main() {
         .default_value("65536") // nearest power of a power of two to (test data / 2 skips / 2 for safety / 2 reliability types)
         .takes_value(true)
         .validator(|value| {
-          let error_message = "Invalid size";
+          let resend_budget = value
+            .parse::<isize>()
+            .map_err(|_| String::from("Invalid size"))?;
 
-          let resend_budget = value.parse::<isize>().map_err(|_| String::from(error_message))?;
+          config::validate_resend_budget(resend_budget)
+        }),
+    )
+    .arg(
+      clap::Arg::new("outgoing_rate_limit")
+        .long("outgoing-rate-limit")
+        .help("Bytes per second sent to each client (in KiB), movement and widgets are sent before asset streams past this limit. 0 for unlimited")
+        .value_name("SIZE_IN_KiB")
+        .default_value("1024")
+        .takes_value(true)
+        .validator(|value| match value.parse::<usize>() {
+          Ok(_) => Ok(()),
+          Err(_) => Err(String::from("Invalid rate")),
+        }),
+    )
+    .arg(
+      clap::Arg::new("packet_rate_limit")
+        .long("packet-rate-limit")
+        .help("Packets per second received from each ip address, addresses that keep exceeding this are temporarily blocked. 0 for unlimited")
+        .value_name("PACKETS_PER_SECOND")
+        .default_value("1000")
+        .takes_value(true)
+        .validator(|value| match value.parse::<u32>() {
+          Ok(_) => Ok(()),
+          Err(_) => Err(String::from("Invalid rate")),
+        }),
+    )
+    .arg(
+      clap::Arg::new("flood_block_duration")
+        .long("flood-block-duration")
+        .help("Time to ignore ip addresses that keep sending invalid packets or exceeding the packet rate limit")
+        .value_name("SECONDS")
+        .default_value("60.0")
+        .takes_value(true)
+        .validator(|value| {
+          let duration = value
+            .parse::<f32>()
+            .map_err(|_| String::from("SECONDS must be greater than 0.0"))?;
 
-          if resend_budget < 0 {
-            Err(String::from(error_message))
-          } else {
-            Ok(())
-          }
+          config::validate_duration(duration)
         }),
     )
+    .arg(
+      clap::Arg::new("max_connections")
+        .long("max-connections")
+        .help("Limits connections tracked at once, including players, queued logins, and clients that haven't logged in yet")
+        .value_name("COUNT")
+        .default_value("1000")
+        .takes_value(true)
+        .validator(|value| {
+          let max_connections = value
+            .parse::<usize>()
+            .map_err(|_| String::from("COUNT must be greater than 0"))?;
+
+          config::validate_max_players(max_connections)
+        }),
+    )
+    .arg(
+      clap::Arg::new("no_encryption")
+        .long("no-encryption")
+        .help("Stops offering clients a key exchange, sessions are sent unencrypted")
+        .conflicts_with("require_encryption")
+        .overrides_with("encryption"),
+    )
+    .arg(
+      clap::Arg::new("encryption")
+        .long("encryption")
+        .help("Overrides encryption = false from the config file")
+        .overrides_with("no_encryption"),
+    )
+    .arg(
+      clap::Arg::new("require_encryption")
+        .long("require-encryption")
+        .help("Ignores clients that don't encrypt their session")
+        .overrides_with("no_require_encryption"),
+    )
+    .arg(
+      clap::Arg::new("no_require_encryption")
+        .long("no-require-encryption")
+        .help("Overrides require_encryption = true from the config file")
+        .overrides_with("require_encryption"),
+    )
     .arg(
       clap::Arg::new("receiving_drop_rate")
         .long("receiving-drop-rate")
-        .help("Rate of received packets to randomly drop for simulating an unstable connection")
+        .help("Rate of received packets to randomly drop, same as --incoming-conditions drop_rate=PERCENTAGE")
         .value_name("PERCENTAGE")
-        .default_value("0.0")
         .takes_value(true)
         .validator(|value| {
-          let error_message = "PERCENTAGE must be between 0.0 and 100.0";
-
-          let resend_budget = value.parse::<f32>().map_err(|_| String::from(error_message))?;
+          let drop_rate = value
+            .parse::<f32>()
+            .map_err(|_| String::from("PERCENTAGE must be between 0.0 and 100.0"))?;
 
-          if !(0.0..=100.0).contains(&resend_budget) {
-            Err(String::from(error_message))
-          } else {
-            Ok(())
-          }
+          config::validate_drop_rate(drop_rate)
         }),
     )
+    .arg(
+      clap::Arg::new("incoming_conditions")
+        .long("incoming-conditions")
+        .help("Simulates an unstable connection for received packets, such as \"drop_rate=5,latency=0.1,jitter=0.02,duplicate_rate=1,reorder_rate=1\"")
+        .value_name("CONDITIONS")
+        .takes_value(true)
+        .validator(config::parse_network_conditions),
+    )
+    .arg(
+      clap::Arg::new("outgoing_conditions")
+        .long("outgoing-conditions")
+        .help("Simulates an unstable connection for sent packets, same format as --incoming-conditions")
+        .value_name("CONDITIONS")
+        .takes_value(true)
+        .validator(config::parse_network_conditions),
+    )
     .arg(
       clap::Arg::new("player_asset_limit")
         .long("player-asset-limit")
@@ -131,67 +369,396 @@ fn main() {
       clap::Arg::new("custom_emotes_path")
         .long("custom-emotes-path")
         .value_name("ASSET_PATH")
+        .validator(config::validate_custom_emotes_path),
+    )
+    .arg(
+      clap::Arg::new("tick_rate")
+        .long("tick-rate")
+        .help("Rate the server ticks and plugins receive the tick event")
+        .value_name("TICKS_PER_SECOND")
+        .default_value("20.0")
+        .takes_value(true)
         .validator(|value| {
-          if value.starts_with("/server/assets/") {
-            Ok(())
-          } else {
-            Err(String::from("ASSET_PATH must start with \"/server/assets/\""))
-          }
+          let tick_rate = value
+            .parse::<f64>()
+            .map_err(|_| String::from("TICKS_PER_SECOND must be between 1.0 and 1000.0"))?;
+
+          config::validate_tick_rate(tick_rate)
         }),
     )
+    .arg(
+      clap::Arg::new("max_players")
+        .long("max-players")
+        .help("Limits players on the server, extra logins wait in a queue")
+        .value_name("COUNT")
+        .takes_value(true)
+        .validator(|value| {
+          let max_players = value
+            .parse::<usize>()
+            .map_err(|_| String::from("COUNT must be greater than 0"))?;
+
+          config::validate_max_players(max_players)
+        }),
+    )
+    .arg(
+      clap::Arg::new("login_queue_size")
+        .long("login-queue-size")
+        .help("Logins waiting for space past this limit are rejected, 0 disables the queue")
+        .value_name("COUNT")
+        .default_value("100")
+        .takes_value(true)
+        .validator(|value| match value.parse::<usize>() {
+          Ok(_) => Ok(()),
+          Err(_) => Err(String::from("Invalid count")),
+        }),
+    )
+    .arg(
+      clap::Arg::new("shutdown_kick_reason")
+        .long("shutdown-kick-reason")
+        .help("Reason given to players kicked when the server shuts down")
+        .value_name("REASON")
+        .default_value("Server shutting down")
+        .takes_value(true),
+    )
+    .arg(
+      clap::Arg::new("shutdown_timeout")
+        .long("shutdown-timeout")
+        .help("Time to wait for packets to be received and for scripts to finish jobs when shutting down")
+        .value_name("SECONDS")
+        .default_value("5.0")
+        .takes_value(true)
+        .validator(|value| {
+          let duration = value
+            .parse::<f32>()
+            .map_err(|_| String::from("SECONDS must be greater than 0.0"))?;
+
+          config::validate_duration(duration)
+        }),
+    )
+    .arg(
+      clap::Arg::new("record_packets")
+        .long("record-packets")
+        .help("Records every received packet to a file, for use with the replay binary")
+        .value_name("FILE")
+        .takes_value(true),
+    )
     .get_matches();
 
-  let config = net::ServerConfig {
-    public_ip: get_public_ip().unwrap_or_else(|_| IpAddr::from([127, 0, 0, 1])), // default to localhost
-    // validators makes these safe to unwrap
-    port: matches.value_of("port").unwrap().parse().unwrap(),
-    log_connections: matches.is_present("log_connections"),
-    log_packets: matches.is_present("log_packets"),
-    max_payload_size: unwrap_and_parse_or_default(matches.value_of("max_payload_size")),
-    resend_budget: matches.value_of("resend_budget").unwrap().parse().unwrap(),
-    receiving_drop_rate: matches
-      .value_of("receiving_drop_rate")
-      .unwrap()
-      .parse()
-      .unwrap(),
-    player_asset_limit: unwrap_and_parse_or_default::<usize>(
-      matches.value_of("player_asset_limit"),
+  let config_file = match matches.value_of("config") {
+    Some(path) => match ConfigFile::load(std::path::Path::new(path)) {
+      Ok(config_file) => config_file,
+      Err(err) => {
+        error!("{}", err);
+        std::process::exit(1);
+      }
+    },
+    None => ConfigFile::default(),
+  };
+
+  let public_ip = resolve_public_ip(&matches, &config_file);
+
+  // validators makes these safe to unwrap
+  let bind_address = matches
+    .value_of("bind")
+    .or(config_file.bind.as_deref())
+    .map(|value| config::parse_bind_address(value).unwrap());
+
+  let dual_stack = resolve_flag(&matches, "dual_stack", config_file.dual_stack, false);
+
+  let bind_ip = match bind_address {
+    Some((ip, _)) => ip,
+    None if dual_stack => IpAddr::from(Ipv6Addr::UNSPECIFIED),
+    None => IpAddr::from(Ipv4Addr::UNSPECIFIED),
+  };
+
+  if dual_stack && bind_ip.is_ipv4() {
+    error!(
+      "Dual stack listening requires an IPv6 bind address, received {}",
+      bind_ip
+    );
+    std::process::exit(1);
+  }
+
+  let bind_port = bind_address.and_then(|(_, port)| port);
+
+  let root_dir = matches
+    .value_of("root")
+    .map(PathBuf::from)
+    .or(config_file.root.clone())
+    .unwrap_or_else(|| PathBuf::from("."));
+
+  let resolve_dir = |name: &str, config_file_value: &Option<PathBuf>| {
+    matches
+      .value_of(name)
+      .map(PathBuf::from)
+      .or_else(|| config_file_value.clone())
+      .unwrap_or_else(|| root_dir.join(name.trim_end_matches("_dir")))
+  };
+
+  let areas_dir = resolve_dir("areas_dir", &config_file.areas_dir);
+  let assets_dir = resolve_dir("assets_dir", &config_file.assets_dir);
+  let scripts_dir = resolve_dir("scripts_dir", &config_file.scripts_dir);
+
+  let ban_list_path = matches
+    .value_of("ban_list")
+    .map(PathBuf::from)
+    .or_else(|| config_file.ban_list.clone())
+    .unwrap_or_else(|| root_dir.join("bans.toml"));
+
+  let mut incoming_conditions = resolve_network_conditions(
+    &matches,
+    "incoming_conditions",
+    config_file.incoming_conditions,
+  );
+
+  if let Some(drop_rate) = matches
+    .value_of("receiving_drop_rate")
+    .map(|value| value.parse().unwrap())
+    .or(config_file.receiving_drop_rate)
+  {
+    incoming_conditions.drop_rate = drop_rate;
+  }
+
+  let default_config = ServerConfig::default();
+
+  let config = ServerConfig {
+    public_ip,
+    bind_ip,
+    dual_stack,
+    port: resolve_arg(&matches, "port", bind_port.or(config_file.port)),
+    areas_dir,
+    assets_dir,
+    ban_list_path,
+    allow_list_only: resolve_flag(
+      &matches,
+      "allow_list_only",
+      config_file.allow_list_only,
+      false,
+    ),
+    maintenance: resolve_flag(&matches, "maintenance", config_file.maintenance, false),
+    maintenance_message: resolve_arg(
+      &matches,
+      "maintenance_message",
+      config_file.maintenance_message,
+    ),
+    maintenance_exemptions: config_file.maintenance_exemptions.unwrap_or_default(),
+    admin_console: resolve_flag(&matches, "admin_console", config_file.admin_console, true),
+    handle_signals: true,
+    compress_assets: resolve_flag(
+      &matches,
+      "asset_compression",
+      config_file.compress_assets,
+      true,
+    ),
+    log_connections: resolve_flag(
+      &matches,
+      "log_connections",
+      config_file.log_connections,
+      false,
+    ),
+    log_packets: resolve_flag(&matches, "log_packets", config_file.log_packets, false),
+    max_payload_size: resolve_arg::<u16>(
+      &matches,
+      "max_payload_size",
+      config_file.max_payload_size,
+    )
+    .into(),
+    resend_budget: resolve_arg::<isize>(&matches, "resend_budget", config_file.resend_budget)
+      as usize,
+    outgoing_rate_limit: resolve_arg::<usize>(
+      &matches,
+      "outgoing_rate_limit",
+      config_file.outgoing_rate_limit,
+    ) * 1024,
+    packet_rate_limit: resolve_arg(&matches, "packet_rate_limit", config_file.packet_rate_limit),
+    flood_block_duration: resolve_arg(
+      &matches,
+      "flood_block_duration",
+      config_file.flood_block_duration,
+    ),
+    max_connections: resolve_arg(&matches, "max_connections", config_file.max_connections),
+    encryption: resolve_flag(&matches, "encryption", config_file.encryption, true),
+    require_encryption: resolve_flag(
+      &matches,
+      "require_encryption",
+      config_file.require_encryption,
+      false,
+    ),
+    incoming_conditions,
+    outgoing_conditions: resolve_network_conditions(
+      &matches,
+      "outgoing_conditions",
+      config_file.outgoing_conditions,
+    ),
+    player_asset_limit: resolve_arg::<usize>(
+      &matches,
+      "player_asset_limit",
+      config_file.player_asset_limit,
     ) * 1024,
-    avatar_dimensions_limit: unwrap_and_parse_or_default(
-      matches.value_of("avatar_dimensions_limit"),
+    avatar_dimensions_limit: resolve_arg(
+      &matches,
+      "avatar_dimensions_limit",
+      config_file.avatar_dimensions_limit,
     ),
     custom_emotes_path: matches
       .value_of("custom_emotes_path")
-      .map(|path| path.to_string()),
-    max_idle_packet_duration: 1.0,
-    max_silence_duration: 5.0,
-    heartbeat_rate: 0.5,
+      .map(|path| path.to_string())
+      .or(config_file.custom_emotes_path),
+    max_idle_packet_duration: config_file
+      .max_idle_packet_duration
+      .unwrap_or(default_config.max_idle_packet_duration),
+    max_silence_duration: config_file
+      .max_silence_duration
+      .unwrap_or(default_config.max_silence_duration),
+    resume_grace_period: config_file
+      .resume_grace_period
+      .unwrap_or(default_config.resume_grace_period),
+    heartbeat_rate: config_file
+      .heartbeat_rate
+      .unwrap_or(default_config.heartbeat_rate),
+    tick_rate: resolve_arg(&matches, "tick_rate", config_file.tick_rate),
+    // validator makes this safe to unwrap
+    max_players: matches
+      .value_of("max_players")
+      .map(|value| value.parse().unwrap())
+      .or(config_file.max_players),
+    login_queue_size: resolve_arg(&matches, "login_queue_size", config_file.login_queue_size),
+    login_queue_update_rate: config_file
+      .login_queue_update_rate
+      .unwrap_or(default_config.login_queue_update_rate),
+    shutdown_kick_reason: resolve_arg(
+      &matches,
+      "shutdown_kick_reason",
+      config_file.shutdown_kick_reason,
+    ),
+    shutdown_timeout: resolve_arg(&matches, "shutdown_timeout", config_file.shutdown_timeout),
+    record_packets: matches
+      .value_of("record_packets")
+      .map(PathBuf::from)
+      .or(config_file.record_packets),
   };
 
-  let mut server = net::Server::new(config);
-
-  server.add_plugin_interface(Box::new(LuaPluginInterface::new()));
+  let mut server = ServerBuilder::new(config)
+    .plugin_interface(Box::new(LuaPluginInterface::new(scripts_dir)))
+    .build();
 
   if let Err(err) = server.start() {
     panic!("{}", err);
   }
 }
 
-fn get_public_ip() -> Result<IpAddr, Box<dyn std::error::Error>> {
+/// Prefers values passed on the command line, then the config file, then the command line default
+fn resolve_arg<A>(matches: &clap::ArgMatches, name: &str, config_file_value: Option<A>) -> A
+where
+  A: Default + std::str::FromStr,
+{
+  if matches.occurrences_of(name) == 0 {
+    if let Some(value) = config_file_value {
+      return value;
+    }
+  }
+
+  matches
+    .value_of(name)
+    .unwrap_or_default()
+    .parse()
+    .unwrap_or_default()
+}
+
+/// Flags have a `no_` counterpart for overriding the config file in either direction, the last one passed wins
+fn resolve_flag(
+  matches: &clap::ArgMatches,
+  name: &str,
+  config_file_value: Option<bool>,
+  default: bool,
+) -> bool {
+  if matches.is_present(name) {
+    true
+  } else if matches.is_present(format!("no_{}", name)) {
+    false
+  } else {
+    config_file_value.unwrap_or(default)
+  }
+}
+
+fn resolve_network_conditions(
+  matches: &clap::ArgMatches,
+  name: &str,
+  config_file_value: Option<NetworkConditions>,
+) -> NetworkConditions {
+  matches
+    .value_of(name)
+    // validator makes this safe to unwrap
+    .map(|value| config::parse_network_conditions(value).unwrap())
+    .or(config_file_value)
+    .unwrap_or_default()
+}
+
+fn resolve_public_ip(matches: &clap::ArgMatches, config_file: &ConfigFile) -> IpAddr {
+  if let Some(value) = matches.value_of("public_ip") {
+    // validator makes this safe to unwrap
+    let public_ip = value.parse().unwrap();
+    info!("Using public ip {} (set by --public-ip)", public_ip);
+    return public_ip;
+  }
+
+  if let Some(public_ip) = config_file.public_ip {
+    info!("Using public ip {} (set by config file)", public_ip);
+    return public_ip;
+  }
+
+  let resolver_url: String = resolve_arg(
+    matches,
+    "public_ip_resolver",
+    config_file.public_ip_resolver.clone(),
+  );
+  let timeout: f32 = resolve_arg(matches, "public_ip_timeout", config_file.public_ip_timeout);
+
+  match get_public_ip(&resolver_url, std::time::Duration::from_secs_f32(timeout)) {
+    Ok(public_ip) => {
+      info!(
+        "Using public ip {} (resolved by {})",
+        public_ip, resolver_url
+      );
+      public_ip
+    }
+    Err(err) => {
+      // default to localhost
+      let public_ip = IpAddr::from([127, 0, 0, 1]);
+
+      warn!(
+        "Failed to resolve public ip with {}: {}, using {} (set --public-ip to share a reachable address)",
+        resolver_url, err, public_ip
+      );
+
+      public_ip
+    }
+  }
+}
+
+fn get_public_ip(
+  resolver_url: &str,
+  timeout: std::time::Duration,
+) -> Result<IpAddr, Box<dyn std::error::Error>> {
   use isahc::config::{Configurable, RedirectPolicy};
   use isahc::Request;
   use std::io::Read;
   use std::str::FromStr;
 
-  let request = Request::get("http://checkip.amazonaws.com")
+  let request = Request::get(resolver_url)
     .redirect_policy(RedirectPolicy::Follow)
+    .timeout(timeout)
     .body(())?;
 
   let mut response = isahc::send(request)?;
   let mut response_text = String::new();
   response.body_mut().read_to_string(&mut response_text)?;
 
-  let ip_string = response_text.replace("\n", "");
+  if !response.status().is_success() {
+    return Err(format!("received status {}", response.status()).into());
+  }
+
+  let ip_string = response_text.trim();
 
-  Ok(IpAddr::from_str(&ip_string)?)
+  Ok(IpAddr::from_str(ip_string)?)
 }
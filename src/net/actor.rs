@@ -24,7 +24,15 @@ pub struct Actor {
 }
 
 impl Actor {
-  pub fn create_spawn_packet(&self, x: f32, y: f32, z: f32, warp_in: bool) -> ServerPacket {
+  /// The handle identifies the actor in ActorMoveBatch, 0 for movement sent with ActorMove
+  pub fn create_spawn_packet(
+    &self,
+    handle: u16,
+    x: f32,
+    y: f32,
+    z: f32,
+    warp_in: bool,
+  ) -> ServerPacket {
     ServerPacket::ActorConnected {
       ticket: &self.id,
       name: &self.name,
@@ -41,11 +49,12 @@ impl Actor {
       rotation: self.rotation,
       minimap_color: self.minimap_color,
       animation: self.current_animation.as_deref(),
+      handle,
     }
   }
 
   /// helper function that updates last_movement_time and current_animation if anything has changed
-  pub fn set_position(&mut self, x: f32, y: f32, z: f32) {
+  pub fn set_position(&mut self, x: f32, y: f32, z: f32, time: Instant) {
     #[allow(clippy::float_cmp)]
     let position_changed = self.x != x || self.y != y || self.z != z;
 
@@ -57,16 +66,16 @@ impl Actor {
     self.y = y;
     self.z = z;
     self.current_animation = None;
-    self.last_movement_time = Instant::now();
+    self.last_movement_time = time;
   }
 
   /// helper function that updates last_movement_time if anything has changed
-  pub fn set_direction(&mut self, direction: Direction) {
+  pub fn set_direction(&mut self, direction: Direction, time: Instant) {
     if self.direction == direction {
       return;
     }
 
     self.direction = direction;
-    self.last_movement_time = Instant::now();
+    self.last_movement_time = time;
   }
 }
@@ -0,0 +1,45 @@
+use std::collections::{HashMap, HashSet};
+
+/// Short numeric ids for actors, sent in place of actor ids in ActorMoveBatch.
+/// Handles are assigned in a cycle to avoid reusing a recently released handle, 0 is never assigned
+#[derive(Default)]
+pub(super) struct ActorHandles {
+  handles: HashMap<String, u16>,
+  used: HashSet<u16>,
+  next: u16,
+}
+
+impl ActorHandles {
+  /// Returns 0 if every handle is in use
+  pub fn assign(&mut self, actor_id: &str) -> u16 {
+    if let Some(handle) = self.handles.get(actor_id) {
+      return *handle;
+    }
+
+    for _ in 0..u16::MAX {
+      self.next = self.next.wrapping_add(1).max(1);
+
+      if self.used.insert(self.next) {
+        self.handles.insert(actor_id.to_string(), self.next);
+        return self.next;
+      }
+    }
+
+    0
+  }
+
+  /// Returns 0 for actors without a handle
+  pub fn get(&self, actor_id: &str) -> u16 {
+    self.handles.get(actor_id).copied().unwrap_or_default()
+  }
+
+  pub fn release(&mut self, actor_id: &str) -> u16 {
+    match self.handles.remove(actor_id) {
+      Some(handle) => {
+        self.used.remove(&handle);
+        handle
+      }
+      None => 0,
+    }
+  }
+}
@@ -0,0 +1,129 @@
+use super::{Ban, BanTarget, Net};
+use log::*;
+
+const HELP: &str = "Commands:
+  maintenance on [message]
+  maintenance off
+  maintenance exempt <identity>
+  maintenance unexempt <identity>
+  ban identity <identity> [reason]
+  ban ip <ip or cidr range> [reason]
+  unban identity <identity>
+  unban ip <ip or cidr range>
+  bans";
+
+pub(super) fn handle_console_command(net: &mut Net, command: &str) {
+  let (name, args) = split_word(command);
+
+  let result = match name {
+    "help" => {
+      info!("{}", HELP);
+      Ok(())
+    }
+    "maintenance" => handle_maintenance_command(net, args),
+    "ban" => handle_ban_command(net, args),
+    "unban" => handle_unban_command(net, args),
+    "bans" => {
+      for ban in net.get_ban_list().bans() {
+        let target = match &ban.target {
+          BanTarget::Identity(identity) => format!("identity {}", identity),
+          BanTarget::Ip(ip_range) => format!("ip {}", ip_range),
+        };
+
+        info!(
+          "{} \"{}\" expires at: {:?}",
+          target, ban.reason, ban.expires_at
+        );
+      }
+
+      Ok(())
+    }
+    _ => Err(format!("Unknown command \"{}\", try \"help\"", name)),
+  };
+
+  if let Err(message) = result {
+    warn!("{}", message);
+  }
+}
+
+fn handle_maintenance_command(net: &mut Net, args: &str) -> Result<(), String> {
+  let (action, args) = split_word(args);
+
+  match action {
+    "on" => {
+      let message = (!args.is_empty()).then(|| args.to_string());
+      net.set_maintenance(true, message);
+      info!("Maintenance enabled");
+    }
+    "off" => {
+      net.set_maintenance(false, None);
+      info!("Maintenance disabled");
+    }
+    "exempt" if !args.is_empty() => {
+      net.add_maintenance_exemption(args.to_string());
+      info!("Exempted {} from maintenance", args);
+    }
+    "unexempt" if !args.is_empty() => {
+      if !net.remove_maintenance_exemption(args) {
+        return Err(format!("{} was not exempt", args));
+      }
+
+      info!("Removed maintenance exemption for {}", args);
+    }
+    _ => {
+      return Err(String::from(
+        "Usage: maintenance on [message] | off | exempt <identity> | unexempt <identity>",
+      ))
+    }
+  }
+
+  Ok(())
+}
+
+fn handle_ban_command(net: &mut Net, args: &str) -> Result<(), String> {
+  let (kind, args) = split_word(args);
+  let (target, reason) = split_word(args);
+  let target = parse_target(kind, target)?;
+
+  net.ban(Ban {
+    target,
+    reason: reason.to_string(),
+    expires_at: None,
+  });
+
+  info!("Banned {}", args);
+
+  Ok(())
+}
+
+fn handle_unban_command(net: &mut Net, args: &str) -> Result<(), String> {
+  let (kind, target) = split_word(args);
+  let target = parse_target(kind, target)?;
+
+  if !net.get_ban_list_mut().unban(&target) {
+    return Err(format!("{} was not banned", args));
+  }
+
+  info!("Unbanned {}", args);
+
+  Ok(())
+}
+
+fn parse_target(kind: &str, target: &str) -> Result<BanTarget, String> {
+  match kind {
+    "identity" if !target.is_empty() => Ok(BanTarget::Identity(target.to_string())),
+    "ip" => Ok(BanTarget::Ip(target.parse()?)),
+    _ => Err(String::from(
+      "Expected \"identity <identity>\" or \"ip <ip>\"",
+    )),
+  }
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+  let text = text.trim();
+
+  match text.split_once(char::is_whitespace) {
+    Some((word, rest)) => (word, rest.trim_start()),
+    None => (text, ""),
+  }
+}
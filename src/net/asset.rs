@@ -18,6 +18,22 @@ pub enum AssetData {
   Data(Vec<u8>),
 }
 
+impl AssetData {
+  pub fn as_bytes(&self) -> &[u8] {
+    match self {
+      AssetData::Text(data) => data.as_bytes(),
+      AssetData::Texture(data) => data,
+      AssetData::Audio(data) => data,
+      AssetData::Data(data) => data,
+    }
+  }
+
+  /// Textures and audio are already compressed by their file formats
+  pub fn is_compressible(&self) -> bool {
+    matches!(self, AssetData::Text(_) | AssetData::Data(_))
+  }
+}
+
 #[derive(Clone, Copy, Debug)]
 #[repr(u8)]
 pub enum PackageCategory {
@@ -65,15 +81,16 @@ impl Asset {
     asset
   }
 
-  pub fn load_from_file(path: &std::path::Path) -> Asset {
+  /// `server_path` is the /server/ path the asset will be stored as, used to resolve relative paths in the file
+  pub fn load_from_file(file_path: &std::path::Path, server_path: &std::path::Path) -> Asset {
     use std::fs::{metadata, read};
 
-    let data = read(&path).unwrap_or_default();
-    let asset_data = resolve_asset_data(path, &data);
+    let data = read(file_path).unwrap_or_default();
+    let asset_data = resolve_asset_data(server_path, &data);
 
     let mut last_modified = 0;
 
-    if let Ok(file_meta) = metadata(&path) {
+    if let Ok(file_meta) = metadata(file_path) {
       if let Ok(time) = file_meta.modified() {
         last_modified = time
           .duration_since(std::time::UNIX_EPOCH)
@@ -91,7 +108,7 @@ impl Asset {
       cache_to_disk: true,
     };
 
-    asset.resolve_dependencies(path);
+    asset.resolve_dependencies(server_path);
 
     asset
   }
@@ -105,6 +122,10 @@ impl Asset {
     }
   }
 
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
   /// Resolves dependencies and alternate name. `load_from_*` functions automatically call this
   pub fn resolve_dependencies(&mut self, path: &std::path::Path) {
     let extension = path
@@ -400,11 +421,11 @@ impl Asset {
         if let Ok(requires_scripts_func) =
           globals.get::<&str, mlua::Function>("package_requires_scripts")
         {
-          requires_scripts_func.call(())?;
+          requires_scripts_func.call::<_, ()>(())?;
         }
 
         let init_func: mlua::Function = globals.get("package_init")?;
-        init_func.call(package_table)?;
+        init_func.call::<_, ()>(package_table)?;
 
         // encounter detection
         let package_build_func: mlua::Value = globals.get("package_build")?;
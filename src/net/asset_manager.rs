@@ -1,9 +1,17 @@
 use super::{Asset, AssetID, PackageInfo};
+use crate::packets::Compression;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
+
+// compressed data for each compression scheme, None if compression doesn't shrink the asset
+type CompressedData = HashMap<Compression, Option<Rc<Vec<u8>>>>;
 
 pub struct AssetManager {
   assets: HashMap<String, Asset>,
   package_paths: HashMap<String, String>,
+  /// keyed by path, cleared when the asset is replaced or removed
+  compressed_data: RefCell<HashMap<String, CompressedData>>,
 }
 
 impl AssetManager {
@@ -11,10 +19,20 @@ impl AssetManager {
     AssetManager {
       assets: HashMap::new(),
       package_paths: HashMap::new(),
+      compressed_data: RefCell::new(HashMap::new()),
     }
   }
 
+  /// Files are stored as /server/assets/ + the path relative to `dir`, no matter where `dir` is
   pub fn load_assets_from_dir(&mut self, dir: &std::path::Path) {
+    self.load_assets_from_dir_with_recursion(dir, dir);
+  }
+
+  fn load_assets_from_dir_with_recursion(
+    &mut self,
+    root_dir: &std::path::Path,
+    dir: &std::path::Path,
+  ) {
     use std::fs::read_dir;
 
     if let Ok(entries) = read_dir(dir) {
@@ -22,14 +40,18 @@ impl AssetManager {
         let path = entry.path();
 
         if path.is_dir() {
-          self.load_assets_from_dir(&path);
+          self.load_assets_from_dir_with_recursion(root_dir, &path);
         } else {
-          let mut path_string = String::from("/server/") + path.to_str().unwrap_or_default();
+          let relative_path = path.strip_prefix(root_dir).unwrap_or(&path);
+          let mut path_string =
+            String::from("/server/assets/") + relative_path.to_str().unwrap_or_default();
 
           // adjust windows paths
           path_string = path_string.replace('\\', "/");
 
-          self.set_asset(path_string, Asset::load_from_file(&path));
+          let server_path = std::path::PathBuf::from(&path_string);
+
+          self.set_asset(path_string, Asset::load_from_file(&path, &server_path));
         }
       }
     }
@@ -39,7 +61,37 @@ impl AssetManager {
     self.assets.get(path)
   }
 
+  /// Compresses each version of an asset once per compression scheme,
+  /// returns None if the asset is missing, can't be compressed, or doesn't shrink
+  pub fn get_compressed_data(&self, path: &str, compression: Compression) -> Option<Rc<Vec<u8>>> {
+    let asset = self.assets.get(path)?;
+
+    if !asset.data.is_compressible() {
+      return None;
+    }
+
+    let mut compressed_data = self.compressed_data.borrow_mut();
+
+    if let Some(data) = compressed_data
+      .get(path)
+      .and_then(|map| map.get(&compression))
+    {
+      return data.clone();
+    }
+
+    let data = compression.compress(asset.data.as_bytes()).map(Rc::new);
+
+    compressed_data
+      .entry(path.to_string())
+      .or_default()
+      .insert(compression, data.clone());
+
+    data
+  }
+
   pub fn set_asset(&mut self, path: String, asset: Asset) {
+    self.compressed_data.get_mut().remove(&path);
+
     for alternate_name in &asset.alternate_names {
       #[allow(clippy::single_match)]
       match alternate_name {
@@ -58,6 +110,8 @@ impl AssetManager {
   }
 
   pub fn remove_asset(&mut self, path: &str) {
+    self.compressed_data.get_mut().remove(path);
+
     let asset = if let Some(asset) = self.assets.remove(path) {
       asset
     } else {
@@ -131,3 +185,50 @@ impl AssetManager {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn compressed_data() {
+    let mut asset_manager = AssetManager::new();
+    let path = String::from("/server/assets/map.tmx");
+    let text = "<map>".repeat(100);
+
+    asset_manager.set_asset(
+      path.clone(),
+      Asset::load_from_memory(std::path::Path::new(&path), text.as_bytes()),
+    );
+
+    let data = asset_manager
+      .get_compressed_data(&path, Compression::Zlib)
+      .unwrap();
+
+    assert!(data.len() < text.len());
+    assert!(
+      Rc::ptr_eq(
+        &data,
+        &asset_manager
+          .get_compressed_data(&path, Compression::Zlib)
+          .unwrap()
+      ),
+      "assets should only be compressed once"
+    );
+    assert!(asset_manager
+      .get_compressed_data(&path, Compression::None)
+      .is_none());
+
+    asset_manager.set_asset(
+      path.clone(),
+      Asset::load_from_memory(std::path::Path::new(&path), b"<map/>"),
+    );
+
+    assert!(
+      asset_manager
+        .get_compressed_data(&path, Compression::Zlib)
+        .is_none(),
+      "replacing an asset should clear its compressed data"
+    );
+  }
+}
@@ -0,0 +1,498 @@
+use crate::helpers::canonical_ip;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+
+/// An IP address or a CIDR range such as `10.0.0.0/8`, host bits are cleared so equivalent ranges compare equal
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct IpRange {
+  ip: IpAddr,
+  prefix_len: u8,
+}
+
+impl IpRange {
+  pub fn new(ip: IpAddr, prefix_len: u8) -> Option<IpRange> {
+    let ip = canonical_ip(ip);
+
+    let max_prefix_len = match ip {
+      IpAddr::V4(_) => 32,
+      IpAddr::V6(_) => 128,
+    };
+
+    if prefix_len > max_prefix_len {
+      return None;
+    }
+
+    Some(IpRange {
+      ip: mask_ip(ip, prefix_len),
+      prefix_len,
+    })
+  }
+
+  pub fn contains(&self, ip: IpAddr) -> bool {
+    let ip = canonical_ip(ip);
+
+    self.ip.is_ipv4() == ip.is_ipv4() && mask_ip(ip, self.prefix_len) == self.ip
+  }
+}
+
+// keeps the first prefix_len bits
+fn mask_ip(ip: IpAddr, prefix_len: u8) -> IpAddr {
+  match ip {
+    IpAddr::V4(ip) => {
+      let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+      IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask))
+    }
+    IpAddr::V6(ip) => {
+      let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+      IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+    }
+  }
+}
+
+impl From<IpAddr> for IpRange {
+  fn from(ip: IpAddr) -> IpRange {
+    let prefix_len = if canonical_ip(ip).is_ipv4() { 32 } else { 128 };
+
+    IpRange::new(ip, prefix_len).unwrap()
+  }
+}
+
+impl FromStr for IpRange {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<IpRange, String> {
+    let invalid = || format!("\"{}\" is not an IP address or CIDR range", value);
+
+    match value.split_once('/') {
+      Some((ip_str, prefix_len_str)) => {
+        let ip = ip_str.parse().map_err(|_| invalid())?;
+        let prefix_len = prefix_len_str.parse().map_err(|_| invalid())?;
+
+        IpRange::new(ip, prefix_len).ok_or_else(invalid)
+      }
+      None => value
+        .parse::<IpAddr>()
+        .map(IpRange::from)
+        .map_err(|_| invalid()),
+    }
+  }
+}
+
+impl std::fmt::Display for IpRange {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if *self == IpRange::from(self.ip) {
+      write!(f, "{}", self.ip)
+    } else {
+      write!(f, "{}/{}", self.ip, self.prefix_len)
+    }
+  }
+}
+
+impl Serialize for IpRange {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&self.to_string())
+  }
+}
+
+impl<'de> Deserialize<'de> for IpRange {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<IpRange, D::Error> {
+    let value = String::deserialize(deserializer)?;
+
+    value.parse().map_err(serde::de::Error::custom)
+  }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BanTarget {
+  Identity(String),
+  Ip(IpRange),
+}
+
+impl BanTarget {
+  pub fn matches(&self, identity: &str, ip: IpAddr) -> bool {
+    match self {
+      BanTarget::Identity(target_identity) => target_identity == identity,
+      BanTarget::Ip(ip_range) => ip_range.contains(ip),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Ban {
+  pub target: BanTarget,
+  pub reason: String,
+  /// unix time in seconds, permanent when unset
+  pub expires_at: Option<u64>,
+}
+
+impl Ban {
+  pub fn is_expired(&self, now: u64) -> bool {
+    matches!(self.expires_at, Some(expires_at) if expires_at <= now)
+  }
+}
+
+/// Bans and allowed identities or addresses, saved to a TOML file after every change
+pub struct BanList {
+  path: PathBuf,
+  bans: Vec<Ban>,
+  allowed: Vec<BanTarget>,
+  allow_list_only: bool,
+  /// started by the first change
+  writer: Option<BanListWriter>,
+}
+
+impl BanList {
+  /// Starts with an empty list if the file doesn't exist yet
+  pub fn load(path: &Path, allow_list_only: bool) -> Result<BanList, String> {
+    let mut ban_list = BanList {
+      path: path.to_path_buf(),
+      bans: Vec::new(),
+      allowed: Vec::new(),
+      allow_list_only,
+      writer: None,
+    };
+
+    let text = match std::fs::read_to_string(path) {
+      Ok(text) => text,
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(ban_list),
+      Err(error) => return Err(error.to_string()),
+    };
+
+    let file: BanListFile = toml::from_str(&text).map_err(|error| error.to_string())?;
+
+    ban_list.bans = file
+      .bans
+      .into_iter()
+      .map(|record| {
+        Ok(Ban {
+          target: record.target()?,
+          reason: record.reason,
+          expires_at: record.expires_at,
+        })
+      })
+      .collect::<Result<_, String>>()?;
+
+    ban_list.allowed = file
+      .allowed
+      .into_iter()
+      .map(|record| record.target())
+      .collect::<Result<_, String>>()?;
+
+    Ok(ban_list)
+  }
+
+  pub fn is_allow_list_only(&self) -> bool {
+    self.allow_list_only
+  }
+
+  pub fn set_allow_list_only(&mut self, allow_list_only: bool) {
+    self.allow_list_only = allow_list_only;
+  }
+
+  /// Returns the reason a login should be rejected, if any
+  pub fn check_login(&self, identity: &str, ip: IpAddr) -> Option<String> {
+    if self.is_allowed(identity, ip) {
+      return None;
+    }
+
+    if let Some(ban) = self.find_ban(identity, ip) {
+      return if ban.reason.is_empty() {
+        Some(String::from("Banned"))
+      } else {
+        Some(format!("Banned: {}", ban.reason))
+      };
+    }
+
+    if self.allow_list_only {
+      return Some(String::from("Not on the allow list"));
+    }
+
+    None
+  }
+
+  pub fn find_ban(&self, identity: &str, ip: IpAddr) -> Option<&Ban> {
+    let now = unix_time();
+
+    self
+      .bans
+      .iter()
+      .find(|ban| !ban.is_expired(now) && ban.target.matches(identity, ip))
+  }
+
+  pub fn bans(&self) -> impl Iterator<Item = &Ban> {
+    let now = unix_time();
+
+    self.bans.iter().filter(move |ban| !ban.is_expired(now))
+  }
+
+  /// Replaces any existing ban for the same target
+  pub fn ban(&mut self, ban: Ban) {
+    self.bans.retain(|existing| existing.target != ban.target);
+    self.bans.push(ban);
+    self.save();
+  }
+
+  /// Returns false if the target wasn't banned
+  pub fn unban(&mut self, target: &BanTarget) -> bool {
+    let len = self.bans.len();
+    self.bans.retain(|ban| ban.target != *target);
+
+    let removed = self.bans.len() != len;
+
+    if removed {
+      self.save();
+    }
+
+    removed
+  }
+
+  pub fn is_allowed(&self, identity: &str, ip: IpAddr) -> bool {
+    self
+      .allowed
+      .iter()
+      .any(|target| target.matches(identity, ip))
+  }
+
+  pub fn allowed(&self) -> impl Iterator<Item = &BanTarget> {
+    self.allowed.iter()
+  }
+
+  /// Allowed targets skip bans, and are the only targets accepted in allow list only mode
+  pub fn allow(&mut self, target: BanTarget) {
+    if !self.allowed.contains(&target) {
+      self.allowed.push(target);
+      self.save();
+    }
+  }
+
+  /// Returns false if the target wasn't allowed
+  pub fn disallow(&mut self, target: &BanTarget) -> bool {
+    let len = self.allowed.len();
+    self.allowed.retain(|allowed| allowed != target);
+
+    let removed = self.allowed.len() != len;
+
+    if removed {
+      self.save();
+    }
+
+    removed
+  }
+
+  fn save(&mut self) {
+    let now = unix_time();
+    self.bans.retain(|ban| !ban.is_expired(now));
+
+    let file = BanListFile {
+      bans: self
+        .bans
+        .iter()
+        .map(|ban| BanRecord {
+          reason: ban.reason.clone(),
+          expires_at: ban.expires_at,
+          ..BanRecord::from(&ban.target)
+        })
+        .collect(),
+      allowed: self.allowed.iter().map(BanRecord::from).collect(),
+    };
+
+    match toml::to_string(&file) {
+      Ok(text) => self
+        .writer
+        .get_or_insert_with(|| BanListWriter::new(self.path.clone()))
+        .write(text),
+      Err(error) => warn!("Failed to save ban list {}: {}", self.path.display(), error),
+    }
+  }
+
+  /// True until every change has been written to the file
+  pub fn is_saving(&self) -> bool {
+    self
+      .writer
+      .as_ref()
+      .map(BanListWriter::is_writing)
+      .unwrap_or_default()
+  }
+}
+
+/// Writes the list on its own thread to keep file io off the game loop, only the newest pending list is written
+struct BanListWriter {
+  sender: mpsc::Sender<String>,
+  pending_writes: Arc<AtomicUsize>,
+}
+
+impl BanListWriter {
+  fn new(path: PathBuf) -> BanListWriter {
+    let (sender, receiver) = mpsc::channel::<String>();
+    let pending_writes = Arc::new(AtomicUsize::new(0));
+    let thread_pending_writes = pending_writes.clone();
+
+    std::thread::spawn(move || {
+      while let Ok(mut text) = receiver.recv() {
+        let mut write_count = 1;
+
+        while let Ok(newer_text) = receiver.try_recv() {
+          text = newer_text;
+          write_count += 1;
+        }
+
+        // write to a temporary file first to avoid losing the list if the server stops mid write
+        let temp_path = path.with_extension("toml.tmp");
+        let result =
+          std::fs::write(&temp_path, text).and_then(|_| std::fs::rename(&temp_path, &path));
+
+        if let Err(error) = result {
+          warn!("Failed to save ban list {}: {}", path.display(), error);
+        }
+
+        thread_pending_writes.fetch_sub(write_count, Ordering::SeqCst);
+      }
+    });
+
+    BanListWriter {
+      sender,
+      pending_writes,
+    }
+  }
+
+  fn write(&self, text: String) {
+    self.pending_writes.fetch_add(1, Ordering::SeqCst);
+    let _ = self.sender.send(text);
+  }
+
+  fn is_writing(&self) -> bool {
+    self.pending_writes.load(Ordering::SeqCst) > 0
+  }
+}
+
+pub fn unix_time() -> u64 {
+  std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or_default()
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct BanListFile {
+  #[serde(default)]
+  bans: Vec<BanRecord>,
+  #[serde(default)]
+  allowed: Vec<BanRecord>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct BanRecord {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  identity: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  ip: Option<IpRange>,
+  #[serde(default, skip_serializing_if = "String::is_empty")]
+  reason: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  expires_at: Option<u64>,
+}
+
+impl BanRecord {
+  fn target(&self) -> Result<BanTarget, String> {
+    match (&self.identity, self.ip) {
+      (Some(identity), None) => Ok(BanTarget::Identity(identity.clone())),
+      (None, Some(ip_range)) => Ok(BanTarget::Ip(ip_range)),
+      _ => Err(String::from(
+        "each entry requires either an identity or an ip",
+      )),
+    }
+  }
+}
+
+impl From<&BanTarget> for BanRecord {
+  fn from(target: &BanTarget) -> BanRecord {
+    match target {
+      BanTarget::Identity(identity) => BanRecord {
+        identity: Some(identity.clone()),
+        ..Default::default()
+      },
+      BanTarget::Ip(ip_range) => BanRecord {
+        ip: Some(*ip_range),
+        ..Default::default()
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ip_ranges() {
+    let range: IpRange = "10.1.0.0/16".parse().unwrap();
+    assert!(range.contains("10.1.200.3".parse().unwrap()));
+    assert!(range.contains("::ffff:10.1.0.1".parse().unwrap()));
+    assert!(!range.contains("10.2.0.1".parse().unwrap()));
+    assert_eq!(range.to_string(), "10.1.0.0/16");
+
+    let unmasked_range: IpRange = "10.1.2.3/16".parse().unwrap();
+    assert_eq!(unmasked_range, range, "host bits should be ignored");
+    assert_eq!(unmasked_range.to_string(), "10.1.0.0/16");
+    assert_eq!(
+      "2001:db8:ffff::1/32".parse::<IpRange>().unwrap(),
+      "2001:db8::/32".parse().unwrap()
+    );
+
+    let range: IpRange = "2001:db8::/32".parse().unwrap();
+    assert!(range.contains("2001:db8:1::1".parse().unwrap()));
+    assert!(!range.contains("10.1.0.1".parse().unwrap()));
+
+    let range: IpRange = "0.0.0.0/0".parse().unwrap();
+    assert!(range.contains("192.168.0.1".parse().unwrap()));
+
+    assert_eq!("::1".parse::<IpRange>().unwrap().to_string(), "::1");
+    assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+    assert!("example.com".parse::<IpRange>().is_err());
+  }
+
+  #[test]
+  fn persistence() {
+    let path = std::env::temp_dir().join(format!("bans-{}.toml", uuid::Uuid::new_v4()));
+    let ip: IpAddr = "192.168.0.10".parse().unwrap();
+
+    let mut ban_list = BanList::load(&path, false).unwrap();
+
+    ban_list.ban(Ban {
+      target: BanTarget::Ip("192.168.0.0/24".parse().unwrap()),
+      reason: String::from("spam"),
+      expires_at: None,
+    });
+    ban_list.ban(Ban {
+      target: BanTarget::Identity(String::from("expired")),
+      reason: String::new(),
+      expires_at: Some(1),
+    });
+    ban_list.allow(BanTarget::Identity(String::from("friend")));
+
+    while ban_list.is_saving() {
+      std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    let ban_list = BanList::load(&path, false).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(ban_list.bans().count(), 1);
+    assert_eq!(
+      ban_list.check_login("stranger", ip),
+      Some(String::from("Banned: spam"))
+    );
+    assert_eq!(ban_list.check_login("friend", ip), None);
+    assert_eq!(
+      ban_list.check_login("expired", "127.0.0.1".parse().unwrap()),
+      None
+    );
+  }
+}
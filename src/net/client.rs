@@ -1,7 +1,11 @@
+use super::movement_batcher::MovementBatcher;
 use super::{Actor, Direction, PlayerData, WidgetTracker};
+use crate::packets::{Compression, NetworkStats};
+use std::cell::RefCell;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::net::SocketAddr;
+use std::rc::Rc;
 
 pub(super) struct Client {
   pub socket_address: SocketAddr,
@@ -14,6 +18,10 @@ pub(super) struct Client {
   pub warp_direction: Direction,
   pub ready: bool,
   pub transferring: bool,
+  /// reconnected with the resume token, player_resume fires in place of player_join
+  pub resuming: bool,
+  /// secret for taking back the player after losing connection, replaced on every resume
+  pub resume_token: String,
   pub area_join_time: u64,
   pub cached_assets: HashSet<String>,
   pub texture_buffer: Vec<u8>,
@@ -24,6 +32,13 @@ pub(super) struct Client {
   pub battle_tracker: VecDeque<usize>,
   pub player_data: PlayerData,
   pub is_input_locked: bool,
+  /// actors excluded by scripts
+  pub excluded_actors: HashSet<String>,
+  /// actors excluded for being outside of the area's interest radius
+  pub culled_actors: HashSet<String>,
+  pub movement_batcher: MovementBatcher,
+  pub compression: Compression,
+  pub network_stats: Rc<RefCell<NetworkStats>>,
 }
 
 impl Client {
@@ -37,9 +52,11 @@ impl Client {
     spawn_y: f32,
     spawn_z: f32,
     spawn_direction: Direction,
+    time: std::time::Instant,
+    compression: Compression,
+    network_stats: Rc<RefCell<NetworkStats>>,
   ) -> Client {
     use super::asset;
-    use std::time::Instant;
     use uuid::Uuid;
 
     let id = Uuid::new_v4().to_string();
@@ -58,7 +75,7 @@ impl Client {
         x: spawn_x,
         y: spawn_y,
         z: spawn_z,
-        last_movement_time: Instant::now(),
+        last_movement_time: time,
         scale_x: 1.0,
         scale_y: 1.0,
         rotation: 0.0,
@@ -74,6 +91,8 @@ impl Client {
       warp_direction: spawn_direction,
       ready: false,
       transferring: false,
+      resuming: false,
+      resume_token: create_resume_token(),
       area_join_time: 0,
       cached_assets: HashSet::new(),
       texture_buffer: Vec::new(),
@@ -84,6 +103,11 @@ impl Client {
       battle_tracker: VecDeque::new(),
       player_data: PlayerData::new(identity),
       is_input_locked: false,
+      excluded_actors: HashSet::new(),
+      culled_actors: HashSet::new(),
+      movement_batcher: MovementBatcher::new(),
+      compression,
+      network_stats,
     }
   }
 
@@ -104,6 +128,15 @@ impl Client {
   }
 }
 
+pub(super) fn create_resume_token() -> String {
+  use rand::RngCore;
+
+  let mut bytes = [0; 16];
+  rand::rngs::OsRng.fill_bytes(&mut bytes);
+
+  bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
 pub(super) fn find_longest_frame_length(animation_data: &str) -> u32 {
   animation_data
     .lines()
@@ -0,0 +1,23 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counts packets ignored by flood protection, shared between the listening thread and the main thread
+#[derive(Default, Debug)]
+pub struct DroppedTraffic {
+  packets: AtomicU64,
+  bytes: AtomicU64,
+}
+
+impl DroppedTraffic {
+  pub fn record(&self, bytes: usize) {
+    self.packets.fetch_add(1, Ordering::Relaxed);
+    self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+  }
+
+  pub fn packets(&self) -> u64 {
+    self.packets.load(Ordering::Relaxed)
+  }
+
+  pub fn bytes(&self) -> u64 {
+    self.bytes.load(Ordering::Relaxed)
+  }
+}
@@ -0,0 +1,106 @@
+use rand::RngCore;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+// cookies stay valid for one to two rotations
+const ROTATION_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Issues cookies through VersionInfo that clients echo in Connect before the server stores anything for their address.
+/// Spoofed addresses never see their cookie, so they can't create connections.
+/// Each cookie covers a random that can only be redeemed once, so replayed Connect packets can't recreate a session
+pub(super) struct HandshakeCookies {
+  /// SipHash with random keys, unique to this server
+  secret: RandomState,
+  start_time: Instant,
+  /// randoms redeemed while their cookie is still valid, with the epoch they were issued in
+  redeemed_randoms: HashMap<[u8; 16], u64>,
+}
+
+impl HandshakeCookies {
+  pub fn new() -> HandshakeCookies {
+    HandshakeCookies {
+      secret: RandomState::new(),
+      start_time: Instant::now(),
+      redeemed_randoms: HashMap::new(),
+    }
+  }
+
+  /// Returns the cookie and the random it covers
+  pub fn issue(&self, socket_address: SocketAddr) -> (u64, [u8; 16]) {
+    let mut random = [0; 16];
+    rand::rngs::OsRng.fill_bytes(&mut random);
+
+    let cookie = self.create_cookie(socket_address, &random, self.current_epoch());
+
+    (cookie, random)
+  }
+
+  /// Accepts cookies issued during the current or previous rotation, once
+  pub fn redeem(&mut self, socket_address: SocketAddr, cookie: u64, random: &[u8; 16]) -> bool {
+    let epoch = self.current_epoch();
+
+    // forget randoms that can't be verified anymore
+    self
+      .redeemed_randoms
+      .retain(|_, issued_epoch| *issued_epoch + 1 >= epoch);
+
+    let issued_epoch = if cookie == self.create_cookie(socket_address, random, epoch) {
+      epoch
+    } else if epoch > 0 && cookie == self.create_cookie(socket_address, random, epoch - 1) {
+      epoch - 1
+    } else {
+      return false;
+    };
+
+    self
+      .redeemed_randoms
+      .insert(*random, issued_epoch)
+      .is_none()
+  }
+
+  fn current_epoch(&self) -> u64 {
+    self.start_time.elapsed().as_secs() / ROTATION_INTERVAL.as_secs()
+  }
+
+  fn create_cookie(&self, socket_address: SocketAddr, random: &[u8; 16], epoch: u64) -> u64 {
+    let mut hasher = self.secret.build_hasher();
+    socket_address.hash(&mut hasher);
+    random.hash(&mut hasher);
+    epoch.hash(&mut hasher);
+    hasher.finish()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cookies() {
+    let mut cookies = HandshakeCookies::new();
+    let socket_address: SocketAddr = "203.0.113.5:8765".parse().unwrap();
+    let spoofed_address: SocketAddr = "203.0.113.6:8765".parse().unwrap();
+
+    let (cookie, random) = cookies.issue(socket_address);
+
+    assert!(!cookies.redeem(spoofed_address, cookie, &random));
+    assert!(!cookies.redeem(socket_address, cookie.wrapping_add(1), &random));
+    assert!(!cookies.redeem(socket_address, cookie, &[0; 16]));
+    assert!(
+      !HandshakeCookies::new().redeem(socket_address, cookie, &random),
+      "cookies should be unique to the server that issued them"
+    );
+
+    assert!(cookies.redeem(socket_address, cookie, &random));
+    assert!(
+      !cookies.redeem(socket_address, cookie, &random),
+      "cookies should only be redeemed once"
+    );
+
+    let (cookie, random) = cookies.issue(socket_address);
+    assert!(cookies.redeem(socket_address, cookie, &random));
+  }
+}
@@ -0,0 +1,215 @@
+use crate::packets::{ClientPacket, Compression};
+use std::collections::{HashSet, VecDeque};
+use std::net::SocketAddr;
+
+// enough for avatar asset streams sent before the login is accepted
+const MAX_BUFFERED_PACKETS: usize = 1024;
+
+pub struct QueuedLogin {
+  pub socket_address: SocketAddr,
+  pub username: String,
+  pub identity: String,
+  pub data: String,
+  pub compression: Compression,
+  pub(super) buffered_packets: Vec<ClientPacket>,
+}
+
+impl QueuedLogin {
+  pub(super) fn new(
+    socket_address: SocketAddr,
+    username: String,
+    identity: String,
+    data: String,
+    compression: Compression,
+  ) -> QueuedLogin {
+    QueuedLogin {
+      socket_address,
+      username,
+      identity,
+      data,
+      compression,
+      buffered_packets: Vec::new(),
+    }
+  }
+
+  /// Stores packets received while queued to handle after the login is accepted, returns false if the buffer is full
+  pub(super) fn buffer_packet(&mut self, packet: ClientPacket) -> bool {
+    if self.buffered_packets.len() >= MAX_BUFFERED_PACKETS {
+      return false;
+    }
+
+    self.buffered_packets.push(packet);
+    true
+  }
+}
+
+/// Logins waiting for space on the server, identities with priority are placed ahead of identities without
+pub struct LoginQueue {
+  queue: VecDeque<QueuedLogin>,
+  priority_identities: HashSet<String>,
+}
+
+impl LoginQueue {
+  pub fn new() -> LoginQueue {
+    LoginQueue {
+      queue: VecDeque::new(),
+      priority_identities: HashSet::new(),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.queue.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.queue.is_empty()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &QueuedLogin> {
+    self.queue.iter()
+  }
+
+  pub fn contains(&self, socket_address: &SocketAddr) -> bool {
+    self.position(socket_address).is_some()
+  }
+
+  /// 0 is the front of the queue
+  pub fn position(&self, socket_address: &SocketAddr) -> Option<usize> {
+    self
+      .queue
+      .iter()
+      .position(|queued| queued.socket_address == *socket_address)
+  }
+
+  pub fn has_priority(&self, identity: &str) -> bool {
+    self.priority_identities.contains(identity)
+  }
+
+  /// Moves the identity ahead of identities without priority, and places it ahead of them if it joins the queue later
+  pub fn grant_priority(&mut self, identity: String) {
+    let position = self
+      .queue
+      .iter()
+      .position(|queued| queued.identity == identity);
+
+    self.priority_identities.insert(identity);
+
+    if let Some(position) = position {
+      let queued = self.queue.remove(position).unwrap();
+      self.push(queued);
+    }
+  }
+
+  pub fn revoke_priority(&mut self, identity: &str) {
+    self.priority_identities.remove(identity);
+  }
+
+  /// Moves a queued identity to a new position, returns false if the identity isn't queued
+  pub fn move_identity(&mut self, identity: &str, position: usize) -> bool {
+    let current_position = self
+      .queue
+      .iter()
+      .position(|queued| queued.identity == identity);
+
+    let queued = match current_position.and_then(|position| self.queue.remove(position)) {
+      Some(queued) => queued,
+      None => return false,
+    };
+
+    let position = position.min(self.queue.len());
+    self.queue.insert(position, queued);
+
+    true
+  }
+
+  pub(super) fn push(&mut self, queued: QueuedLogin) {
+    if !self.has_priority(&queued.identity) {
+      self.queue.push_back(queued);
+      return;
+    }
+
+    // behind other identities with priority
+    let position = self
+      .queue
+      .iter()
+      .position(|queued| !self.has_priority(&queued.identity))
+      .unwrap_or(self.queue.len());
+
+    self.queue.insert(position, queued);
+  }
+
+  pub(super) fn pop(&mut self) -> Option<QueuedLogin> {
+    self.queue.pop_front()
+  }
+
+  pub(super) fn get_mut(&mut self, socket_address: &SocketAddr) -> Option<&mut QueuedLogin> {
+    self
+      .queue
+      .iter_mut()
+      .find(|queued| queued.socket_address == *socket_address)
+  }
+
+  pub(super) fn remove(&mut self, socket_address: &SocketAddr) -> Option<QueuedLogin> {
+    let position = self.position(socket_address)?;
+    self.queue.remove(position)
+  }
+}
+
+impl Default for LoginQueue {
+  fn default() -> LoginQueue {
+    LoginQueue::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn queue_login(login_queue: &mut LoginQueue, port: u16, identity: &str) {
+    let socket_address = SocketAddr::from(([127, 0, 0, 1], port));
+
+    login_queue.push(QueuedLogin::new(
+      socket_address,
+      String::new(),
+      identity.to_string(),
+      String::new(),
+      Compression::None,
+    ));
+  }
+
+  fn identities(login_queue: &LoginQueue) -> Vec<&str> {
+    login_queue
+      .iter()
+      .map(|queued| queued.identity.as_str())
+      .collect()
+  }
+
+  #[test]
+  fn priority() {
+    let mut login_queue = LoginQueue::new();
+
+    queue_login(&mut login_queue, 1, "a");
+    queue_login(&mut login_queue, 2, "b");
+
+    login_queue.grant_priority(String::from("c"));
+    queue_login(&mut login_queue, 3, "c");
+    assert_eq!(identities(&login_queue), ["c", "a", "b"]);
+
+    login_queue.grant_priority(String::from("b"));
+    assert_eq!(identities(&login_queue), ["c", "b", "a"]);
+
+    login_queue.revoke_priority("c");
+    queue_login(&mut login_queue, 4, "d");
+    assert_eq!(identities(&login_queue), ["c", "b", "a", "d"]);
+
+    assert!(login_queue.move_identity("d", 0));
+    assert!(login_queue.move_identity("c", 100));
+    assert!(!login_queue.move_identity("e", 0));
+    assert_eq!(identities(&login_queue), ["d", "b", "a", "c"]);
+
+    let socket_address = SocketAddr::from(([127, 0, 0, 1], 2));
+    assert_eq!(login_queue.position(&socket_address), Some(1));
+    assert!(login_queue.remove(&socket_address).is_some());
+    assert_eq!(identities(&login_queue), ["d", "a", "c"]);
+  }
+}
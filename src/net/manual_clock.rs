@@ -0,0 +1,28 @@
+use crate::threads::ThreadMessage;
+use std::sync::mpsc;
+
+/// Steps the server's clock with a supplied delta_time, created by [`super::Server::create_manual_clock`]
+///
+/// Steps are queued and processed in order, allowing time based logic to be tested quickly and reproducibly.
+pub struct ManualClock {
+  tx: mpsc::Sender<ThreadMessage>,
+}
+
+impl ManualClock {
+  pub(super) fn new(tx: mpsc::Sender<ThreadMessage>) -> ManualClock {
+    ManualClock { tx }
+  }
+
+  /// Queues a tick, returns false if the server has stopped
+  pub fn step(&self, delta_time: f32) -> bool {
+    self
+      .tx
+      .send(ThreadMessage::ManualTick { delta_time })
+      .is_ok()
+  }
+
+  /// Starts the same shutdown process as SIGINT/SIGTERM, shutdown is bounded by real time and will not wait for steps
+  pub fn shutdown(&self) {
+    let _ = self.tx.send(ThreadMessage::Shutdown);
+  }
+}
@@ -26,6 +26,8 @@ pub struct Map {
   foreground_vel_y: f32,
   foreground_parallax: f32,
   song_path: String,
+  /// actors further than this many tiles from a player are excluded for that player
+  interest_radius: Option<f32>,
   custom_properties: HashMap<String, String>,
   width: usize,
   height: usize,
@@ -60,6 +62,7 @@ impl Map {
       foreground_vel_y: 0.0,
       foreground_parallax: 0.0,
       song_path: String::new(),
+      interest_radius: None,
       custom_properties: HashMap::new(),
       width: 0,
       height: 0,
@@ -374,6 +377,21 @@ impl Map {
     self.mark_dirty();
   }
 
+  pub fn get_interest_radius(&self) -> Option<f32> {
+    self.interest_radius
+  }
+
+  pub fn set_interest_radius(&mut self, radius: Option<f32>) {
+    match radius {
+      Some(radius) => self.set_custom_property("Interest Radius", radius.to_string()),
+      None => {
+        self.custom_properties.remove("Interest Radius");
+        self.interest_radius = None;
+        self.mark_dirty();
+      }
+    }
+  }
+
   pub fn get_custom_properties(&self) -> &HashMap<String, String> {
     &self.custom_properties
   }
@@ -424,6 +442,9 @@ impl Map {
       "Song" => {
         self.song_path = value;
       }
+      "Interest Radius" => {
+        self.interest_radius = value.parse().ok().filter(|radius: &f32| *radius > 0.0);
+      }
       _ => {}
     }
 
@@ -697,3 +718,44 @@ impl Map {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn create_map(interest_radius: &str) -> Map {
+    Map::from(&format!(
+      r#"<map width="1" height="1" tilewidth="64" tileheight="32">
+        <properties>
+          <property name="Interest Radius" value="{}"/>
+        </properties>
+      </map>"#,
+      interest_radius
+    ))
+  }
+
+  #[test]
+  fn interest_radius() {
+    assert_eq!(create_map("12.5").get_interest_radius(), Some(12.5));
+    assert_eq!(
+      create_map("0").get_interest_radius(),
+      None,
+      "radii that would cull everything should be ignored"
+    );
+    assert_eq!(create_map("-3").get_interest_radius(), None);
+    assert_eq!(create_map("far").get_interest_radius(), None);
+
+    let mut map = create_map("12.5");
+
+    map.set_interest_radius(Some(4.0));
+    assert_eq!(map.get_interest_radius(), Some(4.0));
+    assert_eq!(
+      map.get_custom_properties().get("Interest Radius"),
+      Some(&String::from("4"))
+    );
+
+    map.set_interest_radius(None);
+    assert_eq!(map.get_interest_radius(), None);
+    assert!(!map.get_custom_properties().contains_key("Interest Radius"));
+  }
+}
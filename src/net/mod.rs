@@ -2,32 +2,48 @@
 mod net;
 
 mod actor;
+mod actor_handles;
 pub mod actor_property_animation;
+mod admin_console;
 mod area;
 pub mod asset;
 mod asset_manager;
+mod ban_list;
 mod battle_stats;
 pub mod bbs_post;
 mod boot;
 mod client;
 mod direction;
+mod dropped_traffic;
+mod handshake_cookies;
 mod item;
+mod login_queue;
+mod manual_clock;
 pub mod map;
+mod movement_batcher;
+mod packet_recording;
 mod player_data;
 mod plugin_wrapper;
 mod server;
+mod server_builder;
 mod shop_item;
 mod widget_tracker;
 
 pub use actor::Actor;
 pub use area::Area;
 pub use asset::*;
+pub use ban_list::{unix_time, Ban, BanList, BanTarget, IpRange};
 pub use battle_stats::*;
 pub use bbs_post::BbsPost;
 pub use direction::Direction;
+pub use dropped_traffic::DroppedTraffic;
 pub use item::Item;
+pub use login_queue::{LoginQueue, QueuedLogin};
+pub use manual_clock::ManualClock;
 pub use net::Net;
+pub use packet_recording::{load_recording, RecordedEvent, ReplayHandle};
 pub use player_data::PlayerData;
 pub use server::*;
+pub use server_builder::ServerBuilder;
 pub use shop_item::ShopItem;
 pub use widget_tracker::WidgetTracker;
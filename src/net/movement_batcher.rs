@@ -0,0 +1,201 @@
+use super::Direction;
+use crate::packets::{BatchedMove, BatchedPosition};
+use std::collections::{HashMap, VecDeque};
+
+// positions are sent in 1/64ths of a tile
+const POSITION_QUANTIZATION: f32 = 64.0;
+// small enough to fit within the minimum payload size even when every position is absolute
+const MAX_MOVES_PER_BATCH: usize = 64;
+// acks for older batches are ignored
+const MAX_TRACKED_BATCHES: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct MovementState {
+  x: i32,
+  y: i32,
+  z: i32,
+  direction: Direction,
+}
+
+impl MovementState {
+  fn quantize(x: f32, y: f32, z: f32, direction: Direction) -> MovementState {
+    MovementState {
+      x: (x * POSITION_QUANTIZATION).round() as i32,
+      y: (y * POSITION_QUANTIZATION).round() as i32,
+      z: (z * POSITION_QUANTIZATION).round() as i32,
+      direction,
+    }
+  }
+
+  fn delta_from(&self, baseline: &MovementState, baseline_age: u64) -> Option<BatchedPosition> {
+    Some(BatchedPosition::Delta {
+      baseline_age: u8::try_from(baseline_age).ok()?,
+      x: i16::try_from(self.x - baseline.x).ok()?,
+      y: i16::try_from(self.y - baseline.y).ok()?,
+      z: i16::try_from(self.z - baseline.z).ok()?,
+    })
+  }
+}
+
+/// Tracks which actor positions a client acknowledged, to send movement as deltas from those positions
+pub(super) struct MovementBatcher {
+  next_batch_id: u64,
+  /// recent batches that haven't been acknowledged
+  sent_batches: VecDeque<(u64, Vec<(u16, MovementState)>)>,
+  /// the newest acknowledged state for each handle, with the batch it was sent in
+  acknowledged: HashMap<u16, (u64, MovementState)>,
+}
+
+impl MovementBatcher {
+  pub fn new() -> MovementBatcher {
+    MovementBatcher {
+      next_batch_id: 0,
+      sent_batches: VecDeque::new(),
+      acknowledged: HashMap::new(),
+    }
+  }
+
+  /// Takes handle, x, y, z, and direction for each actor, and returns batch ids with moves to send in each batch.
+  /// Actors are skipped once the client acknowledged their current state
+  pub fn create_batches(
+    &mut self,
+    actors: impl Iterator<Item = (u16, f32, f32, f32, Direction)>,
+  ) -> Vec<(u64, Vec<BatchedMove>)> {
+    let changed_states: Vec<(u16, MovementState)> = actors
+      .map(|(handle, x, y, z, direction)| (handle, MovementState::quantize(x, y, z, direction)))
+      .filter(|(handle, state)| {
+        !matches!(self.acknowledged.get(handle), Some((_, acknowledged)) if acknowledged == state)
+      })
+      .collect();
+
+    let mut batches = Vec::new();
+
+    for states in changed_states.chunks(MAX_MOVES_PER_BATCH) {
+      let id = self.next_batch_id;
+      self.next_batch_id += 1;
+
+      let moves = states
+        .iter()
+        .map(|(handle, state)| {
+          let position = self
+            .acknowledged
+            .get(handle)
+            .and_then(|(baseline_id, baseline)| state.delta_from(baseline, id - baseline_id))
+            .unwrap_or(BatchedPosition::Absolute {
+              x: state.x,
+              y: state.y,
+              z: state.z,
+            });
+
+          BatchedMove {
+            handle: *handle,
+            direction: state.direction,
+            position,
+          }
+        })
+        .collect();
+
+      self.sent_batches.push_back((id, states.to_vec()));
+
+      if self.sent_batches.len() > MAX_TRACKED_BATCHES {
+        self.sent_batches.pop_front();
+      }
+
+      batches.push((id, moves));
+    }
+
+    batches
+  }
+
+  pub fn acknowledge(&mut self, batch_id: u64) {
+    let index = match self.sent_batches.iter().position(|(id, _)| *id == batch_id) {
+      Some(index) => index,
+      None => return,
+    };
+
+    let (_, states) = self.sent_batches.remove(index).unwrap();
+
+    for (handle, state) in states {
+      match self.acknowledged.get(&handle) {
+        // batches can be acknowledged out of order
+        Some((acknowledged_id, _)) if *acknowledged_id > batch_id => {}
+        _ => {
+          self.acknowledged.insert(handle, (batch_id, state));
+        }
+      }
+    }
+  }
+
+  /// Drops state for an actor the client no longer knows about, the handle may be reassigned
+  pub fn forget(&mut self, handle: u16) {
+    self.acknowledged.remove(&handle);
+
+    for (_, states) in &mut self.sent_batches {
+      states.retain(|(batch_handle, _)| *batch_handle != handle);
+    }
+  }
+
+  /// For clients that were sent a new area
+  pub fn reset(&mut self) {
+    self.sent_batches.clear();
+    self.acknowledged.clear();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn delta_encoding() {
+    let mut batcher = MovementBatcher::new();
+
+    let batches = batcher.create_batches([(1, 2.0, 3.0, 0.0, Direction::Up)].into_iter());
+    assert_eq!(batches.len(), 1);
+    assert_eq!(
+      batches[0].1[0].position,
+      BatchedPosition::Absolute {
+        x: 128,
+        y: 192,
+        z: 0
+      }
+    );
+
+    // not acknowledged yet, so the position is resent in full
+    let batches = batcher.create_batches([(1, 2.0, 3.0, 0.0, Direction::Up)].into_iter());
+    assert!(matches!(
+      batches[0].1[0].position,
+      BatchedPosition::Absolute { .. }
+    ));
+
+    batcher.acknowledge(0);
+
+    let batches = batcher.create_batches([(1, 2.0, 3.0, 0.0, Direction::Up)].into_iter());
+    assert!(
+      batches.is_empty(),
+      "acknowledged states shouldn't be resent"
+    );
+
+    let batches = batcher.create_batches([(1, 2.5, 3.0, 0.0, Direction::Up)].into_iter());
+    assert_eq!(
+      batches[0].1[0].position,
+      BatchedPosition::Delta {
+        baseline_age: 2,
+        x: 32,
+        y: 0,
+        z: 0
+      }
+    );
+
+    let batches = batcher.create_batches([(1, 1000.0, 3.0, 0.0, Direction::Up)].into_iter());
+    assert!(
+      matches!(batches[0].1[0].position, BatchedPosition::Absolute { .. }),
+      "deltas too large for an i16 should be sent in full"
+    );
+
+    let actors = (1..=100).map(|handle| (handle, 0.0, 0.0, 0.0, Direction::None));
+    let batches = batcher.create_batches(actors);
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0].1.len(), MAX_MOVES_PER_BATCH);
+  }
+}
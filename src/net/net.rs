@@ -1,16 +1,25 @@
+use super::actor_handles::ActorHandles;
 use super::actor_property_animation::KeyFrame;
 use super::asset_manager::AssetManager;
 use super::boot::Boot;
 use super::client::Client;
 use super::map::Map;
 use super::server::ServerConfig;
-use super::{Actor, Area, Asset, AssetData, BbsPost, Direction, Item, PlayerData, ShopItem};
-use crate::packets::{create_asset_stream, PacketOrchestrator, Reliability, ServerPacket};
+use super::{
+  Actor, Area, Asset, AssetData, Ban, BanList, BbsPost, Direction, DroppedTraffic, Item,
+  LoginQueue, PlayerData, ShopItem,
+};
+use crate::packets::{
+  create_asset_stream, sequence_channel, Compression, NetworkStats, PacketOrchestrator,
+  Reliability, ServerPacket,
+};
 use log::*;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::UdpSocket;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
 
 pub struct Net {
   socket: Rc<UdpSocket>,
@@ -19,10 +28,17 @@ pub struct Net {
   areas: HashMap<String, Area>,
   clients: HashMap<String, Client>,
   bots: HashMap<String, Actor>,
+  actor_handles: ActorHandles,
   asset_manager: AssetManager,
   active_plugin: usize,
   kick_list: Vec<Boot>,
   items: HashMap<String, Item>,
+  login_queue: LoginQueue,
+  ban_list: BanList,
+  maintenance_message: Option<String>,
+  maintenance_exemptions: HashSet<String>,
+  manual_time: Option<Instant>,
+  dropped_traffic: Arc<DroppedTraffic>,
 }
 
 impl Net {
@@ -35,15 +51,19 @@ impl Net {
     use std::fs::{read_dir, read_to_string};
 
     let mut asset_manager = AssetManager::new();
-    asset_manager.load_assets_from_dir(std::path::Path::new("assets"));
+    asset_manager.load_assets_from_dir(&config.assets_dir);
 
     let mut areas = HashMap::new();
     let mut default_area_provided = false;
 
-    for map_dir_entry in read_dir("./areas")
-      .expect("Area folder missing! (./areas)")
-      .flatten()
-    {
+    let map_dir_entries = read_dir(&config.areas_dir).unwrap_or_else(|_| {
+      panic!(
+        "Area folder missing! ({})",
+        config.areas_dir.to_string_lossy()
+      )
+    });
+
+    for map_dir_entry in map_dir_entries.flatten() {
       let map_path = map_dir_entry.path();
       let area_id = map_path
         .file_stem()
@@ -70,6 +90,20 @@ impl Net {
       panic!("No default (default.tmx) area data found");
     }
 
+    let ban_list =
+      BanList::load(&config.ban_list_path, config.allow_list_only).unwrap_or_else(|error| {
+        panic!(
+          "Failed to load ban list {}: {}",
+          config.ban_list_path.display(),
+          error
+        )
+      });
+
+    let maintenance_message = config
+      .maintenance
+      .then(|| config.maintenance_message.clone());
+    let maintenance_exemptions = config.maintenance_exemptions.iter().cloned().collect();
+
     Net {
       socket,
       packet_orchestrator,
@@ -77,10 +111,17 @@ impl Net {
       areas,
       clients: HashMap::new(),
       bots: HashMap::new(),
+      actor_handles: ActorHandles::default(),
       asset_manager,
       active_plugin: 0,
       kick_list: Vec::new(),
       items: HashMap::new(),
+      login_queue: LoginQueue::new(),
+      ban_list,
+      maintenance_message,
+      maintenance_exemptions,
+      manual_time: None,
+      dropped_traffic: Arc::new(DroppedTraffic::default()),
     }
   }
 
@@ -93,7 +134,6 @@ impl Net {
 
     update_cached_clients(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       &path,
@@ -154,8 +194,130 @@ impl Net {
     self.clients.get(id).map(|client| &client.actor)
   }
 
+  pub fn get_player_count(&self) -> usize {
+    self.clients.len()
+  }
+
+  pub fn get_login_queue(&self) -> &LoginQueue {
+    &self.login_queue
+  }
+
+  pub fn get_login_queue_mut(&mut self) -> &mut LoginQueue {
+    &mut self.login_queue
+  }
+
+  pub fn get_ban_list(&self) -> &BanList {
+    &self.ban_list
+  }
+
+  /// Use `ban` to add bans, kicks won't be handled otherwise
+  pub fn get_ban_list_mut(&mut self) -> &mut BanList {
+    &mut self.ban_list
+  }
+
+  /// Adds the ban and kicks connected or queued players matching it
+  pub fn ban(&mut self, ban: Ban) {
+    let reason = if ban.reason.is_empty() {
+      String::from("Banned")
+    } else {
+      format!("Banned: {}", ban.reason)
+    };
+
+    let is_allowed = |identity: &str, socket_address: std::net::SocketAddr| {
+      self.ban_list.is_allowed(identity, socket_address.ip())
+    };
+
+    let connected = self
+      .clients
+      .values()
+      .map(|client| (client.player_data.identity.as_str(), client.socket_address));
+
+    let queued = self
+      .login_queue
+      .iter()
+      .map(|queued| (queued.identity.as_str(), queued.socket_address));
+
+    let kick_list: Vec<Boot> = connected
+      .chain(queued)
+      .filter(|(identity, socket_address)| {
+        ban.target.matches(identity, socket_address.ip()) && !is_allowed(identity, *socket_address)
+      })
+      .map(|(_, socket_address)| Boot {
+        socket_address,
+        reason: reason.clone(),
+        warp_out: true,
+      })
+      .collect();
+
+    self.kick_list.extend(kick_list);
+    self.ban_list.ban(ban);
+  }
+
+  pub fn is_in_maintenance(&self) -> bool {
+    self.maintenance_message.is_some()
+  }
+
+  /// The message shown to players refused during maintenance
+  pub fn get_maintenance_message(&self) -> Option<&str> {
+    self.maintenance_message.as_deref()
+  }
+
+  /// Refuses new logins while enabled, logins waiting in the queue are refused as well. Uses the configured message if none is provided
+  pub fn set_maintenance(&mut self, enabled: bool, message: Option<String>) {
+    if !enabled {
+      self.maintenance_message = None;
+      return;
+    }
+
+    let message = message.unwrap_or_else(|| self.config.maintenance_message.clone());
+
+    let kick_list: Vec<Boot> = self
+      .login_queue
+      .iter()
+      .filter(|queued| !self.maintenance_exemptions.contains(&queued.identity))
+      .map(|queued| Boot {
+        socket_address: queued.socket_address,
+        reason: message.clone(),
+        warp_out: false,
+      })
+      .collect();
+
+    self.kick_list.extend(kick_list);
+    self.maintenance_message = Some(message);
+  }
+
+  pub fn is_maintenance_exempt(&self, identity: &str) -> bool {
+    self.maintenance_exemptions.contains(identity)
+  }
+
+  pub fn add_maintenance_exemption(&mut self, identity: String) {
+    self.maintenance_exemptions.insert(identity);
+  }
+
+  /// Returns false if the identity wasn't exempt
+  pub fn remove_maintenance_exemption(&mut self, identity: &str) -> bool {
+    self.maintenance_exemptions.remove(identity)
+  }
+
+  /// Packets ignored by flood protection since the server started
+  pub fn get_dropped_traffic(&self) -> &Arc<DroppedTraffic> {
+    &self.dropped_traffic
+  }
+
+  pub fn get_player_network_stats(&self, id: &str) -> Option<NetworkStats> {
+    self
+      .clients
+      .get(id)
+      .map(|client| client.network_stats.borrow().clone())
+  }
+
   pub fn get_player_addr(&self, id: &str) -> Option<std::net::SocketAddr> {
-    self.clients.get(id).map(|client| client.socket_address)
+    use crate::helpers::canonical_socket_addr;
+
+    self
+      .clients
+      .get(id)
+      .map(|client| canonical_socket_addr(client.socket_address))
   }
 
   #[allow(dead_code)]
@@ -171,7 +333,6 @@ impl Net {
     if let Some(area) = self.areas.get_mut(area_id) {
       ensure_asset(
         &mut *self.packet_orchestrator.borrow_mut(),
-        self.config.max_payload_size,
         &self.asset_manager,
         &mut self.clients,
         area.get_connected_players(),
@@ -186,7 +347,6 @@ impl Net {
     if let Some(area) = self.areas.get(area_id) {
       ensure_asset(
         &mut *self.packet_orchestrator.borrow_mut(),
-        self.config.max_payload_size,
         &self.asset_manager,
         &mut self.clients,
         area.get_connected_players(),
@@ -250,7 +410,6 @@ impl Net {
 
     ensure_assets(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       area.get_connected_players(),
@@ -396,7 +555,6 @@ impl Net {
 
     ensure_assets(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       &[id.to_string()],
@@ -406,7 +564,6 @@ impl Net {
     broadcast_actor_keyframes(
       &mut *self.packet_orchestrator.borrow_mut(),
       area,
-      self.config.max_payload_size,
       id,
       animation,
     );
@@ -431,7 +588,6 @@ impl Net {
   pub fn preload_asset_for_player(&mut self, id: &str, asset_path: &str) {
     ensure_asset(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       &[String::from(id)],
@@ -448,7 +604,6 @@ impl Net {
   pub fn play_sound_for_player(&mut self, id: &str, path: &str) {
     ensure_asset(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       &[id.to_string()],
@@ -479,6 +634,18 @@ impl Net {
   }
 
   pub fn exclude_actor_for_player(&mut self, id: &str, actor_id: &str) {
+    let client = match self.clients.get_mut(id) {
+      Some(client) => client,
+      None => return,
+    };
+
+    client.excluded_actors.insert(actor_id.to_string());
+
+    if client.culled_actors.contains(actor_id) {
+      // already excluded by the interest radius
+      return;
+    }
+
     self.packet_orchestrator.borrow_mut().send_by_id(
       id,
       Reliability::ReliableOrdered,
@@ -487,6 +654,18 @@ impl Net {
   }
 
   pub fn include_actor_for_player(&mut self, id: &str, actor_id: &str) {
+    let client = match self.clients.get_mut(id) {
+      Some(client) => client,
+      None => return,
+    };
+
+    client.excluded_actors.remove(actor_id);
+
+    if client.culled_actors.contains(actor_id) {
+      // included once the actor is within the interest radius
+      return;
+    }
+
     self.packet_orchestrator.borrow_mut().send_by_id(
       id,
       Reliability::ReliableOrdered,
@@ -615,42 +794,18 @@ impl Net {
     z: f32,
     direction: Direction,
   ) {
+    let time = self.get_time();
     let client = self.clients.get_mut(id).unwrap();
 
-    client.actor.set_position(x, y, z);
-    client.actor.set_direction(direction);
-
-    // skip if client has not even been sent to anyone yet
-    if !client.ready {
-      return;
-    }
-
-    let idle_duration = client.actor.last_movement_time.elapsed().as_secs_f32();
+    // sent to other players with the next movement batch
+    client.actor.set_position(x, y, z, time);
+    client.actor.set_direction(direction, time);
+  }
 
-    // skip if we've been sending idle packets for too long
-    if idle_duration > self.config.max_idle_packet_duration {
-      return;
+  pub(super) fn acknowledge_movement_batch(&mut self, id: &str, batch_id: u64) {
+    if let Some(client) = self.clients.get_mut(id) {
+      client.movement_batcher.acknowledge(batch_id);
     }
-
-    let area = match self.areas.get(&client.actor.area_id) {
-      Some(area) => area,
-      None => return, // area deleted, should be getting kicked
-    };
-
-    let packet = ServerPacket::ActorMove {
-      ticket: id,
-      x,
-      y,
-      z,
-      direction,
-    };
-
-    broadcast_to_area(
-      &mut *self.packet_orchestrator.borrow_mut(),
-      area,
-      Reliability::UnreliableSequenced,
-      packet,
-    );
   }
 
   pub fn message_player(
@@ -662,7 +817,6 @@ impl Net {
   ) {
     ensure_assets(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       &[id.to_string()],
@@ -693,7 +847,6 @@ impl Net {
   ) {
     ensure_assets(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       &[id.to_string()],
@@ -726,7 +879,6 @@ impl Net {
   ) {
     ensure_assets(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       &[id.to_string()],
@@ -777,243 +929,53 @@ impl Net {
     color: (u8, u8, u8),
     posts: Vec<BbsPost>,
   ) {
-    use super::bbs_post::calc_size;
-    use crate::helpers::iterators::IteratorHelper;
-
     let client = if let Some(client) = self.clients.get_mut(player_id) {
       client
     } else {
       return;
     };
 
-    let mut packet_orchestrator = self.packet_orchestrator.borrow_mut();
-
-    let start_depth = client.widget_tracker.get_board_count() as u8;
-    client.widget_tracker.track_board(self.active_plugin);
-
-    if posts.is_empty() {
-      // logic below will send nothing if there's no posts,
-      // we want to at least open the bbs if the post vec is empty
-      packet_orchestrator.send(
-        client.socket_address,
-        Reliability::ReliableOrdered,
-        ServerPacket::OpenBoard {
-          current_depth: start_depth,
-          name,
-          color,
-          posts: &[],
-        },
-      );
-    }
-
-    let max_payload_size = self.config.max_payload_size;
-    let chunk_state: RefCell<(usize, Option<String>)> = RefCell::new((0, None));
-
-    let calc_chunk_limit = |_| {
-      // reliability + id + type
-      let mut packet_size = 1 + 8 + 2;
-
-      let borrowed_state = chunk_state.borrow();
-
-      if borrowed_state.0 == 0 {
-        packet_size += 2 + name.len();
-        packet_size += 3; // color
-      } else {
-        packet_size += 2; // currentDepth + hasReference
-
-        if let Some(last_id) = borrowed_state.1.as_ref() {
-          packet_size += 2 + last_id.len(); // reference
-        }
-      }
-
-      max_payload_size - packet_size
-    };
-
-    let calc_post_size = |post: &BbsPost| {
-      let mut borrowed_state = chunk_state.borrow_mut();
-      borrowed_state.0 += 1;
-      borrowed_state.1 = Some(post.id.clone());
-      calc_size(post)
-    };
-
-    let chunks = posts
-      .into_iter()
-      .pack_chunks_lossy(calc_chunk_limit, calc_post_size);
-
-    let mut last_id = None;
     let current_depth = client.widget_tracker.get_board_count() as u8;
+    client.widget_tracker.track_board(self.active_plugin);
 
-    use crate::packets::build_packet;
-
-    let mut packets = Vec::new();
-
-    for (i, mut chunk) in chunks.enumerate() {
-      let mut ref_id = None;
-      std::mem::swap(&mut ref_id, &mut last_id); // avoiding clone
-
-      let packet = if i == 0 {
-        ServerPacket::OpenBoard {
-          current_depth: start_depth,
-          name,
-          color,
-          posts: chunk.as_slice(),
-        }
-      } else {
-        ServerPacket::AppendPosts {
-          current_depth,
-          reference: ref_id.as_deref(),
-          posts: chunk.as_slice(),
-        }
-      };
-
-      packets.push(build_packet(packet));
-
-      last_id = chunk.pop().map(|post| post.id);
-    }
-
-    packet_orchestrator.send_byte_packets(
+    self.packet_orchestrator.borrow_mut().send(
       client.socket_address,
       Reliability::ReliableOrdered,
-      &packets,
+      ServerPacket::OpenBoard {
+        current_depth,
+        name,
+        color,
+        posts: &posts,
+      },
     );
   }
 
   pub fn prepend_posts(&mut self, player_id: &str, reference: Option<&str>, posts: Vec<BbsPost>) {
-    use super::bbs_post::calc_size;
-    use crate::helpers::iterators::IteratorHelper;
-
-    let client = if let Some(client) = self.clients.get_mut(player_id) {
-      client
-    } else {
-      return;
-    };
-
-    let max_payload_size = self.config.max_payload_size;
-
-    let reference = reference.map(|reference_str| reference_str.to_string());
-    let last_id = RefCell::new(reference.clone());
-
-    let calc_chunk_limit = |_| {
-      // reliability + id + type
-      let mut packet_size = 1 + 8 + 2;
-      packet_size += 2; // currentDepth + hasReference
-
-      if let Some(last_id) = last_id.borrow().as_ref() {
-        packet_size += 2 + last_id.len(); // reference
-      }
-
-      max_payload_size - packet_size
-    };
-
-    let calc_post_size = |post: &BbsPost| {
-      *last_id.borrow_mut() = Some(post.id.clone());
-      calc_size(post)
-    };
-
-    let chunks = posts
-      .into_iter()
-      .pack_chunks_lossy(calc_chunk_limit, calc_post_size);
-
-    let mut last_id = reference;
-    let current_depth = client.widget_tracker.get_board_count() as u8;
-
-    use crate::packets::build_packet;
-
-    let mut packets = Vec::new();
-
-    for (i, mut chunk) in chunks.enumerate() {
-      let mut ref_id = None;
-      std::mem::swap(&mut ref_id, &mut last_id); // avoiding clone
-
-      let packet = if i == 0 {
+    if let Some(client) = self.clients.get(player_id) {
+      self.packet_orchestrator.borrow_mut().send(
+        client.socket_address,
+        Reliability::ReliableOrdered,
         ServerPacket::PrependPosts {
-          current_depth,
-          reference: ref_id.as_deref(),
-          posts: chunk.as_slice(),
-        }
-      } else {
-        ServerPacket::AppendPosts {
-          current_depth,
-          reference: ref_id.as_deref(),
-          posts: chunk.as_slice(),
-        }
-      };
-
-      packets.push(build_packet(packet));
-
-      last_id = chunk.pop().map(|post| post.id);
+          current_depth: client.widget_tracker.get_board_count() as u8,
+          reference,
+          posts: &posts,
+        },
+      );
     }
-
-    self.packet_orchestrator.borrow_mut().send_byte_packets(
-      client.socket_address,
-      Reliability::ReliableOrdered,
-      &packets,
-    );
   }
 
   pub fn append_posts(&mut self, player_id: &str, reference: Option<&str>, posts: Vec<BbsPost>) {
-    use super::bbs_post::calc_size;
-    use crate::helpers::iterators::IteratorHelper;
-
-    let client = if let Some(client) = self.clients.get_mut(player_id) {
-      client
-    } else {
-      return;
-    };
-
-    let max_payload_size = self.config.max_payload_size;
-
-    let reference = reference.map(|reference_str| reference_str.to_string());
-    let last_id = RefCell::new(reference.clone());
-
-    let calc_chunk_limit = |_| {
-      // reliability + id + type
-      let mut packet_size = 1 + 8 + 2;
-      packet_size += 2; // currentDepth + hasReference
-
-      if let Some(last_id) = last_id.borrow().as_ref() {
-        packet_size += 2 + last_id.len(); // reference
-      }
-
-      max_payload_size - packet_size
-    };
-
-    let calc_post_size = |post: &BbsPost| {
-      *last_id.borrow_mut() = Some(post.id.clone());
-      calc_size(post)
-    };
-
-    let chunks = posts
-      .into_iter()
-      .pack_chunks_lossy(calc_chunk_limit, calc_post_size);
-
-    let mut last_id = reference;
-    let current_depth = client.widget_tracker.get_board_count() as u8;
-
-    use crate::packets::build_packet;
-
-    let mut packets = Vec::new();
-
-    for mut chunk in chunks {
-      let mut ref_id = None;
-      std::mem::swap(&mut ref_id, &mut last_id); // avoiding clone
-
-      let packet = ServerPacket::AppendPosts {
-        current_depth,
-        reference: ref_id.as_deref(),
-        posts: chunk.as_slice(),
-      };
-
-      packets.push(build_packet(packet));
-
-      last_id = chunk.pop().map(|post| post.id);
+    if let Some(client) = self.clients.get(player_id) {
+      self.packet_orchestrator.borrow_mut().send(
+        client.socket_address,
+        Reliability::ReliableOrdered,
+        ServerPacket::AppendPosts {
+          current_depth: client.widget_tracker.get_board_count() as u8,
+          reference,
+          posts: &posts,
+        },
+      );
     }
-
-    self.packet_orchestrator.borrow_mut().send_byte_packets(
-      client.socket_address,
-      Reliability::ReliableOrdered,
-      &packets,
-    );
   }
 
   pub fn remove_post(&mut self, player_id: &str, post_id: &str) {
@@ -1044,9 +1006,6 @@ impl Net {
     mug_texture_path: &str,
     mug_animation_path: &str,
   ) {
-    use super::shop_item::calc_size;
-    use crate::helpers::iterators::IteratorHelper;
-
     let client = if let Some(client) = self.clients.get_mut(player_id) {
       client
     } else {
@@ -1055,31 +1014,15 @@ impl Net {
 
     client.widget_tracker.track_shop(self.active_plugin);
 
-    let max_payload_size = self.config.max_payload_size;
-
-    let calc_chunk_limit = |_| {
-      // reliability + id + type
-      let header_size = 1 + 8 + 2;
-
-      max_payload_size - header_size
-    };
-
     use crate::packets::build_packet;
 
-    let mut packets: Vec<Vec<u8>> = items
-      .into_iter()
-      .pack_chunks_lossy(calc_chunk_limit, calc_size)
-      .map(|chunk| {
-        build_packet(ServerPacket::ShopInventory {
-          items: chunk.as_slice(),
-        })
-      })
-      .collect();
-
-    packets.push(build_packet(ServerPacket::OpenShop {
-      mug_texture_path,
-      mug_animation_path,
-    }));
+    let packets = [
+      build_packet(ServerPacket::ShopInventory { items: &items }),
+      build_packet(ServerPacket::OpenShop {
+        mug_texture_path,
+        mug_animation_path,
+      }),
+    ];
 
     self.packet_orchestrator.borrow_mut().send_byte_packets(
       client.socket_address,
@@ -1151,7 +1094,6 @@ impl Net {
 
     ensure_asset(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       &[player_id.to_string()],
@@ -1162,7 +1104,6 @@ impl Net {
   pub fn set_mod_whitelist_for_player(&mut self, player_id: &str, whitelist_path: &str) {
     ensure_asset(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       &[String::from(player_id)],
@@ -1179,7 +1120,6 @@ impl Net {
   pub fn offer_package(&mut self, player_id: &str, package_path: &str) {
     ensure_asset(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       &[String::from(player_id)],
@@ -1233,7 +1173,6 @@ impl Net {
 
     ensure_asset(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       &[String::from(player_id)],
@@ -1323,7 +1262,7 @@ impl Net {
 
       self.packet_orchestrator.borrow_mut().send(
         client.socket_address,
-        Reliability::ReliableOrdered,
+        Reliability::ReliableSequenced(sequence_channel("health")),
         ServerPacket::Health { health, max_health },
       );
     }
@@ -1337,7 +1276,7 @@ impl Net {
 
       self.packet_orchestrator.borrow_mut().send(
         client.socket_address,
-        Reliability::ReliableOrdered,
+        Reliability::ReliableSequenced(sequence_channel("health")),
         ServerPacket::Health { health, max_health },
       );
     }
@@ -1349,7 +1288,7 @@ impl Net {
 
       self.packet_orchestrator.borrow_mut().send(
         client.socket_address,
-        Reliability::ReliableOrdered,
+        Reliability::ReliableSequenced(sequence_channel("emotion")),
         ServerPacket::Emotion { emotion },
       );
     }
@@ -1361,7 +1300,7 @@ impl Net {
 
       self.packet_orchestrator.borrow_mut().send(
         client.socket_address,
-        Reliability::ReliableOrdered,
+        Reliability::ReliableSequenced(sequence_channel("money")),
         ServerPacket::Money { money },
       );
     }
@@ -1467,6 +1406,13 @@ impl Net {
       .borrow_mut()
       .leave_room(client.socket_address, previous_area.get_id());
 
+    // actors in the new area are spawned for the client from scratch
+    client.excluded_actors.clear();
+    client.culled_actors.clear();
+    client.movement_batcher.reset();
+    forget_actor(&mut self.clients, id, self.actor_handles.get(id));
+    let client = self.clients.get_mut(id).unwrap();
+
     client.warp_area = area_id.to_string();
 
     broadcast_to_area(
@@ -1521,7 +1467,6 @@ impl Net {
 
     ensure_assets(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       area.get_connected_players(),
@@ -1567,11 +1512,13 @@ impl Net {
     data: &str,
     warp_out: bool,
   ) {
+    use crate::helpers::strip_ipv6_brackets;
+
     self.packet_orchestrator.borrow_mut().send_by_id(
       id,
       Reliability::ReliableOrdered,
       ServerPacket::TransferServer {
-        address,
+        address: strip_ipv6_brackets(address),
         port,
         data,
         warp_out,
@@ -1582,11 +1529,13 @@ impl Net {
   }
 
   pub fn request_authorization(&mut self, id: &str, address: &str, port: u16, data: &[u8]) {
+    use crate::helpers::strip_ipv6_brackets;
+
     self.packet_orchestrator.borrow_mut().send_by_id(
       id,
       Reliability::ReliableOrdered,
       ServerPacket::Authorize {
-        address,
+        address: strip_ipv6_brackets(address),
         port,
         data,
       },
@@ -1616,6 +1565,8 @@ impl Net {
     socket_address: std::net::SocketAddr,
     name: String,
     identity: String,
+    compression: Compression,
+    network_stats: Rc<RefCell<NetworkStats>>,
   ) -> String {
     let area_id = String::from("default");
     let area = self.get_area_mut(&area_id).unwrap();
@@ -1632,12 +1583,16 @@ impl Net {
       spawn_y,
       spawn_z,
       spawn_direction,
+      self.get_time(),
+      compression,
+      network_stats.clone(),
     );
 
     let id = client.actor.id.clone();
+    self.actor_handles.assign(&id);
 
     let mut packet_orchestrator = self.packet_orchestrator.borrow_mut();
-    packet_orchestrator.add_client(client.socket_address, id.clone());
+    packet_orchestrator.add_client(client.socket_address, id.clone(), network_stats);
 
     self.clients.insert(id.clone(), client);
 
@@ -1730,7 +1685,6 @@ impl Net {
 
     ensure_assets(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       area.get_connected_players(),
@@ -1748,6 +1702,7 @@ impl Net {
       spawn_y: client.warp_y,
       spawn_z: client.warp_z,
       spawn_direction: client.warp_direction,
+      resume_token: &client.resume_token,
     };
 
     self.packet_orchestrator.borrow_mut().send(
@@ -1757,6 +1712,84 @@ impl Net {
     );
   }
 
+  /// Despawns a player that lost connection, keeping their state for resume_player
+  pub(super) fn suspend_player(&mut self, id: &str) {
+    let client = match self.clients.get_mut(id) {
+      Some(client) => client,
+      None => return,
+    };
+
+    client.ready = false;
+    // actors are spawned from scratch when the player resumes
+    client.excluded_actors.clear();
+    client.culled_actors.clear();
+    client.movement_batcher.reset();
+
+    let socket_address = client.socket_address;
+    let mut packet_orchestrator = self.packet_orchestrator.borrow_mut();
+    packet_orchestrator.drop_client(socket_address);
+
+    let area = self.areas.get_mut(&client.actor.area_id);
+
+    match area {
+      Some(area) if area.get_connected_players().contains(&client.actor.id) => {
+        // respawn where the player left off
+        client.warp_in = true;
+        client.warp_x = client.actor.x;
+        client.warp_y = client.actor.y;
+        client.warp_z = client.actor.z;
+        client.warp_direction = client.actor.direction;
+
+        area.remove_player(id);
+
+        broadcast_to_area(
+          &mut packet_orchestrator,
+          area,
+          Reliability::ReliableOrdered,
+          ServerPacket::ActorDisconnected {
+            ticket: id,
+            warp_out: true,
+          },
+        );
+      }
+      Some(_) => {
+        // left the previous area for a transfer, respawn at the destination
+        client.actor.area_id = client.warp_area.clone();
+      }
+      None => {} // area deleted, should be getting kicked
+    }
+
+    forget_actor(&mut self.clients, id, self.actor_handles.get(id));
+  }
+
+  /// Moves a suspended player to a new connection, spawn_client should follow
+  pub(super) fn resume_player(
+    &mut self,
+    id: &str,
+    socket_address: std::net::SocketAddr,
+    network_stats: Rc<RefCell<NetworkStats>>,
+  ) {
+    use super::client::create_resume_token;
+
+    let client = match self.clients.get_mut(id) {
+      Some(client) => client,
+      None => return,
+    };
+
+    client.socket_address = socket_address;
+    client.network_stats = network_stats.clone();
+    client.resuming = true;
+    client.transferring = false;
+    client.resume_token = create_resume_token();
+    // the client may have lost its cache, it can report cached assets again
+    client.cached_assets.clear();
+
+    self
+      .packet_orchestrator
+      .borrow_mut()
+      .add_client(socket_address, id.to_string(), network_stats);
+  }
+
   pub(super) fn connect_client(&mut self, player_id: &str) {
     self.packet_orchestrator.borrow_mut().send_by_id(
       player_id,
@@ -1792,7 +1825,8 @@ impl Net {
       asset_paths.push(actor.texture_path.clone());
       asset_paths.push(actor.animation_path.clone());
 
-      packets.push(actor.create_spawn_packet(actor.x, actor.y, actor.z, false));
+      let handle = self.actor_handles.get(&actor.id);
+      packets.push(actor.create_spawn_packet(handle, actor.x, actor.y, actor.z, false));
     }
 
     // send bots
@@ -1802,7 +1836,8 @@ impl Net {
       asset_paths.push(bot.texture_path.clone());
       asset_paths.push(bot.animation_path.clone());
 
-      packets.push(bot.create_spawn_packet(bot.x, bot.y, bot.z, false));
+      let handle = self.actor_handles.get(&bot.id);
+      packets.push(bot.create_spawn_packet(handle, bot.x, bot.y, bot.z, false));
     }
 
     if let Some(custom_emotes_path) = &self.config.custom_emotes_path {
@@ -1822,7 +1857,6 @@ impl Net {
     for asset_path in asset_paths {
       ensure_asset(
         &mut *self.packet_orchestrator.borrow_mut(),
-        self.config.max_payload_size,
         &self.asset_manager,
         &mut self.clients,
         &asset_recievers[..],
@@ -1852,11 +1886,15 @@ impl Net {
 
     client.ready = true;
     client.transferring = false;
-
-    let packet =
-      client
-        .actor
-        .create_spawn_packet(client.warp_x, client.warp_y, client.warp_z, client.warp_in);
+    client.resuming = false;
+
+    let packet = client.actor.create_spawn_packet(
+      self.actor_handles.get(id),
+      client.warp_x,
+      client.warp_y,
+      client.warp_z,
+      client.warp_in,
+    );
 
     let packet_bytes = build_packet(packet);
 
@@ -1885,26 +1923,34 @@ impl Net {
       self.asset_manager.remove_asset(asset_path);
     }
 
+    let handle = self.actor_handles.release(id);
+
     let area = match self.areas.get_mut(&client.actor.area_id) {
       Some(area) => area,
       None => return,
     };
 
-    area.remove_player(&client.actor.id);
+    // suspended players have already been despawned
+    let spawned = area.get_connected_players().contains(&client.actor.id);
 
-    let packet = ServerPacket::ActorDisconnected {
-      ticket: id,
-      warp_out,
-    };
+    area.remove_player(&client.actor.id);
+    forget_actor(&mut self.clients, id, handle);
 
     let mut packet_orchestrator = self.packet_orchestrator.borrow_mut();
 
-    broadcast_to_area(
-      &mut packet_orchestrator,
-      area,
-      Reliability::ReliableOrdered,
-      packet,
-    );
+    if spawned {
+      let packet = ServerPacket::ActorDisconnected {
+        ticket: id,
+        warp_out,
+      };
+
+      broadcast_to_area(
+        &mut packet_orchestrator,
+        area,
+        Reliability::ReliableOrdered,
+        packet,
+      );
+    }
 
     packet_orchestrator.drop_client(client.socket_address);
   }
@@ -1927,11 +1973,11 @@ impl Net {
     if let Some(area) = self.areas.get_mut(&bot.area_id) {
       area.add_bot(bot.id.clone());
 
-      let packet = bot.create_spawn_packet(bot.x, bot.y, bot.z, warp_in);
+      let handle = self.actor_handles.assign(&bot.id);
+      let packet = bot.create_spawn_packet(handle, bot.x, bot.y, bot.z, warp_in);
 
       ensure_assets(
         &mut *self.packet_orchestrator.borrow_mut(),
-        self.config.max_payload_size,
         &self.asset_manager,
         &mut self.clients,
         area.get_connected_players(),
@@ -1955,12 +2001,15 @@ impl Net {
       None => return,
     };
 
+    let handle = self.actor_handles.release(id);
+
     let area = match self.areas.get_mut(&bot.area_id) {
       Some(area) => area,
       None => return,
     };
 
     area.remove_bot(&bot.id);
+    forget_actor(&mut self.clients, id, handle);
 
     let packet = ServerPacket::ActorDisconnected {
       ticket: id,
@@ -1999,20 +2048,24 @@ impl Net {
   }
 
   pub fn move_bot(&mut self, id: &str, x: f32, y: f32, z: f32) {
+    let time = self.get_time();
+
     if let Some(bot) = self.bots.get_mut(id) {
       let updated_direction = Direction::from_offset(x - bot.x, y - bot.y);
 
       if !matches!(updated_direction, Direction::None) {
-        bot.set_direction(updated_direction);
+        bot.set_direction(updated_direction, time);
       }
 
-      bot.set_position(x, y, z);
+      bot.set_position(x, y, z, time);
     }
   }
 
   pub fn set_bot_direction(&mut self, id: &str, direction: Direction) {
+    let time = self.get_time();
+
     if let Some(bot) = self.bots.get_mut(id) {
-      bot.set_direction(direction);
+      bot.set_direction(direction, time);
     }
   }
 
@@ -2027,7 +2080,6 @@ impl Net {
 
     update_cached_clients(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       texture_path,
@@ -2035,7 +2087,6 @@ impl Net {
 
     update_cached_clients(
       &mut *self.packet_orchestrator.borrow_mut(),
-      self.config.max_payload_size,
       &self.asset_manager,
       &mut self.clients,
       animation_path,
@@ -2167,7 +2218,6 @@ impl Net {
       broadcast_actor_keyframes(
         &mut *self.packet_orchestrator.borrow_mut(),
         area,
-        self.config.max_payload_size,
         id,
         animation,
       );
@@ -2183,6 +2233,7 @@ impl Net {
     if let Some(bot) = self.bots.get_mut(id) {
       if let Some(previous_area) = self.areas.get_mut(&bot.area_id) {
         previous_area.remove_bot(id);
+        forget_actor(&mut self.clients, id, self.actor_handles.get(id));
 
         broadcast_to_area(
           &mut *self.packet_orchestrator.borrow_mut(),
@@ -2205,7 +2256,6 @@ impl Net {
 
       ensure_assets(
         &mut *self.packet_orchestrator.borrow_mut(),
-        self.config.max_payload_size,
         &self.asset_manager,
         &mut self.clients,
         area.get_connected_players(),
@@ -2216,7 +2266,7 @@ impl Net {
         &mut *self.packet_orchestrator.borrow_mut(),
         area,
         Reliability::ReliableOrdered,
-        bot.create_spawn_packet(bot.x, bot.y, bot.z, warp_in),
+        bot.create_spawn_packet(self.actor_handles.get(id), bot.x, bot.y, bot.z, warp_in),
       );
     }
   }
@@ -2236,42 +2286,221 @@ impl Net {
     self.active_plugin = active_plugin;
   }
 
+  /// Current time for the server, only moves forward on ticks when the server is using a manual clock
+  pub fn get_time(&self) -> Instant {
+    self.manual_time.unwrap_or_else(Instant::now)
+  }
+
+  pub(super) fn use_manual_time(&mut self) {
+    self.manual_time = Some(Instant::now());
+  }
+
+  pub(super) fn advance_time(&mut self, delta_time: f32) {
+    if let Some(time) = &mut self.manual_time {
+      *time += std::time::Duration::from_secs_f32(delta_time);
+    }
+  }
+
   pub(super) fn tick(&mut self) {
-    self.broadcast_bot_positions();
+    self.update_interest();
+    self.broadcast_actor_movement();
     self.broadcast_map_changes();
   }
 
-  fn broadcast_bot_positions(&mut self) {
-    use std::time::Instant;
+  /// Sends each player a batch of movement for actors in their area, skipping actors outside of the interest radius.
+  /// Actors without a handle fall back to ActorMove
+  fn broadcast_actor_movement(&mut self) {
+    let now = self.get_time();
+    let max_idle_packet_duration = self.config.max_idle_packet_duration;
+    let mut packet_orchestrator = self.packet_orchestrator.borrow_mut();
+
+    for area in self.areas.values() {
+      // players that haven't been sent to anyone yet are skipped
+      let players = area
+        .get_connected_players()
+        .iter()
+        .filter_map(|id| self.clients.get(id))
+        .filter(|client| client.ready)
+        .map(|client| &client.actor);
+
+      let bots = area
+        .get_connected_bots()
+        .iter()
+        .filter_map(|id| self.bots.get(id));
+
+      // stop sending idle actors after a while
+      let moving_actors: Vec<(&Actor, u16)> = players
+        .chain(bots)
+        .filter(|actor| (now - actor.last_movement_time).as_secs_f32() <= max_idle_packet_duration)
+        .map(|actor| (actor, self.actor_handles.get(&actor.id)))
+        .collect();
+
+      if moving_actors.is_empty() {
+        continue;
+      }
 
-    let now = Instant::now();
+      let mut recipient_batches = Vec::new();
 
-    for bot in self.bots.values() {
-      let time_since_last_movement = now - bot.last_movement_time;
+      for player_id in area.get_connected_players() {
+        let client = match self.clients.get(player_id) {
+          Some(client) => client,
+          None => continue,
+        };
 
-      if time_since_last_movement.as_secs_f32() > self.config.max_idle_packet_duration {
-        continue;
+        let visible_actors = moving_actors
+          .iter()
+          .filter(|(actor, _)| actor.id != *player_id && !client.culled_actors.contains(&actor.id));
+
+        for (actor, _) in visible_actors.clone().filter(|(_, handle)| *handle == 0) {
+          packet_orchestrator.send(
+            client.socket_address,
+            Reliability::UnreliableSequenced,
+            ServerPacket::ActorMove {
+              ticket: &actor.id,
+              x: actor.x,
+              y: actor.y,
+              z: actor.z,
+              direction: actor.direction,
+            },
+          );
+        }
+
+        let batched_actors: Vec<_> = visible_actors
+          .filter(|(_, handle)| *handle != 0)
+          .map(|(actor, handle)| (*handle, actor.x, actor.y, actor.z, actor.direction))
+          .collect();
+
+        recipient_batches.push((player_id, client.socket_address, batched_actors));
       }
 
-      let area = match self.areas.get(&bot.area_id) {
-        Some(area) => area,
-        None => continue,
-      };
+      // batchers are updated after reading every actor
+      for (player_id, socket_address, batched_actors) in recipient_batches {
+        let client = match self.clients.get_mut(player_id) {
+          Some(client) => client,
+          None => continue,
+        };
 
-      let packet = ServerPacket::ActorMove {
-        ticket: &bot.id,
-        x: bot.x,
-        y: bot.y,
-        z: bot.z,
-        direction: bot.direction,
-      };
+        let batches = client
+          .movement_batcher
+          .create_batches(batched_actors.into_iter());
 
-      broadcast_to_area(
-        &mut *self.packet_orchestrator.borrow_mut(),
-        area,
-        Reliability::UnreliableSequenced,
-        packet,
-      );
+        for (id, moves) in batches {
+          packet_orchestrator.send(
+            socket_address,
+            Reliability::UnreliableSequenced,
+            ServerPacket::ActorMoveBatch { id, moves: &moves },
+          );
+        }
+      }
+    }
+  }
+
+  /// Excludes actors outside of the area's interest radius for each player, and includes them again once they're in range.
+  /// Exclusions made by scripts are left alone
+  fn update_interest(&mut self) {
+    let mut packet_orchestrator = self.packet_orchestrator.borrow_mut();
+
+    for area in self.areas.values() {
+      let interest_radius = area.get_map().get_interest_radius();
+
+      for player_id in area.get_connected_players() {
+        let client = match self.clients.get(player_id) {
+          Some(client) if client.ready => client,
+          _ => continue,
+        };
+
+        if interest_radius.is_none() && client.culled_actors.is_empty() {
+          continue;
+        }
+
+        let players = area
+          .get_connected_players()
+          .iter()
+          .filter_map(|id| self.clients.get(id))
+          .map(|client| &client.actor);
+
+        let bots = area
+          .get_connected_bots()
+          .iter()
+          .filter_map(|id| self.bots.get(id));
+
+        // actors entering or leaving the radius, with positions for actors entering
+        let mut changes = Vec::new();
+
+        for actor in players.chain(bots) {
+          if actor.id == *player_id {
+            continue;
+          }
+
+          let in_range = match interest_radius {
+            Some(radius) => {
+              let distance = (actor.x - client.actor.x).hypot(actor.y - client.actor.y);
+              distance <= radius
+            }
+            None => true,
+          };
+
+          if in_range != client.culled_actors.contains(&actor.id) {
+            continue;
+          }
+
+          let position = in_range.then_some((actor.x, actor.y, actor.z, actor.direction));
+          changes.push((actor.id.clone(), position));
+        }
+
+        if changes.is_empty() {
+          continue;
+        }
+
+        let client = self.clients.get_mut(player_id).unwrap();
+
+        for (actor_id, position) in changes {
+          let excluded_by_script = client.excluded_actors.contains(&actor_id);
+
+          let (x, y, z, direction) = match position {
+            Some(position) => position,
+            None => {
+              if !excluded_by_script {
+                packet_orchestrator.send_by_id(
+                  player_id,
+                  Reliability::ReliableOrdered,
+                  ServerPacket::ExcludeActor {
+                    actor_id: &actor_id,
+                  },
+                );
+              }
+
+              client.culled_actors.insert(actor_id);
+              continue;
+            }
+          };
+
+          if !excluded_by_script {
+            packet_orchestrator.send_by_id(
+              player_id,
+              Reliability::ReliableOrdered,
+              ServerPacket::IncludeActor {
+                actor_id: &actor_id,
+              },
+            );
+          }
+
+          // movement was suppressed while out of range
+          packet_orchestrator.send_by_id(
+            player_id,
+            Reliability::ReliableOrdered,
+            ServerPacket::ActorMove {
+              ticket: &actor_id,
+              x,
+              y,
+              z,
+              direction,
+            },
+          );
+
+          client.culled_actors.remove(&actor_id);
+        }
+      }
     }
   }
 
@@ -2288,7 +2517,6 @@ impl Net {
         self.asset_manager.set_asset(map_path.clone(), map_asset);
         update_cached_clients(
           &mut *self.packet_orchestrator.borrow_mut(),
-          self.config.max_payload_size,
           &self.asset_manager,
           &mut self.clients,
           &map_path,
@@ -2312,86 +2540,29 @@ impl Net {
 fn broadcast_actor_keyframes(
   packet_orchestrator: &mut PacketOrchestrator,
   area: &Area,
-  max_payload_size: usize,
   id: &str,
   animation: Vec<KeyFrame>,
 ) {
-  use super::actor_property_animation::ActorProperty;
-  use crate::helpers::iterators::IteratorHelper;
-
-  // reliability + reliability id + type + u16 size + id + tail + keyframe size
-  let header_size = 1 + 8 + 2 + 2 + id.len() + 1 + 2;
-  let remaining_size = max_payload_size - header_size;
-
-  let measure = |keyframe: &KeyFrame| {
-    // duration + step count
-    let mut size = 4 + 2;
-
-    for (property, _) in &keyframe.property_steps {
-      // ease id + property id
-      size += 1 + 1;
-
-      // + value
-      match property {
-        ActorProperty::Animation(value) => size += 2 + value.len(),
-        ActorProperty::Direction(_) => size += 1,
-        _ => size += 4,
-      }
-    }
-
-    size
-  };
-
-  let chunks = animation
-    .into_iter()
-    .pack_chunks_lossy(|_| remaining_size, measure);
-
-  let mut last_chunk = None;
-
-  let mut packets = Vec::new();
-  use crate::packets::build_packet;
-
-  for chunk in chunks {
-    if let Some(chunk) = last_chunk {
-      packets.push(build_packet(ServerPacket::ActorPropertyKeyFrames {
-        ticket: id,
-        tail: false,
-        keyframes: chunk,
-      }));
-    }
-
-    last_chunk = Some(chunk)
-  }
-
-  if let Some(chunk) = last_chunk {
-    packets.push(build_packet(ServerPacket::ActorPropertyKeyFrames {
-      ticket: id,
-      tail: true,
-      keyframes: chunk,
-    }));
-  }
-
-  packet_orchestrator.broadcast_byte_packets_to_room(
+  packet_orchestrator.broadcast_to_room(
     area.get_id(),
     Reliability::ReliableOrdered,
-    &packets,
+    ServerPacket::ActorPropertyKeyFrames {
+      ticket: id,
+      tail: true,
+      keyframes: animation,
+    },
   );
 }
 
 fn update_cached_clients(
   packet_orchestrator: &mut PacketOrchestrator,
-  max_payload_size: usize,
   asset_manager: &AssetManager,
   clients: &mut HashMap<String, Client>,
   asset_path: &str,
 ) {
-  use crate::packets::build_packet;
-
   let mut dependencies = asset_manager.get_flattened_dependency_chain(asset_path);
   dependencies.pop();
 
-  let reliability = Reliability::ReliableOrdered;
-
   let mut clients_to_update: Vec<&mut Client> = clients
     .values_mut()
     .filter(|client| client.cached_assets.contains(asset_path))
@@ -2400,7 +2571,7 @@ fn update_cached_clients(
   // ensuring dependencies
   for asset_path in dependencies {
     if let Some(asset) = asset_manager.get_asset(asset_path) {
-      let mut byte_vecs = Vec::new();
+      let mut asset_stream = LazyAssetStream::new(asset_manager, asset_path, asset);
 
       for client in &mut clients_to_update {
         if client.cached_assets.contains(asset_path) {
@@ -2409,28 +2580,17 @@ fn update_cached_clients(
 
         client.cached_assets.insert(asset_path.to_string());
 
-        // lazily create stream
-        if byte_vecs.is_empty() {
-          byte_vecs = create_asset_stream(max_payload_size, asset_path, asset)
-            .into_iter()
-            .map(build_packet)
-            .collect();
-        }
-
-        packet_orchestrator.send_byte_packets(client.socket_address, reliability, &byte_vecs);
+        asset_stream.send(packet_orchestrator, client);
       }
     }
   }
 
   // updating clients who have this asset
   if let Some(asset) = asset_manager.get_asset(asset_path) {
-    let byte_vecs: Vec<Vec<u8>> = create_asset_stream(max_payload_size, asset_path, asset)
-      .into_iter()
-      .map(build_packet)
-      .collect();
+    let mut asset_stream = LazyAssetStream::new(asset_manager, asset_path, asset);
 
     for client in &mut clients_to_update {
-      packet_orchestrator.send_byte_packets(client.socket_address, reliability, &byte_vecs);
+      asset_stream.send(packet_orchestrator, client);
     }
   }
 }
@@ -2444,9 +2604,17 @@ fn broadcast_to_area(
   packet_orchestrator.broadcast_to_room(area.get_id(), reliability, packet);
 }
 
+/// Clears state kept for an actor that left the area or was removed
+fn forget_actor(clients: &mut HashMap<String, Client>, actor_id: &str, handle: u16) {
+  for client in clients.values_mut() {
+    client.excluded_actors.remove(actor_id);
+    client.culled_actors.remove(actor_id);
+    client.movement_batcher.forget(handle);
+  }
+}
+
 fn ensure_asset(
   packet_orchestrator: &mut PacketOrchestrator,
-  max_payload_size: usize,
   asset_manager: &AssetManager,
   clients: &mut HashMap<String, Client>,
   player_ids: &[String],
@@ -2471,7 +2639,7 @@ fn ensure_asset(
       continue;
     };
 
-    let mut byte_vecs = Vec::new();
+    let mut asset_stream = LazyAssetStream::new(asset_manager, asset_path, asset);
 
     for player_id in player_ids {
       let client = clients.get_mut(player_id).unwrap();
@@ -2480,32 +2648,17 @@ fn ensure_asset(
         continue;
       }
 
-      // lazily create stream
-      if byte_vecs.is_empty() {
-        use crate::packets::build_packet;
-
-        byte_vecs = create_asset_stream(max_payload_size, asset_path, asset)
-          .into_iter()
-          .map(build_packet)
-          .collect();
-      }
-
       if asset.cachable {
         client.cached_assets.insert(asset_path.to_string());
       }
 
-      packet_orchestrator.send_byte_packets(
-        client.socket_address,
-        Reliability::ReliableOrdered,
-        &byte_vecs,
-      );
+      asset_stream.send(packet_orchestrator, client);
     }
   }
 }
 
 fn ensure_assets<'a, I>(
   packet_orchestrator: &mut PacketOrchestrator,
-  max_payload_size: usize,
   asset_manager: &AssetManager,
   clients: &mut HashMap<String, Client>,
   player_ids: &[String],
@@ -2516,7 +2669,6 @@ fn ensure_assets<'a, I>(
   for asset_path in asset_paths {
     ensure_asset(
       packet_orchestrator,
-      max_payload_size,
       asset_manager,
       clients,
       player_ids,
@@ -2524,3 +2676,213 @@ fn ensure_assets<'a, I>(
     );
   }
 }
+
+/// Builds asset stream packets on first use, shared between clients using the same compression
+struct LazyAssetStream<'a> {
+  asset_manager: &'a AssetManager,
+  asset_path: &'a str,
+  asset: &'a Asset,
+  // packets + bytes saved by compression
+  streams: HashMap<Compression, (Vec<Vec<u8>>, usize)>,
+}
+
+impl<'a> LazyAssetStream<'a> {
+  fn new(
+    asset_manager: &'a AssetManager,
+    asset_path: &'a str,
+    asset: &'a Asset,
+  ) -> LazyAssetStream<'a> {
+    LazyAssetStream {
+      asset_manager,
+      asset_path,
+      asset,
+      streams: HashMap::new(),
+    }
+  }
+
+  fn send(&mut self, packet_orchestrator: &mut PacketOrchestrator, client: &Client) {
+    use crate::packets::build_packet;
+
+    let (asset_manager, asset_path, asset) = (self.asset_manager, self.asset_path, self.asset);
+
+    let (byte_vecs, bytes_saved) = self.streams.entry(client.compression).or_insert_with(|| {
+      let compressed_data = asset_manager.get_compressed_data(asset_path, client.compression);
+
+      let bytes_saved = compressed_data
+        .as_ref()
+        .map(|data| asset.len() - data.len())
+        .unwrap_or_default();
+
+      let compressed_data = compressed_data
+        .as_deref()
+        .map(|data| (client.compression, data.as_slice()));
+
+      let byte_vecs = create_asset_stream(asset_path, asset, compressed_data)
+        .into_iter()
+        .map(build_packet)
+        .collect();
+
+      (byte_vecs, bytes_saved)
+    });
+
+    client
+      .network_stats
+      .borrow_mut()
+      .record_compression_savings(*bytes_saved);
+
+    packet_orchestrator.send_byte_packets(
+      client.socket_address,
+      Reliability::ReliableOrdered,
+      byte_vecs,
+    );
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::packets::build_packet;
+
+  fn create_net() -> Net {
+    let socket = Rc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+    let config = Rc::new(ServerConfig::default());
+    let packet_orchestrator = Rc::new(RefCell::new(PacketOrchestrator::new(
+      socket.clone(),
+      config.max_payload_size,
+      usize::MAX / 2,
+      0,
+    )));
+
+    Net::new(socket, packet_orchestrator, config)
+  }
+
+  fn create_bot(x: f32, y: f32) -> Actor {
+    Actor {
+      id: String::from("bot"),
+      name: String::from("Bot"),
+      area_id: String::from("default"),
+      texture_path: String::new(),
+      animation_path: String::new(),
+      mugshot_texture_path: String::new(),
+      mugshot_animation_path: String::new(),
+      direction: Direction::Down,
+      x,
+      y,
+      z: 0.0,
+      last_movement_time: Instant::now(),
+      scale_x: 1.0,
+      scale_y: 1.0,
+      rotation: 0.0,
+      minimap_color: (0, 0, 0, 0),
+      current_animation: None,
+      solid: false,
+    }
+  }
+
+  fn packet_id(packet: ServerPacket) -> u16 {
+    let bytes = build_packet(packet);
+    u16::from_le_bytes([bytes[0], bytes[1]])
+  }
+
+  /// Drains packets sent to the socket, returning their ids
+  fn receive_packet_ids(socket: &UdpSocket) -> Vec<u16> {
+    let mut buf = [0; 2048];
+    let mut packet_ids = Vec::new();
+
+    while let Ok(size) = socket.recv(&mut buf) {
+      // reliability + reliability id + sequence channel
+      let header_size = match buf[0] {
+        0 => 1,
+        3 => 1 + 8 + 8,
+        _ => 1 + 8,
+      };
+
+      if size >= header_size + 2 {
+        packet_ids.push(u16::from_le_bytes([buf[header_size], buf[header_size + 1]]));
+      }
+    }
+
+    packet_ids
+  }
+
+  #[test]
+  fn interest_radius() {
+    let mut net = create_net();
+
+    let player_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    player_socket.set_nonblocking(true).unwrap();
+
+    let player_id = net.add_client(
+      player_socket.local_addr().unwrap(),
+      String::from("Player"),
+      String::from("identity"),
+      Compression::None,
+      Default::default(),
+    );
+    net.spawn_client(&player_id);
+    net.mark_client_ready(&player_id);
+
+    let player = net.get_player(&player_id).unwrap();
+    let (near_x, near_y) = (player.x, player.y);
+    let (far_x, far_y) = (near_x + 10.0, near_y);
+
+    let area = net.get_area_mut("default").unwrap();
+    area.get_map_mut().set_interest_radius(Some(4.0));
+
+    net.add_bot(create_bot(far_x, far_y), false);
+    receive_packet_ids(&player_socket);
+
+    let exclude_id = packet_id(ServerPacket::ExcludeActor { actor_id: "bot" });
+    let include_id = packet_id(ServerPacket::IncludeActor { actor_id: "bot" });
+    let is_culled = |net: &Net| net.clients[&player_id].culled_actors.contains("bot");
+
+    net.tick();
+    assert!(is_culled(&net));
+    assert!(receive_packet_ids(&player_socket).contains(&exclude_id));
+
+    net.move_bot("bot", near_x, near_y, 0.0);
+    net.tick();
+    assert!(!is_culled(&net));
+    assert!(receive_packet_ids(&player_socket).contains(&include_id));
+
+    // culling shouldn't override script exclusions
+    net.exclude_actor_for_player(&player_id, "bot");
+    receive_packet_ids(&player_socket);
+
+    net.move_bot("bot", far_x, far_y, 0.0);
+    net.tick();
+    assert!(is_culled(&net));
+    assert!(!receive_packet_ids(&player_socket).contains(&exclude_id));
+
+    net.move_bot("bot", near_x, near_y, 0.0);
+    net.tick();
+    assert!(!is_culled(&net));
+    assert!(
+      !receive_packet_ids(&player_socket).contains(&include_id),
+      "actors excluded by scripts should stay excluded in range"
+    );
+
+    // script inclusions wait for the actor to be in range
+    net.move_bot("bot", far_x, far_y, 0.0);
+    net.tick();
+    net.include_actor_for_player(&player_id, "bot");
+    assert!(!receive_packet_ids(&player_socket).contains(&include_id));
+
+    net.move_bot("bot", near_x, near_y, 0.0);
+    net.tick();
+    assert!(receive_packet_ids(&player_socket).contains(&include_id));
+
+    // exclusions are forgotten with the actor, a new bot reusing the id is culled as usual
+    net.exclude_actor_for_player(&player_id, "bot");
+    net.remove_bot("bot", false);
+    net.add_bot(create_bot(far_x, far_y), false);
+    receive_packet_ids(&player_socket);
+
+    net.tick();
+    assert!(is_culled(&net));
+    assert!(
+      receive_packet_ids(&player_socket).contains(&exclude_id),
+      "script exclusions shouldn't outlive the actor"
+    );
+  }
+}
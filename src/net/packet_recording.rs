@@ -0,0 +1,266 @@
+use crate::packets::bytes::*;
+use crate::packets::{parse_client_packet, VERSION_ITERATION};
+use crate::threads::ThreadMessage;
+use std::io::{BufWriter, Write};
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::time::Instant;
+
+const MAGIC: &[u8] = b"ONBREC";
+
+const TICK_EVENT: u8 = 0;
+const PACKET_EVENT: u8 = 1;
+const CONSOLE_COMMAND_EVENT: u8 = 2;
+const RESUME_TOKEN_EVENT: u8 = 3;
+
+/// An event processed by the server's event loop, times are in microseconds since the recording started
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordedEvent {
+  Tick {
+    time: u64,
+    delta_time: f32,
+  },
+  /// A datagram that was decoded into a ClientPacket, including the reliability headers
+  Packet {
+    time: u64,
+    socket_address: SocketAddr,
+    data: Vec<u8>,
+  },
+  ConsoleCommand {
+    time: u64,
+    command: String,
+  },
+  /// A resume token issued to the player at this address, tokens are random and replays reissue the recorded token
+  ResumeToken {
+    time: u64,
+    socket_address: SocketAddr,
+    token: String,
+  },
+}
+
+/// Writes events to a file as they're processed, created through `--record-packets`
+pub(super) struct PacketRecorder {
+  writer: BufWriter<std::fs::File>,
+  start_time: Instant,
+}
+
+impl PacketRecorder {
+  pub fn create(path: &std::path::Path) -> std::io::Result<PacketRecorder> {
+    let mut writer = BufWriter::new(std::fs::File::create(path)?);
+
+    let mut buf = MAGIC.to_vec();
+    write_u64(&mut buf, VERSION_ITERATION);
+    writer.write_all(&buf)?;
+
+    Ok(PacketRecorder {
+      writer,
+      start_time: Instant::now(),
+    })
+  }
+
+  pub fn record_tick(&mut self, delta_time: f32) {
+    let mut buf = self.start_event(TICK_EVENT);
+    write_f32(&mut buf, delta_time);
+    self.write(&buf);
+
+    // keeping the file mostly up to date in case of a crash
+    let _ = self.writer.flush();
+  }
+
+  pub fn record_packet(&mut self, socket_address: SocketAddr, data: &[u8]) {
+    let mut buf = self.start_event(PACKET_EVENT);
+    write_string_u8(&mut buf, &socket_address.to_string());
+    write_data(&mut buf, data);
+    self.write(&buf);
+  }
+
+  pub fn record_console_command(&mut self, command: &str) {
+    let mut buf = self.start_event(CONSOLE_COMMAND_EVENT);
+    write_string_u16(&mut buf, command);
+    self.write(&buf);
+  }
+
+  pub fn record_resume_token(&mut self, socket_address: SocketAddr, token: &str) {
+    let mut buf = self.start_event(RESUME_TOKEN_EVENT);
+    write_string_u8(&mut buf, &socket_address.to_string());
+    write_string_u8(&mut buf, token);
+    self.write(&buf);
+  }
+
+  fn start_event(&self, event_type: u8) -> Vec<u8> {
+    let mut buf = vec![event_type];
+    write_u64(&mut buf, self.start_time.elapsed().as_micros() as u64);
+    buf
+  }
+
+  fn write(&mut self, buf: &[u8]) {
+    if let Err(err) = self.writer.write_all(buf) {
+      log::error!("Failed to record packets: {}", err);
+    }
+  }
+}
+
+/// Reads every event from a recording made with `--record-packets`
+pub fn load_recording(path: &std::path::Path) -> std::io::Result<Vec<RecordedEvent>> {
+  use std::io::{Error, ErrorKind};
+
+  let data = std::fs::read(path)?;
+  let mut work_buf = data.as_slice();
+
+  let invalid_data = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+
+  if !work_buf.starts_with(MAGIC) {
+    return Err(invalid_data("Not a packet recording"));
+  }
+
+  work_buf = &work_buf[MAGIC.len()..];
+
+  let version_iteration = read_u64(&mut work_buf).ok_or_else(|| invalid_data("Missing version"))?;
+
+  if version_iteration != VERSION_ITERATION {
+    return Err(invalid_data(&format!(
+      "Recorded with protocol version {}, expected {}",
+      version_iteration, VERSION_ITERATION
+    )));
+  }
+
+  let mut events = Vec::new();
+
+  while !work_buf.is_empty() {
+    // a crash can leave the last event partially written
+    match read_event(&mut work_buf) {
+      Some(event) => events.push(event),
+      None => break,
+    }
+  }
+
+  Ok(events)
+}
+
+fn read_event(work_buf: &mut &[u8]) -> Option<RecordedEvent> {
+  let event_type = read_byte(work_buf)?;
+  let time = read_u64(work_buf)?;
+
+  let event = match event_type {
+    TICK_EVENT => RecordedEvent::Tick {
+      time,
+      delta_time: read_f32(work_buf)?,
+    },
+    PACKET_EVENT => {
+      let socket_address = read_string_u8(work_buf)?.parse().ok()?;
+      let size = read_u16(work_buf)? as usize;
+      let data = read_data(work_buf, size)?;
+
+      RecordedEvent::Packet {
+        time,
+        socket_address,
+        data,
+      }
+    }
+    CONSOLE_COMMAND_EVENT => RecordedEvent::ConsoleCommand {
+      time,
+      command: read_string_u16(work_buf)?,
+    },
+    RESUME_TOKEN_EVENT => RecordedEvent::ResumeToken {
+      time,
+      socket_address: read_string_u8(work_buf)?.parse().ok()?,
+      token: read_string_u8(work_buf)?,
+    },
+    _ => return None,
+  };
+
+  Some(event)
+}
+
+/// Feeds recorded events to a server, created by [`super::Server::create_replay_handle`].
+/// The server's clock is only stepped by recorded ticks
+pub struct ReplayHandle {
+  tx: mpsc::Sender<ThreadMessage>,
+}
+
+impl ReplayHandle {
+  pub(super) fn new(tx: mpsc::Sender<ThreadMessage>) -> ReplayHandle {
+    ReplayHandle { tx }
+  }
+
+  /// Queues an event, returns false if the server has stopped
+  pub fn replay(&self, event: RecordedEvent) -> bool {
+    let message = match event {
+      RecordedEvent::Tick { delta_time, .. } => ThreadMessage::ManualTick { delta_time },
+      RecordedEvent::Packet {
+        socket_address,
+        data,
+        ..
+      } => {
+        let (headers, packet) = match parse_client_packet(&data) {
+          Some(parsed) => parsed,
+          // recorded packets were decoded successfully, the recording is likely corrupted
+          None => return true,
+        };
+
+        ThreadMessage::ClientPacket {
+          socket_address,
+          headers,
+          packet,
+          data,
+        }
+      }
+      RecordedEvent::ConsoleCommand { command, .. } => ThreadMessage::ConsoleCommand(command),
+      RecordedEvent::ResumeToken {
+        socket_address,
+        token,
+        ..
+      } => ThreadMessage::ResumeToken {
+        socket_address,
+        token,
+      },
+    };
+
+    self.tx.send(message).is_ok()
+  }
+
+  /// Starts the same shutdown process as SIGINT/SIGTERM
+  pub fn shutdown(&self) {
+    let _ = self.tx.send(ThreadMessage::Shutdown);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let path = std::env::temp_dir().join(format!("recording_{}.bin", std::process::id()));
+    let socket_address: SocketAddr = "[::1]:8765".parse().unwrap();
+
+    let mut recorder = PacketRecorder::create(&path).unwrap();
+    recorder.record_tick(0.05);
+    recorder.record_packet(socket_address, &[0, 0, 0]);
+    recorder.record_console_command("maintenance on");
+    recorder.record_resume_token(socket_address, "0123abcd");
+    drop(recorder);
+
+    let events = load_recording(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert!(matches!(
+      events[0],
+      RecordedEvent::Tick { delta_time, .. } if delta_time == 0.05
+    ));
+    assert!(matches!(
+      &events[1],
+      RecordedEvent::Packet { socket_address: address, data, .. }
+        if *address == socket_address && data == &[0, 0, 0]
+    ));
+    assert!(matches!(
+      &events[2],
+      RecordedEvent::ConsoleCommand { command, .. } if command == "maintenance on"
+    ));
+    assert!(matches!(
+      &events[3],
+      RecordedEvent::ResumeToken { socket_address: address, token, .. }
+        if *address == socket_address && token == "0123abcd"
+    ));
+  }
+}
@@ -87,6 +87,12 @@ impl PluginInterface for PluginWrapper {
     });
   }
 
+  fn handle_player_resume(&mut self, net: &mut Net, player_id: &str) {
+    self.wrap_calls(net, |plugin_interface, net| {
+      plugin_interface.handle_player_resume(net, player_id)
+    });
+  }
+
   fn handle_player_disconnect(&mut self, net: &mut Net, player_id: &str) {
     self.wrap_calls(net, |plugin_interface, net| {
       plugin_interface.handle_player_disconnect(net, player_id)
@@ -327,4 +333,17 @@ impl PluginInterface for PluginWrapper {
       plugin_interface.handle_server_message(net, socket_address, data)
     });
   }
+
+  fn handle_server_shutdown(&mut self, net: &mut Net) {
+    self.wrap_calls(net, |plugin_interface, net| {
+      plugin_interface.handle_server_shutdown(net)
+    });
+  }
+
+  fn has_pending_jobs(&self) -> bool {
+    self
+      .plugin_interfaces
+      .iter()
+      .any(|plugin_interface| plugin_interface.has_pending_jobs())
+  }
 }
@@ -1,73 +1,264 @@
+use super::admin_console::handle_console_command;
 use super::boot::Boot;
+use super::handshake_cookies::HandshakeCookies;
+use super::manual_clock::ManualClock;
+use super::packet_recording::{PacketRecorder, ReplayHandle};
 use super::plugin_wrapper::PluginWrapper;
-use super::Net;
+use super::{Net, QueuedLogin};
 use crate::packets::{
-  build_unreliable_packet, ClientPacket, PacketOrchestrator, PacketSorter, Reliability,
-  ServerPacket,
+  build_unreliable_packet, ClientPacket, Compression, EncryptedSessions, KeyPair,
+  NetworkConditions, PacketOrchestrator, PacketSorter, Reliability, ServerPacket,
 };
 use crate::plugins::PluginInterface;
-use crate::threads::{create_clock_thread, create_listening_thread, ThreadMessage};
+use crate::threads::{
+  create_clock_thread, create_console_thread, create_listening_thread, create_signal_handler,
+  ThreadMessage,
+};
 use log::*;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::net::UdpSocket;
 use std::rc::Rc;
+use std::sync::{mpsc, Arc};
+use std::time::Instant;
 
 #[derive(Clone)]
 pub struct ServerConfig {
   pub public_ip: std::net::IpAddr,
+  pub bind_ip: std::net::IpAddr,
+  pub dual_stack: bool,
   pub port: u16,
+  pub areas_dir: std::path::PathBuf,
+  pub assets_dir: std::path::PathBuf,
+  /// created on the first ban, read at startup if it exists
+  pub ban_list_path: std::path::PathBuf,
+  /// rejects logins from identities and addresses missing from the allow list
+  pub allow_list_only: bool,
+  /// refuses new logins from identities missing from maintenance_exemptions
+  pub maintenance: bool,
+  pub maintenance_message: String,
+  pub maintenance_exemptions: Vec<String>,
+  /// reads commands from stdin
+  pub admin_console: bool,
+  /// shuts down gracefully on SIGINT/SIGTERM, installs a process wide handler
+  pub handle_signals: bool,
+  /// compresses text and data asset streams for clients that support it
+  pub compress_assets: bool,
   pub log_connections: bool,
   pub log_packets: bool,
+  /// upper bound for path MTU discovery
   pub max_payload_size: usize,
   pub resend_budget: usize,
-  pub receiving_drop_rate: f32,
+  /// bytes per second sent to each client, packets past the limit are queued by priority. 0 for unlimited
+  pub outgoing_rate_limit: usize,
+  /// packets per second received from each ip address, packets past the limit are dropped. 0 for unlimited
+  pub packet_rate_limit: u32,
+  /// seconds to ignore addresses that keep sending invalid packets or exceeding the packet rate limit
+  pub flood_block_duration: f32,
+  /// connections tracked at once, including players, queued logins, and clients that haven't logged in yet
+  pub max_connections: usize,
+  /// offers clients a key exchange in VersionInfo for encrypting their session
+  pub encryption: bool,
+  /// ignores clients that don't encrypt their session, implies encryption
+  pub require_encryption: bool,
+  /// simulated trouble for packets received from clients
+  pub incoming_conditions: NetworkConditions,
+  /// simulated trouble for packets sent to clients
+  pub outgoing_conditions: NetworkConditions,
   pub player_asset_limit: usize,
   pub avatar_dimensions_limit: u32,
   pub custom_emotes_path: Option<String>,
   pub max_idle_packet_duration: f32,
   pub max_silence_duration: f32,
+  /// seconds a player silent past max_silence_duration can reconnect with their resume token. 0 to kick immediately
+  pub resume_grace_period: f32,
   pub heartbeat_rate: f32,
+  /// ticks per second for the real time clock
+  pub tick_rate: f64,
+  /// logins past this limit wait in the login queue
+  pub max_players: Option<usize>,
+  /// logins are rejected when the queue is full
+  pub login_queue_size: usize,
+  /// seconds between queue position updates
+  pub login_queue_update_rate: f32,
+  pub shutdown_kick_reason: String,
+  pub shutdown_timeout: f32,
+  /// writes every decoded client packet, tick, console command, and issued resume token to this file for replaying
+  pub record_packets: Option<std::path::PathBuf>,
+}
+
+impl Default for ServerConfig {
+  /// Matches the command line defaults, except for the admin console and signal handling which are left to the embedding application
+  fn default() -> ServerConfig {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    ServerConfig {
+      public_ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+      bind_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+      dual_stack: false,
+      port: 8765,
+      areas_dir: std::path::PathBuf::from("./areas"),
+      assets_dir: std::path::PathBuf::from("./assets"),
+      ban_list_path: std::path::PathBuf::from("./bans.toml"),
+      allow_list_only: false,
+      maintenance: false,
+      maintenance_message: String::from("Server is under maintenance"),
+      maintenance_exemptions: Vec::new(),
+      admin_console: false,
+      handle_signals: false,
+      compress_assets: true,
+      log_connections: false,
+      log_packets: false,
+      max_payload_size: 1400,
+      resend_budget: 65536,
+      outgoing_rate_limit: 1024 * 1024,
+      packet_rate_limit: 1000,
+      flood_block_duration: 60.0,
+      max_connections: 1000,
+      encryption: true,
+      require_encryption: false,
+      incoming_conditions: NetworkConditions::default(),
+      outgoing_conditions: NetworkConditions::default(),
+      player_asset_limit: 50 * 1024,
+      avatar_dimensions_limit: 80,
+      custom_emotes_path: None,
+      max_idle_packet_duration: 1.0,
+      max_silence_duration: 5.0,
+      resume_grace_period: 30.0,
+      heartbeat_rate: 0.5,
+      tick_rate: 20.0,
+      max_players: None,
+      login_queue_size: 100,
+      login_queue_update_rate: 5.0,
+      shutdown_kick_reason: String::from("Server shutting down"),
+      shutdown_timeout: 5.0,
+      record_packets: None,
+    }
+  }
+}
+
+enum ShutdownStage {
+  // waiting for clients to acknowledge reliable packets
+  Flushing,
+  // clients are kicked, waiting for plugins to finish jobs
+  WaitingForJobs,
+}
+
+struct Shutdown {
+  stage: ShutdownStage,
+  stage_start: Instant,
 }
 
 pub struct Server {
   player_id_map: HashMap<std::net::SocketAddr, String>,
+  /// players that went silent, waiting for Resume. Keyed by player id with the time of suspension
+  suspended_players: HashMap<String, Instant>,
   packet_sorter_map: HashMap<std::net::SocketAddr, PacketSorter>,
   plugin_wrapper: PluginWrapper,
   config: Rc<ServerConfig>,
+  socket: Option<UdpSocket>,
+  tx: mpsc::Sender<ThreadMessage>,
+  rx: mpsc::Receiver<ThreadMessage>,
+  manual_clock: bool,
+  time_since_heartbeat: f32,
+  time_since_queue_update: f32,
+  shutdown: Option<Shutdown>,
+  packet_recorder: Option<PacketRecorder>,
+  handshake_cookies: HandshakeCookies,
+  /// recorded cookies were issued by a different server
+  replaying: bool,
+  /// None when encryption is disabled
+  key_pair: Option<KeyPair>,
+  encrypted_sessions: Arc<EncryptedSessions>,
 }
 
 impl Server {
   pub fn new(config: ServerConfig) -> Server {
+    let (tx, rx) = mpsc::channel();
+
+    let key_pair = if config.encryption || config.require_encryption {
+      Some(KeyPair::generate())
+    } else {
+      None
+    };
+
     Server {
       player_id_map: HashMap::new(),
+      suspended_players: HashMap::new(),
       packet_sorter_map: HashMap::new(),
       plugin_wrapper: PluginWrapper::new(),
       config: Rc::new(config),
+      socket: None,
+      tx,
+      rx,
+      manual_clock: false,
+      time_since_heartbeat: 0.0,
+      time_since_queue_update: 0.0,
+      shutdown: None,
+      packet_recorder: None,
+      handshake_cookies: HandshakeCookies::new(),
+      replaying: false,
+      key_pair,
+      encrypted_sessions: Arc::new(EncryptedSessions::default()),
     }
   }
 
+  /// Replaces the real time clock, the server will only tick when [`ManualClock::step`] is called
+  pub fn create_manual_clock(&mut self) -> ManualClock {
+    self.manual_clock = true;
+
+    ManualClock::new(self.tx.clone())
+  }
+
+  /// Replaces the real time clock like [`Server::create_manual_clock`], for feeding recorded events to the server
+  pub fn create_replay_handle(&mut self) -> ReplayHandle {
+    self.manual_clock = true;
+    self.replaying = true;
+
+    ReplayHandle::new(self.tx.clone())
+  }
+
+  pub(super) fn set_socket(&mut self, socket: UdpSocket) {
+    self.socket = Some(socket);
+  }
+
   pub fn add_plugin_interface(&mut self, plugin_interface: Box<dyn PluginInterface>) {
     self.plugin_wrapper.add_plugin_interface(plugin_interface);
   }
 
   pub fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-    use std::sync::mpsc;
-    use std::time::Instant;
-
-    let addr = format!("0.0.0.0:{}", self.config.port);
-    let socket = UdpSocket::bind(addr)?;
+    let socket = match self.socket.take() {
+      Some(socket) => socket,
+      None => self.bind_socket()?,
+    };
 
     socket.take_error()?;
 
-    info!("Server listening on: {}", self.config.port);
+    if let Some(path) = &self.config.record_packets {
+      self.packet_recorder = Some(PacketRecorder::create(path)?);
+      info!("Recording packets to {}", path.display());
+    }
+
+    info!("Server listening on: {}", socket.local_addr()?);
 
     let socket = Rc::new(socket);
     let packet_orchestrator = Rc::new(RefCell::new(PacketOrchestrator::new(
       socket.clone(),
+      self.config.max_payload_size,
       self.config.resend_budget,
+      self.config.outgoing_rate_limit,
     )));
 
+    packet_orchestrator
+      .borrow_mut()
+      .set_encrypted_sessions(self.encrypted_sessions.clone());
+
+    if !self.config.outgoing_conditions.is_ideal() {
+      packet_orchestrator
+        .borrow_mut()
+        .simulate_outgoing_conditions(self.config.outgoing_conditions)?;
+    }
+
     let mut net = Net::new(
       socket.clone(),
       packet_orchestrator.clone(),
@@ -76,79 +267,137 @@ impl Server {
 
     self.plugin_wrapper.init(&mut net);
 
-    let (tx, rx) = mpsc::channel();
-    create_clock_thread(tx.clone());
-    create_listening_thread(tx, socket.try_clone()?, (*self.config).clone());
+    if self.manual_clock {
+      net.use_manual_time();
+    }
+
+    let tx = self.tx.clone();
+
+    if self.config.handle_signals {
+      create_signal_handler(tx.clone());
+    }
+
+    if !self.manual_clock {
+      create_clock_thread(tx.clone(), self.config.tick_rate);
+    }
+
+    if self.config.admin_console {
+      create_console_thread(tx.clone());
+    }
+
+    create_listening_thread(
+      tx.clone(),
+      socket.try_clone()?,
+      (*self.config).clone(),
+      net.get_dropped_traffic().clone(),
+      self.encrypted_sessions.clone(),
+    );
 
     info!("Server started");
 
     let mut time = Instant::now();
-    let mut last_heartbeat = Instant::now();
 
     loop {
-      match rx.recv()? {
+      match self.rx.recv()? {
         ThreadMessage::Tick(started) => {
           started();
 
           let elapsed_time = time.elapsed();
           time = Instant::now();
 
-          self
-            .plugin_wrapper
-            .tick(&mut net, elapsed_time.as_secs_f32());
-
-          // kick silent clients
-          let mut kick_list = Vec::new();
-
-          for (socket_address, packet_sorter) in &mut self.packet_sorter_map {
-            let last_message = packet_sorter.get_last_message_time();
-
-            if last_message.elapsed().as_secs_f32() > self.config.max_silence_duration {
-              kick_list.push(Boot {
-                socket_address: *socket_address,
-                reason: String::from("packet silence"),
-                warp_out: true,
-              });
-            }
+          if self.tick(
+            &mut net,
+            &packet_orchestrator,
+            &socket,
+            elapsed_time.as_secs_f32(),
+          ) {
+            break;
           }
+        }
+        ThreadMessage::ManualTick { delta_time } => {
+          time = Instant::now();
 
-          kick_list.extend(net.take_kick_list());
-
-          // actually kick clients
-          for boot in kick_list {
-            self.disconnect_client(&mut net, &boot.socket_address, &boot.reason, boot.warp_out);
-
-            // send reason
-            let buf = build_unreliable_packet(ServerPacket::Kick {
-              reason: &boot.reason,
-            });
-
-            let _ = socket.send_to(&buf, boot.socket_address);
-          }
-
-          packet_orchestrator.borrow_mut().resend_backed_up_packets();
-
-          net.tick();
-
-          if last_heartbeat.elapsed().as_secs_f32() >= self.config.heartbeat_rate {
-            packet_orchestrator
-              .borrow_mut()
-              .broadcast(Reliability::Reliable, ServerPacket::Heartbeat);
-
-            last_heartbeat = time;
+          if self.tick(&mut net, &packet_orchestrator, &socket, delta_time) {
+            break;
           }
         }
         ThreadMessage::ClientPacket {
           socket_address,
           headers,
           packet,
+          data,
         } => {
+          if let Some(packet_recorder) = &mut self.packet_recorder {
+            packet_recorder.record_packet(socket_address, &data);
+          }
+
           let is_reliable = headers.reliability.is_reliable();
 
+          if self.shutdown.is_some() && !self.packet_sorter_map.contains_key(&socket_address) {
+            // not accepting new connections
+            continue;
+          }
+
           if headers.id == 0 && is_reliable && !self.packet_sorter_map.contains_key(&socket_address)
           {
+            if self.packet_sorter_map.len() >= self.config.max_connections {
+              net.get_dropped_traffic().record(data.len());
+              continue;
+            }
+
+            // no state is stored until the client proves it can receive packets at this address
+            let (verified, server_random, key_exchange) = match &packet {
+              ClientPacket::Connect {
+                cookie,
+                server_random,
+                key_exchange,
+              } => (
+                self.replaying
+                  || self
+                    .handshake_cookies
+                    .redeem(socket_address, *cookie, server_random),
+                *server_random,
+                *key_exchange,
+              ),
+              _ => (false, [0; 16], None),
+            };
+
+            if !verified {
+              net.get_dropped_traffic().record(data.len());
+              continue;
+            }
+
+            let encrypted_session = match (&self.key_pair, &key_exchange) {
+              (Some(key_pair), Some(key_exchange)) => key_pair
+                .create_session(key_exchange, &server_random)
+                .map(Arc::new),
+              _ => None,
+            };
+
+            // clients that sent a key can't read unencrypted replies
+            if encrypted_session.is_none()
+              && (self.config.require_encryption || key_exchange.is_some())
+            {
+              net.get_dropped_traffic().record(data.len());
+              continue;
+            }
+
             // received the first reliable packet, store a new connection
-            let packet_sorter = PacketSorter::new(socket_address);
+            // asset streams are the largest packets clients send
+            let max_packet_size = self.config.player_asset_limit + self.config.max_payload_size;
+            let mut packet_sorter = PacketSorter::new(socket_address, max_packet_size);
+
+            if let Some(network_simulator) = packet_orchestrator.borrow().get_network_simulator() {
+              packet_sorter.set_network_simulator(network_simulator);
+            }
+
+            if let Some(encrypted_session) = encrypted_session {
+              packet_sorter.set_encrypted_session(encrypted_session.clone());
+              self
+                .encrypted_sessions
+                .insert(socket_address, encrypted_session);
+            }
+
             self.packet_sorter_map.insert(socket_address, packet_sorter);
 
             if self.config.log_connections {
@@ -157,7 +406,7 @@ impl Server {
           }
 
           if let Some(packet_sorter) = self.packet_sorter_map.get_mut(&socket_address) {
-            let packets = packet_sorter.sort_packet(&socket, headers, packet);
+            let packets = packet_sorter.sort_packet(&socket, net.get_time(), headers, packet);
 
             for packet in packets {
               self.handle_packet(
@@ -179,8 +428,248 @@ impl Server {
             );
           }
         }
+        ThreadMessage::ConsoleCommand(command) => {
+          if let Some(packet_recorder) = &mut self.packet_recorder {
+            packet_recorder.record_console_command(&command);
+          }
+
+          handle_console_command(&mut net, &command);
+        }
+        ThreadMessage::ResumeToken {
+          socket_address,
+          token,
+        } => {
+          let player_id = match self.player_id_map.get(&socket_address) {
+            Some(player_id) => player_id.clone(),
+            None => continue,
+          };
+
+          // recorded Resume packets carry the token issued by the recorded server
+          if let Some(client) = net.get_client_mut(&player_id) {
+            client.resume_token = token;
+          }
+
+          self.record_resume_token(&net, socket_address, &player_id);
+        }
+        ThreadMessage::Shutdown => {
+          if self.shutdown.is_some() {
+            continue;
+          }
+
+          info!("Shutting down...");
+
+          self.plugin_wrapper.handle_server_shutdown(&mut net);
+
+          self.shutdown = Some(Shutdown {
+            stage: ShutdownStage::Flushing,
+            stage_start: Instant::now(),
+          });
+
+          if self.manual_clock {
+            // shutdown stages are bounded by real time, the manual clock may never be stepped again
+            create_clock_thread(tx.clone(), self.config.tick_rate);
+          }
+        }
+      }
+    }
+
+    info!("Server stopped");
+
+    Ok(())
+  }
+
+  /// Returns true when the server is ready to exit
+  fn tick(
+    &mut self,
+    net: &mut Net,
+    packet_orchestrator: &RefCell<PacketOrchestrator>,
+    socket: &UdpSocket,
+    delta_time: f32,
+  ) -> bool {
+    if let Some(packet_recorder) = &mut self.packet_recorder {
+      packet_recorder.record_tick(delta_time);
+    }
+
+    net.advance_time(delta_time);
+
+    self.plugin_wrapper.tick(net, delta_time);
+
+    // kick silent clients, players in an area are suspended if they can resume
+    let mut kick_list = Vec::new();
+    let mut suspend_list = Vec::new();
+    let now = net.get_time();
+
+    for (socket_address, packet_sorter) in &mut self.packet_sorter_map {
+      let last_message = packet_sorter.get_last_message_time();
+
+      if now.duration_since(*last_message).as_secs_f32() <= self.config.max_silence_duration {
+        continue;
+      }
+
+      let resumable = self.config.resume_grace_period > 0.0
+        && self
+          .player_id_map
+          .get(socket_address)
+          .and_then(|player_id| net.get_client(player_id))
+          .map(|client| client.ready)
+          .unwrap_or_default();
+
+      if resumable {
+        suspend_list.push(*socket_address);
+      } else {
+        kick_list.push(Boot {
+          socket_address: *socket_address,
+          reason: String::from("packet silence"),
+          warp_out: true,
+        });
+      }
+    }
+
+    for socket_address in suspend_list {
+      self.suspend_client(net, socket_address);
+    }
+
+    let expired_players: Vec<String> = self
+      .suspended_players
+      .iter()
+      .filter(|(_, suspend_time)| {
+        now.duration_since(**suspend_time).as_secs_f32() > self.config.resume_grace_period
+      })
+      .map(|(player_id, _)| player_id.clone())
+      .collect();
+
+    for player_id in expired_players {
+      self.remove_suspended_player(net, &player_id, "packet silence");
+    }
+
+    kick_list.extend(net.take_kick_list());
+
+    self.kick_clients(net, socket, kick_list);
+
+    if self.shutdown.is_none() {
+      self.accept_queued_logins(net, packet_orchestrator, socket);
+    }
+
+    self.time_since_queue_update += delta_time;
+
+    if self.time_since_queue_update >= self.config.login_queue_update_rate {
+      self.send_queue_positions(net, socket);
+    }
+
+    packet_orchestrator.borrow_mut().resend_backed_up_packets();
+    packet_orchestrator.borrow_mut().send_queued_packets();
+    packet_orchestrator.borrow_mut().probe_path_mtus();
+
+    net.tick();
+
+    self.time_since_heartbeat += delta_time;
+
+    if self.time_since_heartbeat >= self.config.heartbeat_rate {
+      packet_orchestrator
+        .borrow_mut()
+        .broadcast(Reliability::Reliable, ServerPacket::Heartbeat);
+
+      self.time_since_heartbeat = 0.0;
+    }
+
+    self.shutdown.is_some() && self.update_shutdown(net, packet_orchestrator, socket)
+  }
+
+  /// Returns true when the server is ready to exit
+  fn update_shutdown(
+    &mut self,
+    net: &mut Net,
+    packet_orchestrator: &RefCell<PacketOrchestrator>,
+    socket: &UdpSocket,
+  ) -> bool {
+    let shutdown = self.shutdown.as_mut().unwrap();
+    let timed_out = shutdown.stage_start.elapsed().as_secs_f32() > self.config.shutdown_timeout;
+
+    match shutdown.stage {
+      ShutdownStage::Flushing => {
+        if packet_orchestrator.borrow().has_backed_up_packets() && !timed_out {
+          return false;
+        }
+
+        if timed_out {
+          warn!("Timed out waiting for clients to receive reliable packets");
+        }
+
+        shutdown.stage = ShutdownStage::WaitingForJobs;
+        shutdown.stage_start = Instant::now();
+
+        // includes connections that haven't logged in yet
+        let kick_list = self
+          .packet_sorter_map
+          .keys()
+          .map(|socket_address| Boot {
+            socket_address: *socket_address,
+            reason: self.config.shutdown_kick_reason.clone(),
+            warp_out: true,
+          })
+          .collect();
+
+        self.kick_clients(net, socket, kick_list);
+
+        let suspended_players: Vec<String> = self.suspended_players.keys().cloned().collect();
+
+        for player_id in suspended_players {
+          let reason = self.config.shutdown_kick_reason.clone();
+          self.remove_suspended_player(net, &player_id, &reason);
+        }
+
+        false
       }
+      ShutdownStage::WaitingForJobs => {
+        let pending_jobs = self.plugin_wrapper.has_pending_jobs() || net.get_ban_list().is_saving();
+
+        if pending_jobs && !timed_out {
+          return false;
+        }
+
+        if timed_out {
+          warn!("Timed out waiting for plugin jobs and the ban list to be saved");
+        }
+
+        true
+      }
+    }
+  }
+
+  fn kick_clients(&mut self, net: &mut Net, socket: &UdpSocket, kick_list: Vec<Boot>) {
+    for boot in kick_list {
+      // send reason, sealed before disconnecting forgets the session
+      let buf = self.encrypted_sessions.seal(
+        boot.socket_address,
+        build_unreliable_packet(ServerPacket::Kick {
+          reason: &boot.reason,
+        }),
+      );
+
+      self.disconnect_client(net, &boot.socket_address, &boot.reason, boot.warp_out);
+
+      let _ = socket.send_to(&buf, boot.socket_address);
+    }
+  }
+
+  fn bind_socket(&self) -> std::io::Result<UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let address = std::net::SocketAddr::new(self.config.bind_ip, self.config.port);
+    let socket = Socket::new(
+      Domain::for_address(address),
+      Type::DGRAM,
+      Some(Protocol::UDP),
+    )?;
+
+    if address.is_ipv6() {
+      // explicitly set, the os default varies
+      socket.set_only_v6(!self.config.dual_stack)?;
     }
+
+    socket.bind(&address.into())?;
+
+    Ok(socket.into())
   }
 
   fn handle_packet(
@@ -198,10 +687,7 @@ impl Server {
             debug!("Received bad VersionRequest packet from {}", socket_address);
           }
 
-          let buf = build_unreliable_packet(ServerPacket::VersionInfo {
-            max_payload_size: self.config.max_payload_size,
-          });
-          let _ = socket.send_to(&buf, socket_address);
+          self.send_version_info(net, socket, socket_address);
         }
         ClientPacket::Heartbeat => {
           if self.config.log_packets {
@@ -250,7 +736,7 @@ impl Server {
           };
 
           if let Some(asset_buffer) = asset_buffer {
-            if asset_buffer.len() < self.config.player_asset_limit {
+            if asset_buffer.len() + data.len() <= self.config.player_asset_limit {
               asset_buffer.extend(data);
             } else {
               let reason = format!(
@@ -274,6 +760,18 @@ impl Server {
             .borrow_mut()
             .acknowledged(socket_address, reliability, id);
         }
+        ClientPacket::PathMtuProbeResponse { size } => {
+          if self.config.log_packets {
+            debug!(
+              "Received PathMtuProbeResponse for {} bytes from {}",
+              size, socket_address
+            );
+          }
+
+          packet_orchestrator
+            .borrow_mut()
+            .path_mtu_probe_received(socket_address, size);
+        }
         ClientPacket::Authorize {
           origin_address: _,
           port: _,
@@ -284,15 +782,16 @@ impl Server {
             debug!("Received bad Authorize packet from {}", socket_address);
           }
         }
-        ClientPacket::Login {
-          username: _,
-          identity: _,
-          data: _,
-        } => {
+        ClientPacket::Login { .. } => {
           if self.config.log_packets {
             debug!("Received bad Login packet from {}", socket_address);
           }
         }
+        ClientPacket::Resume { .. } => {
+          if self.config.log_packets {
+            debug!("Received bad Resume packet from {}", socket_address);
+          }
+        }
         ClientPacket::RequestJoin => {
           if self.config.log_packets {
             debug!("Received RequestJoin packet from {}", socket_address);
@@ -344,6 +843,13 @@ impl Server {
             net.update_player_position(player_id, x, y, z, direction);
           }
         }
+        ClientPacket::ActorMoveBatchAck { id } => {
+          if self.config.log_packets {
+            debug!("Received ActorMoveBatchAck packet from {}", socket_address);
+          }
+
+          net.acknowledge_movement_batch(player_id, id);
+        }
         ClientPacket::Ready { time } => {
           if self.config.log_packets {
             debug!("Received Ready packet from {}", socket_address);
@@ -358,6 +864,8 @@ impl Server {
 
           if client.transferring {
             self.plugin_wrapper.handle_player_transfer(net, player_id);
+          } else if client.resuming {
+            self.plugin_wrapper.handle_player_resume(net, player_id);
           } else {
             self.plugin_wrapper.handle_player_join(net, player_id);
           }
@@ -539,7 +1047,21 @@ impl Server {
             .plugin_wrapper
             .handle_server_message(net, socket_address, &data);
         }
+        ClientPacket::Fragment { .. } => {
+          // fragments are reassembled by the PacketSorter, this should never happen
+          if self.config.log_packets {
+            debug!("Received unsorted Fragment packet from {}", socket_address);
+          }
+        }
+        ClientPacket::Connect { .. } => {
+          // verified before the PacketSorter was created
+          if self.config.log_packets {
+            debug!("Received Connect packet from {}", socket_address);
+          }
+        }
       }
+    } else if net.get_login_queue().contains(&socket_address) {
+      self.handle_queued_packet(net, socket, socket_address, client_packet);
     } else {
       match client_packet {
         ClientPacket::VersionRequest => {
@@ -547,10 +1069,7 @@ impl Server {
             debug!("Received VersionRequest packet from {}", socket_address);
           }
 
-          let buf = build_unreliable_packet(ServerPacket::VersionInfo {
-            max_payload_size: self.config.max_payload_size,
-          });
-          let _ = socket.send_to(&buf, socket_address);
+          self.send_version_info(net, socket, socket_address);
         }
         ClientPacket::Authorize {
           origin_address,
@@ -566,18 +1085,67 @@ impl Server {
           username,
           identity,
           data,
+          supported_compression,
         } => {
           if self.config.log_packets {
             debug!("Received Login packet from {}", socket_address);
           }
 
-          let player_id = net.add_client(socket_address, username, identity);
+          if let Some(reason) = net
+            .get_ban_list()
+            .check_login(&identity, socket_address.ip())
+          {
+            if self.config.log_connections {
+              debug!("Rejected login from {}: {}", socket_address, reason);
+            }
+
+            let boot = Boot {
+              socket_address,
+              reason,
+              warp_out: false,
+            };
 
-          self.player_id_map.insert(socket_address, player_id.clone());
+            self.kick_clients(net, socket, vec![boot]);
+            return;
+          }
 
-          self
-            .plugin_wrapper
-            .handle_player_request(net, &player_id, &data);
+          if let Some(message) = net.get_maintenance_message() {
+            if !net.is_maintenance_exempt(&identity) {
+              if self.config.log_connections {
+                debug!("Refused login from {} during maintenance", socket_address);
+              }
+
+              let boot = Boot {
+                socket_address,
+                reason: message.to_string(),
+                warp_out: false,
+              };
+
+              self.kick_clients(net, socket, vec![boot]);
+              return;
+            }
+          }
+
+          let compression =
+            Compression::negotiate(self.get_supported_compression(), supported_compression);
+
+          let queued_login =
+            QueuedLogin::new(socket_address, username, identity, data, compression);
+
+          let queue_empty = net.get_login_queue().is_empty();
+
+          if queue_empty && self.has_space_for_player(net) {
+            self.accept_login(net, queued_login);
+          } else {
+            self.queue_login(net, socket, queued_login);
+          }
+        }
+        ClientPacket::Resume { token } => {
+          if self.config.log_packets {
+            debug!("Received Resume packet from {}", socket_address);
+          }
+
+          self.resume_client(net, socket, socket_address, &token);
         }
         ClientPacket::ServerMessage { data } => {
           self
@@ -595,6 +1163,338 @@ impl Server {
     }
   }
 
+  /// Always unencrypted, VersionRequest is resent unencrypted by clients that haven't received VersionInfo
+  fn send_version_info(&self, net: &Net, socket: &UdpSocket, socket_address: std::net::SocketAddr) {
+    let (cookie, server_random) = self.handshake_cookies.issue(socket_address);
+
+    let buf = build_unreliable_packet(ServerPacket::VersionInfo {
+      max_payload_size: self.config.max_payload_size,
+      maintenance_message: net.get_maintenance_message(),
+      supported_compression: self.get_supported_compression(),
+      cookie,
+      server_random,
+      public_key: self.key_pair.as_ref().map(KeyPair::public_key),
+      encryption_required: self.config.require_encryption,
+    });
+
+    let _ = socket.send_to(&buf, socket_address);
+  }
+
+  fn get_supported_compression(&self) -> u8 {
+    if self.config.compress_assets {
+      Compression::SUPPORTED_FLAGS
+    } else {
+      0
+    }
+  }
+
+  fn has_space_for_player(&self, net: &Net) -> bool {
+    match self.config.max_players {
+      Some(max_players) => net.get_player_count() < max_players,
+      None => true,
+    }
+  }
+
+  fn accept_login(&mut self, net: &mut Net, queued_login: QueuedLogin) {
+    let QueuedLogin {
+      socket_address,
+      username,
+      identity,
+      data,
+      compression,
+      ..
+    } = queued_login;
+
+    let network_stats = self
+      .packet_sorter_map
+      .get(&socket_address)
+      .map(|packet_sorter| packet_sorter.get_network_stats())
+      .unwrap_or_default();
+
+    let player_id = net.add_client(
+      socket_address,
+      username,
+      identity,
+      compression,
+      network_stats,
+    );
+
+    self.player_id_map.insert(socket_address, player_id.clone());
+    self.record_resume_token(net, socket_address, &player_id);
+
+    self
+      .plugin_wrapper
+      .handle_player_request(net, &player_id, &data);
+  }
+
+  fn record_resume_token(
+    &mut self,
+    net: &Net,
+    socket_address: std::net::SocketAddr,
+    player_id: &str,
+  ) {
+    if let Some(packet_recorder) = &mut self.packet_recorder {
+      if let Some(client) = net.get_client(player_id) {
+        packet_recorder.record_resume_token(socket_address, &client.resume_token);
+      }
+    }
+  }
+
+  /// Forgets the connection for a silent player, keeping the player for resume_client
+  fn suspend_client(&mut self, net: &mut Net, socket_address: std::net::SocketAddr) {
+    let player_id = match self.player_id_map.remove(&socket_address) {
+      Some(player_id) => player_id,
+      None => return,
+    };
+
+    self.packet_sorter_map.remove(&socket_address);
+    self.encrypted_sessions.remove(socket_address);
+
+    net.suspend_player(&player_id);
+
+    if self.config.log_connections {
+      debug!("{} suspended for packet silence", player_id);
+    }
+
+    self.suspended_players.insert(player_id, net.get_time());
+  }
+
+  fn resume_client(
+    &mut self,
+    net: &mut Net,
+    socket: &UdpSocket,
+    socket_address: std::net::SocketAddr,
+    token: &str,
+  ) {
+    let player_id = self
+      .suspended_players
+      .keys()
+      .find(|player_id| {
+        net
+          .get_client(player_id)
+          .map(|client| client.resume_token == token)
+          .unwrap_or_default()
+      })
+      .cloned();
+
+    let player_id = match player_id {
+      Some(player_id) => player_id,
+      None => {
+        // the client can fall back to Login
+        let boot = Boot {
+          socket_address,
+          reason: String::from("Session expired"),
+          warp_out: false,
+        };
+
+        self.kick_clients(net, socket, vec![boot]);
+        return;
+      }
+    };
+
+    // bans may have been added while the player was away
+    let identity = &net.get_player_data(&player_id).unwrap().identity;
+
+    if let Some(reason) = net
+      .get_ban_list()
+      .check_login(identity, socket_address.ip())
+    {
+      if self.config.log_connections {
+        debug!("Rejected resume from {}: {}", socket_address, reason);
+      }
+
+      self.remove_suspended_player(net, &player_id, &reason);
+
+      let boot = Boot {
+        socket_address,
+        reason,
+        warp_out: false,
+      };
+
+      self.kick_clients(net, socket, vec![boot]);
+      return;
+    }
+
+    if let Some(message) = net.get_maintenance_message() {
+      if !net.is_maintenance_exempt(identity) {
+        if self.config.log_connections {
+          debug!("Refused resume from {} during maintenance", socket_address);
+        }
+
+        let reason = message.to_string();
+
+        self.remove_suspended_player(net, &player_id, &reason);
+
+        let boot = Boot {
+          socket_address,
+          reason,
+          warp_out: false,
+        };
+
+        self.kick_clients(net, socket, vec![boot]);
+        return;
+      }
+    }
+
+    self.suspended_players.remove(&player_id);
+
+    let network_stats = self
+      .packet_sorter_map
+      .get(&socket_address)
+      .map(|packet_sorter| packet_sorter.get_network_stats())
+      .unwrap_or_default();
+
+    net.resume_player(&player_id, socket_address, network_stats);
+    self.player_id_map.insert(socket_address, player_id.clone());
+    self.record_resume_token(net, socket_address, &player_id);
+
+    // player_resume fires once the client is ready
+    net.spawn_client(&player_id);
+    net.connect_client(&player_id);
+
+    if self.config.log_connections {
+      debug!("{} resumed from {}", player_id, socket_address);
+    }
+  }
+
+  fn remove_suspended_player(&mut self, net: &mut Net, player_id: &str, reason: &str) {
+    if self.suspended_players.remove(player_id).is_none() {
+      return;
+    }
+
+    self.plugin_wrapper.handle_player_disconnect(net, player_id);
+
+    // already despawned
+    net.remove_player(player_id, false);
+
+    if self.config.log_connections {
+      debug!("{} disconnected for {}", player_id, reason);
+    }
+  }
+
+  fn queue_login(&mut self, net: &mut Net, socket: &UdpSocket, queued_login: QueuedLogin) {
+    let socket_address = queued_login.socket_address;
+    let login_queue = net.get_login_queue_mut();
+
+    // identities with priority can exceed the queue size
+    if login_queue.len() >= self.config.login_queue_size
+      && !login_queue.has_priority(&queued_login.identity)
+    {
+      let reason = match self.config.max_players {
+        Some(max_players) => format!("Server is full ({} players)", max_players),
+        None => String::from("Server is full"),
+      };
+
+      let boot = Boot {
+        socket_address,
+        reason,
+        warp_out: false,
+      };
+
+      self.kick_clients(net, socket, vec![boot]);
+      return;
+    }
+
+    login_queue.push(queued_login);
+
+    if self.config.log_connections {
+      debug!(
+        "{} queued for login ({} in queue)",
+        socket_address,
+        login_queue.len()
+      );
+    }
+
+    self.send_queue_positions(net, socket);
+  }
+
+  fn handle_queued_packet(
+    &mut self,
+    net: &mut Net,
+    socket: &UdpSocket,
+    socket_address: std::net::SocketAddr,
+    client_packet: ClientPacket,
+  ) {
+    match client_packet {
+      ClientPacket::VersionRequest => {
+        self.send_version_info(net, socket, socket_address);
+      }
+      ClientPacket::Heartbeat | ClientPacket::Login { .. } | ClientPacket::Resume { .. } => {}
+      ClientPacket::Logout => {
+        let boot = Boot {
+          socket_address,
+          reason: String::from("Logout"),
+          warp_out: false,
+        };
+
+        self.kick_clients(net, socket, vec![boot]);
+      }
+      client_packet => {
+        let queued = net.get_login_queue_mut().get_mut(&socket_address).unwrap();
+
+        if !queued.buffer_packet(client_packet) {
+          let boot = Boot {
+            socket_address,
+            reason: String::from("Too many packets sent while queued"),
+            warp_out: false,
+          };
+
+          self.kick_clients(net, socket, vec![boot]);
+        }
+      }
+    }
+  }
+
+  fn accept_queued_logins(
+    &mut self,
+    net: &mut Net,
+    packet_orchestrator: &RefCell<PacketOrchestrator>,
+    socket: &UdpSocket,
+  ) {
+    let mut accepted = false;
+
+    while self.has_space_for_player(net) {
+      let mut queued = match net.get_login_queue_mut().pop() {
+        Some(queued) => queued,
+        None => break,
+      };
+
+      let socket_address = queued.socket_address;
+      let buffered_packets = std::mem::take(&mut queued.buffered_packets);
+
+      self.accept_login(net, queued);
+
+      for client_packet in buffered_packets {
+        self.handle_packet(
+          net,
+          packet_orchestrator,
+          socket,
+          socket_address,
+          client_packet,
+        );
+      }
+
+      accepted = true;
+    }
+
+    if accepted {
+      self.send_queue_positions(net, socket);
+    }
+  }
+
+  fn send_queue_positions(&mut self, net: &Net, socket: &UdpSocket) {
+    self.time_since_queue_update = 0.0;
+
+    for (position, queued) in net.get_login_queue().iter().enumerate() {
+      let buf = build_unreliable_packet(ServerPacket::LoginQueue {
+        position: position + 1,
+      });
+      let buf = self.encrypted_sessions.seal(queued.socket_address, buf);
+
+      let _ = socket.send_to(&buf, queued.socket_address);
+    }
+  }
+
   fn disconnect_client(
     &mut self,
     net: &mut Net,
@@ -612,9 +1512,27 @@ impl Server {
       if self.config.log_connections {
         debug!("{} disconnected for {}", player_id, reason);
       }
+    } else if !self.packet_sorter_map.contains_key(socket_address) {
+      // kicked by a plugin while suspended, the address isn't reused by a live connection
+      let suspended_player = self
+        .suspended_players
+        .keys()
+        .find(|player_id| {
+          net
+            .get_client(player_id)
+            .map(|client| client.socket_address == *socket_address)
+            .unwrap_or_default()
+        })
+        .cloned();
+
+      if let Some(player_id) = suspended_player {
+        self.remove_suspended_player(net, &player_id, reason);
+      }
     }
 
     self.packet_sorter_map.remove(socket_address);
+    self.encrypted_sessions.remove(*socket_address);
+    net.get_login_queue_mut().remove(socket_address);
 
     if self.config.log_connections {
       debug!("{} disconnected for {}", socket_address, reason);
@@ -0,0 +1,55 @@
+use super::{Server, ServerConfig};
+use crate::plugins::PluginInterface;
+use std::net::UdpSocket;
+
+/// Creates a [`Server`], plugins are called in the order they're added
+///
+/// ```no_run
+/// use net_battle_server::{LuaPluginInterface, ServerBuilder, ServerConfig};
+///
+/// let mut server = ServerBuilder::new(ServerConfig::default())
+///   .plugin_interface(Box::new(LuaPluginInterface::new("./scripts".into())))
+///   .build();
+///
+/// server.start().unwrap();
+/// ```
+pub struct ServerBuilder {
+  config: ServerConfig,
+  plugin_interfaces: Vec<Box<dyn PluginInterface>>,
+  socket: Option<UdpSocket>,
+}
+
+impl ServerBuilder {
+  pub fn new(config: ServerConfig) -> ServerBuilder {
+    ServerBuilder {
+      config,
+      plugin_interfaces: Vec::new(),
+      socket: None,
+    }
+  }
+
+  pub fn plugin_interface(mut self, plugin_interface: Box<dyn PluginInterface>) -> ServerBuilder {
+    self.plugin_interfaces.push(plugin_interface);
+    self
+  }
+
+  /// Uses an already bound socket instead of binding to `config.bind_ip` and `config.port`
+  pub fn socket(mut self, socket: UdpSocket) -> ServerBuilder {
+    self.socket = Some(socket);
+    self
+  }
+
+  pub fn build(self) -> Server {
+    let mut server = Server::new(self.config);
+
+    for plugin_interface in self.plugin_interfaces {
+      server.add_plugin_interface(plugin_interface);
+    }
+
+    if let Some(socket) = self.socket {
+      server.set_socket(socket);
+    }
+
+    server
+  }
+}
@@ -7,6 +7,12 @@ pub struct WidgetTracker<T> {
   active_shop: Option<T>,
 }
 
+impl<T> Default for WidgetTracker<T> {
+  fn default() -> WidgetTracker<T> {
+    WidgetTracker::new()
+  }
+}
+
 impl<T> WidgetTracker<T> {
   pub fn new() -> WidgetTracker<T> {
     WidgetTracker {
@@ -111,6 +111,15 @@ pub fn read_data(buf: &mut &[u8], size: usize) -> Option<Vec<u8>> {
   Some(data)
 }
 
+/// Reads a fixed size array, such as a key
+pub fn read_bytes<const N: usize>(buf: &mut &[u8]) -> Option<[u8; N]> {
+  let bytes = buf.get(..N)?.try_into().ok()?;
+
+  *buf = &buf[N..];
+
+  Some(bytes)
+}
+
 // writers
 
 pub fn write_bool(buf: &mut Vec<u8>, data: bool) {
@@ -2,7 +2,7 @@
 
 use super::bytes::*;
 use super::management::{get_reliability, Reliability};
-use super::PacketHeaders;
+use super::{KeyExchange, PacketHeaders};
 use crate::net::{BattleStats, Direction, EnemyBattleStats};
 
 #[derive(Debug)]
@@ -34,6 +34,8 @@ pub enum ClientPacket {
     username: String,
     identity: String,
     data: String,
+    /// Compression flags, 0 for clients that don't advertise any
+    supported_compression: u8,
   },
   Logout,
   RequestJoin,
@@ -92,6 +94,33 @@ pub enum ClientPacket {
   BattleResults {
     battle_stats: BattleStats,
   },
+  /// Part of a packet too large to send at once, handled by the PacketSorter
+  Fragment {
+    id: u64,
+    index: u32,
+    count: u32,
+    data: Vec<u8>,
+  },
+  /// The client received a PathMtuProbe of this size
+  PathMtuProbeResponse {
+    size: u16,
+  },
+  /// The client received an ActorMoveBatch
+  ActorMoveBatchAck {
+    id: u64,
+  },
+  /// First reliable packet from a client, echoing the cookie from VersionInfo
+  Connect {
+    cookie: u64,
+    /// From VersionInfo, covered by the cookie
+    server_random: [u8; 16],
+    /// Every datagram after Connect is encrypted when included
+    key_exchange: Option<KeyExchange>,
+  },
+  /// Sent in place of Login to take back a player suspended for packet silence
+  Resume {
+    token: String,
+  },
 }
 
 pub fn parse_client_packet(buf: &[u8]) -> Option<(PacketHeaders, ClientPacket)> {
@@ -99,7 +128,15 @@ pub fn parse_client_packet(buf: &[u8]) -> Option<(PacketHeaders, ClientPacket)>
   Some((parse_headers(&mut work_buf)?, parse_body(&mut work_buf)?))
 }
 
+/// Parses a packet reassembled from fragments, fragments have their headers stripped
+pub fn parse_client_packet_body(buf: &[u8]) -> Option<ClientPacket> {
+  let mut work_buf = buf;
+  parse_body(&mut work_buf)
+}
+
 fn parse_headers(work_buf: &mut &[u8]) -> Option<PacketHeaders> {
+  let size = work_buf.len();
+
   let reliability_id = read_byte(work_buf)?;
 
   let id = if reliability_id > 0 {
@@ -108,9 +145,15 @@ fn parse_headers(work_buf: &mut &[u8]) -> Option<PacketHeaders> {
     0
   };
 
+  let reliability = match get_reliability(reliability_id) {
+    Reliability::ReliableSequenced(_) => Reliability::ReliableSequenced(read_u64(work_buf)?),
+    reliability => reliability,
+  };
+
   Some(PacketHeaders {
-    reliability: get_reliability(reliability_id),
+    reliability,
     id,
+    size,
   })
 }
 
@@ -159,11 +202,13 @@ fn parse_body(work_buf: &mut &[u8]) -> Option<ClientPacket> {
       let identity_bytes = read_data(work_buf, identity_size as usize)?;
       let identity = base64::encode(&identity_bytes);
       let data = read_string_u16(work_buf)?;
+      let supported_compression = read_byte(work_buf).unwrap_or_default();
 
       ClientPacket::Login {
         username,
         identity,
         data,
+        supported_compression,
       }
     }),
     8 => Some(ClientPacket::Logout),
@@ -245,6 +290,31 @@ fn parse_body(work_buf: &mut &[u8]) -> Option<ClientPacket> {
 
       ClientPacket::BattleResults { battle_stats }
     }),
+    28 => Some(ClientPacket::Fragment {
+      id: read_u64(work_buf)?,
+      index: read_u32(work_buf)?,
+      count: read_u32(work_buf)?,
+      data: work_buf.to_vec(),
+    }),
+    29 => Some(ClientPacket::PathMtuProbeResponse {
+      size: read_u16(work_buf)?,
+    }),
+    30 => Some(ClientPacket::Connect {
+      cookie: read_u64(work_buf)?,
+      server_random: read_bytes(work_buf)?,
+      key_exchange: read_bytes(work_buf).and_then(|public_key| {
+        Some(KeyExchange {
+          public_key,
+          client_random: read_bytes(work_buf)?,
+        })
+      }),
+    }),
+    31 => Some(ClientPacket::ActorMoveBatchAck {
+      id: read_u64(work_buf)?,
+    }),
+    32 => Some(ClientPacket::Resume {
+      token: read_string_u8(work_buf)?,
+    }),
     _ => None,
   }
 }
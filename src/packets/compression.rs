@@ -0,0 +1,82 @@
+use std::io::Write;
+
+/// Compression applied to asset streams, negotiated during login
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Compression {
+  None,
+  Zlib,
+}
+
+impl Compression {
+  /// Flags for every scheme the server can send, shared with clients through VersionInfo
+  pub const SUPPORTED_FLAGS: u8 = Compression::Zlib.flag();
+
+  pub const fn flag(self) -> u8 {
+    match self {
+      Compression::None => 0,
+      _ => 1 << (self as u8 - 1),
+    }
+  }
+
+  /// Picks a scheme both sides support, clients that don't advertise flags get Compression::None
+  pub fn negotiate(server_flags: u8, client_flags: u8) -> Compression {
+    if server_flags & client_flags & Compression::Zlib.flag() != 0 {
+      Compression::Zlib
+    } else {
+      Compression::None
+    }
+  }
+
+  /// Returns None if compressing doesn't shrink the data
+  pub fn compress(self, data: &[u8]) -> Option<Vec<u8>> {
+    let compressed = match self {
+      Compression::None => return None,
+      Compression::Zlib => {
+        let mut encoder =
+          flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+
+        encoder.write_all(data).ok()?;
+        encoder.finish().ok()?
+      }
+    };
+
+    (compressed.len() < data.len()).then_some(compressed)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn negotiation() {
+    let supported = Compression::SUPPORTED_FLAGS;
+
+    assert_eq!(Compression::negotiate(supported, 0), Compression::None);
+    assert_eq!(Compression::negotiate(0, supported), Compression::None);
+    assert_eq!(
+      Compression::negotiate(supported, 0xFF),
+      Compression::Zlib,
+      "unknown flags should be ignored"
+    );
+  }
+
+  #[test]
+  fn zlib() {
+    use std::io::Read;
+
+    let data = "<map>".repeat(100);
+    let compressed = Compression::Zlib.compress(data.as_bytes()).unwrap();
+    assert!(compressed.len() < data.len());
+
+    let mut decompressed = String::new();
+    flate2::read::ZlibDecoder::new(compressed.as_slice())
+      .read_to_string(&mut decompressed)
+      .unwrap();
+    assert_eq!(decompressed, data);
+
+    assert_eq!(Compression::Zlib.compress(&[1]), None);
+    assert_eq!(Compression::None.compress(data.as_bytes()), None);
+  }
+}
@@ -0,0 +1,373 @@
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// First byte of encrypted datagrams, unencrypted datagrams start with a reliability byte
+pub const ENCRYPTED_MARKER: u8 = 0xFF;
+// reliability byte + reliability id, or a counter for unreliable datagrams
+const HEADER_SIZE: usize = 1 + 1 + 8;
+const TAG_SIZE: usize = 16;
+/// Most bytes added to a datagram by encryption, reliable datagrams already carry their id
+pub const ENCRYPTION_OVERHEAD: usize = 1 + 8 + TAG_SIZE;
+// counters older than the newest by this much are rejected
+const REPLAY_WINDOW_SIZE: u64 = 64;
+
+/// Sent in Connect by clients encrypting their session
+#[derive(Debug, Clone, Copy)]
+pub struct KeyExchange {
+  /// X25519 key, generated for each connection
+  pub public_key: [u8; 32],
+  /// Mixed into the session keys with the server random from VersionInfo
+  pub client_random: [u8; 16],
+}
+
+/// The server's X25519 key pair, the public key is shared with clients through VersionInfo
+pub struct KeyPair {
+  secret: StaticSecret,
+  public_key: [u8; 32],
+}
+
+impl KeyPair {
+  pub fn generate() -> KeyPair {
+    let secret = StaticSecret::random_from_rng(rand::rngs::OsRng);
+
+    KeyPair {
+      public_key: PublicKey::from(&secret).to_bytes(),
+      secret,
+    }
+  }
+
+  pub fn public_key(&self) -> &[u8; 32] {
+    &self.public_key
+  }
+
+  /// Derives keys for each direction with HKDF-SHA256 from the shared secret, salted with randoms from both sides.
+  /// Returns None for public keys that would produce a predictable shared secret
+  pub fn create_session(
+    &self,
+    key_exchange: &KeyExchange,
+    server_random: &[u8; 16],
+  ) -> Option<EncryptedSession> {
+    let shared_secret = self
+      .secret
+      .diffie_hellman(&PublicKey::from(key_exchange.public_key));
+
+    if !shared_secret.was_contributory() {
+      return None;
+    }
+
+    Some(EncryptedSession::derive(
+      shared_secret.as_bytes(),
+      key_exchange,
+      server_random,
+      &self.public_key,
+    ))
+  }
+}
+
+#[derive(Default)]
+struct ReplayWindow {
+  newest_counter: Option<u64>,
+  /// bit n is set if newest_counter - n was received
+  received: u64,
+}
+
+impl ReplayWindow {
+  /// Returns false if the counter was already received or is too old to tell
+  fn accept(&mut self, counter: u64) -> bool {
+    let newest_counter = match self.newest_counter {
+      Some(newest_counter) => newest_counter,
+      None => {
+        self.newest_counter = Some(counter);
+        self.received = 1;
+        return true;
+      }
+    };
+
+    if counter > newest_counter {
+      let shift = counter - newest_counter;
+
+      self.received = if shift < REPLAY_WINDOW_SIZE {
+        self.received << shift
+      } else {
+        0
+      };
+
+      self.received |= 1;
+      self.newest_counter = Some(counter);
+      return true;
+    }
+
+    let offset = newest_counter - counter;
+
+    if offset >= REPLAY_WINDOW_SIZE || self.received & (1 << offset) != 0 {
+      return false;
+    }
+
+    self.received |= 1 << offset;
+    true
+  }
+}
+
+/// ChaCha20-Poly1305 with a key for each direction.
+/// Datagrams are `[ENCRYPTED_MARKER][reliability][u64 id][ciphertext][tag]`, the header is authenticated and forms the nonce.
+/// Reliable datagrams use their reliability id, which the PacketSorter already checks for replays,
+/// unreliable datagrams have no id and use a counter checked against a replay window instead.
+/// Resent datagrams are sealed again with the same nonce and plaintext, producing the same ciphertext
+pub struct EncryptedSession {
+  sending_cipher: ChaCha20Poly1305,
+  receiving_cipher: ChaCha20Poly1305,
+  next_unreliable_counter: AtomicU64,
+  unreliable_replay_window: Mutex<ReplayWindow>,
+}
+
+impl EncryptedSession {
+  fn derive(
+    shared_secret: &[u8; 32],
+    key_exchange: &KeyExchange,
+    server_random: &[u8; 16],
+    server_public_key: &[u8; 32],
+  ) -> EncryptedSession {
+    let salt = [&key_exchange.client_random[..], server_random].concat();
+    let hkdf = Hkdf::<Sha256>::new(Some(&salt), shared_secret);
+
+    let expand = |label: &[u8]| {
+      let info = [label, &key_exchange.public_key, server_public_key].concat();
+      let mut key = [0; 32];
+      // 32 bytes is well within HKDF-SHA256's output limit
+      hkdf.expand(&info, &mut key).unwrap();
+      ChaCha20Poly1305::new(&key.into())
+    };
+
+    EncryptedSession {
+      sending_cipher: expand(b"server to client"),
+      receiving_cipher: expand(b"client to server"),
+      next_unreliable_counter: AtomicU64::new(0),
+      unreliable_replay_window: Mutex::new(ReplayWindow::default()),
+    }
+  }
+
+  /// `datagram` must start with a reliability byte, followed by the reliability id unless unreliable
+  pub fn seal(&self, datagram: &[u8]) -> Vec<u8> {
+    let (reliability, rest) = datagram.split_first().unwrap();
+
+    let (id, body) = if *reliability == 0 {
+      let counter = self.next_unreliable_counter.fetch_add(1, Ordering::Relaxed);
+      (counter, rest)
+    } else {
+      let (id, body) = rest.split_at(8);
+      (u64::from_le_bytes(id.try_into().unwrap()), body)
+    };
+
+    let mut sealed = Vec::with_capacity(HEADER_SIZE + body.len() + TAG_SIZE);
+    sealed.push(ENCRYPTED_MARKER);
+    sealed.push(*reliability);
+    sealed.extend(id.to_le_bytes());
+
+    let payload = Payload {
+      msg: body,
+      aad: &sealed,
+    };
+
+    // only fails for plaintexts past ChaCha20's 256GiB limit
+    let ciphertext = self
+      .sending_cipher
+      .encrypt(&create_nonce(&sealed), payload)
+      .unwrap();
+
+    sealed.extend(ciphertext);
+    sealed
+  }
+
+  /// Returns None for datagrams that fail authentication or unreliable datagrams that were already received
+  pub fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < HEADER_SIZE + TAG_SIZE || sealed[0] != ENCRYPTED_MARKER {
+      return None;
+    }
+
+    let (header, ciphertext) = sealed.split_at(HEADER_SIZE);
+
+    let payload = Payload {
+      msg: ciphertext,
+      aad: header,
+    };
+
+    let body = self
+      .receiving_cipher
+      .decrypt(&create_nonce(header), payload)
+      .ok()?;
+
+    let reliability = header[1];
+    let id = &header[2..];
+
+    let mut datagram = Vec::with_capacity(HEADER_SIZE + body.len());
+    datagram.push(reliability);
+
+    if reliability == 0 {
+      let counter = u64::from_le_bytes(id.try_into().unwrap());
+
+      if !self
+        .unreliable_replay_window
+        .lock()
+        .unwrap()
+        .accept(counter)
+      {
+        return None;
+      }
+    } else {
+      datagram.extend(id);
+    }
+
+    datagram.extend(body);
+
+    Some(datagram)
+  }
+}
+
+// the reliability byte and id, unique for each datagram sent in a direction
+fn create_nonce(header: &[u8]) -> Nonce {
+  let mut nonce = Nonce::default();
+  nonce[..HEADER_SIZE - 1].copy_from_slice(&header[1..HEADER_SIZE]);
+  nonce
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn create_sessions() -> (EncryptedSession, EncryptedSession) {
+    let server_key_pair = KeyPair::generate();
+    let client_key_pair = KeyPair::generate();
+    let key_exchange = KeyExchange {
+      public_key: *client_key_pair.public_key(),
+      client_random: [1; 16],
+    };
+    let server_random = [2; 16];
+
+    let server_session = server_key_pair
+      .create_session(&key_exchange, &server_random)
+      .unwrap();
+
+    // clients derive the same keys with the directions swapped
+    let shared_secret = client_key_pair
+      .secret
+      .diffie_hellman(&PublicKey::from(*server_key_pair.public_key()));
+    let mirrored_session = EncryptedSession::derive(
+      shared_secret.as_bytes(),
+      &key_exchange,
+      &server_random,
+      server_key_pair.public_key(),
+    );
+    let client_session = EncryptedSession {
+      sending_cipher: mirrored_session.receiving_cipher,
+      receiving_cipher: mirrored_session.sending_cipher,
+      next_unreliable_counter: AtomicU64::new(0),
+      unreliable_replay_window: Mutex::default(),
+    };
+
+    (server_session, client_session)
+  }
+
+  #[test]
+  fn round_trip() {
+    let (server_session, client_session) = create_sessions();
+
+    let mut datagram = vec![2];
+    datagram.extend(7u64.to_le_bytes());
+    datagram.extend(b"Login packet with an identity".repeat(3));
+
+    let sealed = client_session.seal(&datagram);
+
+    assert_eq!(sealed.len(), datagram.len() + 1 + TAG_SIZE);
+    assert_ne!(&sealed[HEADER_SIZE..][..datagram.len() - 9], &datagram[9..]);
+    assert_eq!(server_session.open(&sealed), Some(datagram.clone()));
+    assert_eq!(
+      client_session.seal(&datagram),
+      sealed,
+      "resends should be sealed identically"
+    );
+
+    let unreliable = b"\x00Heartbeat".to_vec();
+    let sealed = client_session.seal(&unreliable);
+    assert_eq!(sealed.len(), unreliable.len() + ENCRYPTION_OVERHEAD);
+    assert_ne!(
+      client_session.seal(&unreliable),
+      sealed,
+      "unreliable datagrams should use a new counter"
+    );
+    assert_eq!(server_session.open(&sealed), Some(unreliable.clone()));
+    assert!(
+      server_session.open(&sealed).is_none(),
+      "replayed unreliable datagrams should be rejected"
+    );
+
+    let sealed = server_session.seal(&unreliable);
+    assert_eq!(client_session.open(&sealed), Some(unreliable));
+
+    let mut tampered = client_session.seal(b"\x00Ack");
+    tampered[HEADER_SIZE] ^= 1;
+    assert!(server_session.open(&tampered).is_none());
+
+    let mut tampered = client_session.seal(&datagram);
+    tampered[2] ^= 1;
+    assert!(
+      server_session.open(&tampered).is_none(),
+      "headers should be authenticated"
+    );
+
+    assert!(server_session.open(b"\x00Ack").is_none());
+  }
+
+  #[test]
+  fn fresh_keys() {
+    let server_key_pair = KeyPair::generate();
+    let key_exchange = KeyExchange {
+      public_key: *KeyPair::generate().public_key(),
+      client_random: [1; 16],
+    };
+
+    let session = server_key_pair
+      .create_session(&key_exchange, &[2; 16])
+      .unwrap();
+    let other_session = server_key_pair
+      .create_session(&key_exchange, &[3; 16])
+      .unwrap();
+
+    assert_ne!(
+      session.seal(b"\x00Ack"),
+      other_session.seal(b"\x00Ack"),
+      "different server randoms should produce different keys"
+    );
+
+    let low_order_key_exchange = KeyExchange {
+      public_key: [0; 32],
+      client_random: [1; 16],
+    };
+
+    assert!(
+      server_key_pair
+        .create_session(&low_order_key_exchange, &[2; 16])
+        .is_none(),
+      "low order points should be rejected"
+    );
+  }
+
+  #[test]
+  fn replay_window() {
+    let mut window = ReplayWindow::default();
+
+    assert!(window.accept(5));
+    assert!(window.accept(3));
+    assert!(!window.accept(3), "duplicates should be rejected");
+    assert!(window.accept(100));
+    assert!(
+      !window.accept(5),
+      "counters outside the window should be rejected"
+    );
+    assert!(window.accept(99));
+  }
+}
@@ -0,0 +1,43 @@
+use super::EncryptedSession;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+/// Sessions shared between the listener, which opens incoming datagrams, and the server thread
+#[derive(Default)]
+pub struct EncryptedSessions {
+  sessions: RwLock<HashMap<SocketAddr, Arc<EncryptedSession>>>,
+}
+
+impl EncryptedSessions {
+  pub fn insert(&self, socket_address: SocketAddr, session: Arc<EncryptedSession>) {
+    let mut sessions = self.sessions.write().unwrap();
+    sessions.insert(socket_address, session);
+  }
+
+  pub fn remove(&self, socket_address: SocketAddr) {
+    let mut sessions = self.sessions.write().unwrap();
+    sessions.remove(&socket_address);
+  }
+
+  pub fn get(&self, socket_address: SocketAddr) -> Option<Arc<EncryptedSession>> {
+    let sessions = self.sessions.read().unwrap();
+    sessions.get(&socket_address).cloned()
+  }
+
+  /// Datagrams for addresses without a session are returned as is
+  pub fn seal(&self, socket_address: SocketAddr, datagram: Vec<u8>) -> Vec<u8> {
+    match self.get(socket_address) {
+      Some(session) => session.seal(&datagram),
+      None => datagram,
+    }
+  }
+
+  /// Returns None if the datagram fails to open or is unencrypted from an address with a session
+  pub fn open(&self, socket_address: SocketAddr, datagram: &[u8]) -> Option<Vec<u8>> {
+    match self.get(socket_address) {
+      Some(session) => session.open(datagram),
+      None => Some(datagram.to_vec()),
+    }
+  }
+}
@@ -0,0 +1,5 @@
+mod encrypted_session;
+mod encrypted_sessions;
+
+pub use encrypted_session::*;
+pub use encrypted_sessions::EncryptedSessions;
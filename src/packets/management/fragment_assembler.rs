@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// limits to avoid holding onto too much memory for a single client
+const MAX_PENDING_PACKETS: usize = 8;
+// smallest fragment a client is expected to send, bounds the declared fragment count
+const MIN_FRAGMENT_SIZE: usize = 512;
+// unreliable fragments may never complete
+const PENDING_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct PendingPacket {
+  fragments: Vec<Option<Vec<u8>>>,
+  received_count: usize,
+  size: usize,
+  last_update: Instant,
+}
+
+/// Rebuilds packets split into fragments by the client
+pub struct FragmentAssembler {
+  pending: HashMap<u64, PendingPacket>,
+  max_packet_size: usize,
+  max_fragments: usize,
+}
+
+impl FragmentAssembler {
+  /// Packets reassembled past max_packet_size are dropped
+  pub fn new(max_packet_size: usize) -> FragmentAssembler {
+    FragmentAssembler {
+      pending: HashMap::new(),
+      max_packet_size,
+      max_fragments: max_packet_size.div_ceil(MIN_FRAGMENT_SIZE),
+    }
+  }
+
+  /// Returns the packet once every fragment is received
+  pub fn add_fragment(
+    &mut self,
+    id: u64,
+    index: u32,
+    count: u32,
+    data: Vec<u8>,
+  ) -> Option<Vec<u8>> {
+    let (index, count) = (index as usize, count as usize);
+
+    if index >= count || count > self.max_fragments {
+      return None;
+    }
+
+    self
+      .pending
+      .retain(|_, pending| pending.last_update.elapsed() < PENDING_TIMEOUT);
+
+    if !self.pending.contains_key(&id) && self.pending.len() >= MAX_PENDING_PACKETS {
+      // drop the packet that's gone the longest without progress
+      let oldest_id = self
+        .pending
+        .iter()
+        .min_by_key(|(_, pending)| pending.last_update)
+        .map(|(id, _)| *id)?;
+
+      self.pending.remove(&oldest_id);
+    }
+
+    let pending = self.pending.entry(id).or_insert_with(|| PendingPacket {
+      fragments: vec![None; count],
+      received_count: 0,
+      size: 0,
+      last_update: Instant::now(),
+    });
+
+    if pending.fragments.len() != count || pending.size + data.len() > self.max_packet_size {
+      // malformed
+      self.pending.remove(&id);
+      return None;
+    }
+
+    pending.last_update = Instant::now();
+
+    if pending.fragments[index].is_none() {
+      pending.size += data.len();
+      pending.received_count += 1;
+      pending.fragments[index] = Some(data);
+    }
+
+    if pending.received_count < count {
+      return None;
+    }
+
+    let pending = self.pending.remove(&id)?;
+
+    Some(pending.fragments.into_iter().flatten().flatten().collect())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reassembly() {
+    let mut assembler = FragmentAssembler::new(4096);
+
+    assert_eq!(assembler.add_fragment(0, 1, 3, vec![3, 4]), None);
+    assert_eq!(assembler.add_fragment(1, 0, 1, vec![9]), Some(vec![9]));
+    assert_eq!(assembler.add_fragment(0, 1, 3, vec![3, 4]), None);
+    assert_eq!(assembler.add_fragment(0, 0, 3, vec![1, 2]), None);
+    assert_eq!(
+      assembler.add_fragment(0, 2, 3, vec![5]),
+      Some(vec![1, 2, 3, 4, 5])
+    );
+
+    assert_eq!(assembler.add_fragment(2, 3, 3, vec![0]), None);
+    assert_eq!(assembler.add_fragment(3, 0, 2, vec![0]), None);
+    assert_eq!(
+      assembler.add_fragment(3, 0, 5, vec![0]),
+      None,
+      "mismatched counts should drop the packet"
+    );
+    assert!(assembler.pending.is_empty());
+  }
+
+  #[test]
+  fn limits() {
+    let mut assembler = FragmentAssembler::new(1024);
+
+    assert_eq!(
+      assembler.add_fragment(0, 0, 3, vec![0]),
+      None,
+      "declared counts past the limit should be rejected"
+    );
+    assert!(assembler.pending.is_empty());
+
+    assert_eq!(assembler.add_fragment(1, 0, 2, vec![0; 1000]), None);
+    assert_eq!(
+      assembler.add_fragment(1, 1, 2, vec![0; 25]),
+      None,
+      "packets larger than the limit should be dropped"
+    );
+    assert!(assembler.pending.is_empty());
+
+    assert_eq!(assembler.add_fragment(2, 1, 2, vec![0; 24]), None);
+    assert_eq!(
+      assembler
+        .add_fragment(2, 0, 2, vec![0; 1000])
+        .map(|data| data.len()),
+      Some(1024)
+    );
+  }
+}
@@ -1,9 +1,19 @@
+mod fragment_assembler;
+mod network_simulator;
+mod network_stats;
 mod packet_orchestrator;
 mod packet_shipper;
 mod packet_sorter;
+mod path_mtu;
+mod priority;
 mod reliability;
 
+pub use fragment_assembler::FragmentAssembler;
+pub use network_simulator::{NetworkConditions, NetworkSimulator};
+pub use network_stats::NetworkStats;
 pub use packet_orchestrator::PacketOrchestrator;
 pub use packet_shipper::PacketShipper;
 pub use packet_sorter::PacketSorter;
+pub use path_mtu::PathMtu;
+pub use priority::{Dependency, Priority};
 pub use reliability::*;
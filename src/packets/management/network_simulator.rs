@@ -0,0 +1,173 @@
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+// long enough for packets sent shortly after to overtake a reordered packet
+const REORDER_DELAY: f32 = 0.05;
+
+/// send time, destination, data
+type DelayedPacket = (Instant, SocketAddr, Vec<u8>);
+
+/// Simulated network trouble for testing, rates are percentages and times are in seconds
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConditions {
+  pub drop_rate: f32,
+  pub latency: f32,
+  /// latency varies by up to this much in either direction
+  pub jitter: f32,
+  pub duplicate_rate: f32,
+  /// reordered packets are held back so later packets can overtake them
+  pub reorder_rate: f32,
+}
+
+impl NetworkConditions {
+  pub fn is_ideal(&self) -> bool {
+    *self == NetworkConditions::default()
+  }
+
+  /// Returns how long to delay each copy of a packet, empty if the packet should be dropped
+  pub fn roll_delays(&self) -> Vec<Duration> {
+    if roll(self.drop_rate) {
+      return Vec::new();
+    }
+
+    let copies = if roll(self.duplicate_rate) { 2 } else { 1 };
+
+    (0..copies).map(|_| self.roll_delay()).collect()
+  }
+
+  fn roll_delay(&self) -> Duration {
+    let mut delay = self.latency;
+
+    if self.jitter > 0.0 {
+      delay += (rand::random::<f32>() * 2.0 - 1.0) * self.jitter;
+    }
+
+    if roll(self.reorder_rate) {
+      delay += REORDER_DELAY;
+    }
+
+    Duration::from_secs_f32(delay.max(0.0))
+  }
+}
+
+fn roll(rate: f32) -> bool {
+  rate > 0.0 && rand::random::<f32>() < rate / 100.0
+}
+
+/// Applies NetworkConditions to outgoing packets, delayed packets are sent from a separate thread
+pub struct NetworkSimulator {
+  conditions: NetworkConditions,
+  tx: mpsc::Sender<DelayedPacket>,
+}
+
+impl NetworkSimulator {
+  pub fn new(socket: UdpSocket, conditions: NetworkConditions) -> NetworkSimulator {
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || delayed_send_loop(socket, rx));
+
+    NetworkSimulator { conditions, tx }
+  }
+
+  /// Returns false if the packet failed to send, dropped packets are treated as sent
+  pub fn send_to(&self, socket: &UdpSocket, buf: &[u8], socket_address: SocketAddr) -> bool {
+    let mut success = true;
+
+    for delay in self.conditions.roll_delays() {
+      if delay.is_zero() {
+        success &= socket.send_to(buf, socket_address).is_ok();
+      } else {
+        let send_time = Instant::now() + delay;
+        let _ = self.tx.send((send_time, socket_address, buf.to_vec()));
+      }
+    }
+
+    success
+  }
+}
+
+fn delayed_send_loop(socket: UdpSocket, rx: mpsc::Receiver<DelayedPacket>) {
+  // the counter keeps packets with the same send time in order
+  let mut pending: BinaryHeap<Reverse<(Instant, u64, DelayedPacket)>> = BinaryHeap::new();
+  let mut counter: u64 = 0;
+
+  loop {
+    let message = match pending.peek() {
+      Some(Reverse((send_time, ..))) => {
+        rx.recv_timeout(send_time.saturating_duration_since(Instant::now()))
+      }
+      None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+    };
+
+    match message {
+      Ok(delayed_packet) => {
+        pending.push(Reverse((delayed_packet.0, counter, delayed_packet)));
+        counter += 1;
+      }
+      Err(mpsc::RecvTimeoutError::Timeout) => {}
+      // the server stopped
+      Err(mpsc::RecvTimeoutError::Disconnected) => break,
+    }
+
+    let now = Instant::now();
+
+    while matches!(pending.peek(), Some(Reverse((send_time, ..))) if *send_time <= now) {
+      let Reverse((_, _, (_, socket_address, data))) = pending.pop().unwrap();
+      let _ = socket.send_to(&data, socket_address);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn roll_delays() {
+    let conditions = NetworkConditions::default();
+    assert!(conditions.is_ideal());
+    assert_eq!(conditions.roll_delays(), vec![Duration::ZERO]);
+
+    let conditions = NetworkConditions {
+      drop_rate: 100.0,
+      duplicate_rate: 100.0,
+      ..Default::default()
+    };
+    assert!(conditions.roll_delays().is_empty());
+
+    let conditions = NetworkConditions {
+      latency: 0.1,
+      duplicate_rate: 100.0,
+      ..Default::default()
+    };
+    assert_eq!(
+      conditions.roll_delays(),
+      vec![Duration::from_secs_f32(0.1); 2]
+    );
+
+    let conditions = NetworkConditions {
+      latency: 0.1,
+      jitter: 0.5,
+      ..Default::default()
+    };
+
+    for _ in 0..100 {
+      let delay = conditions.roll_delays()[0];
+      assert!(delay <= Duration::from_secs_f32(0.6));
+    }
+
+    let conditions = NetworkConditions {
+      reorder_rate: 100.0,
+      ..Default::default()
+    };
+    assert_eq!(
+      conditions.roll_delays(),
+      vec![Duration::from_secs_f32(REORDER_DELAY)]
+    );
+  }
+}
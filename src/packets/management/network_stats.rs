@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+/// Connection measurements for a single client, shared between its PacketSorter and PacketShipper
+#[derive(Clone, Default, Debug)]
+pub struct NetworkStats {
+  /// smoothed round trip time, unset until a reliable packet is acknowledged without being resent
+  pub rtt: Option<Duration>,
+  /// smoothed deviation from the round trip time
+  pub jitter: Duration,
+  pub packets_sent: u64,
+  pub packets_received: u64,
+  pub bytes_sent: u64,
+  pub bytes_received: u64,
+  pub reliable_packets_sent: u64,
+  pub packets_resent: u64,
+  /// bytes left out of asset streams by compression
+  pub compression_bytes_saved: u64,
+}
+
+impl NetworkStats {
+  /// Fraction of reliable packets that needed to be resent, between 0.0 and 1.0
+  pub fn estimated_loss(&self) -> f32 {
+    let attempts = self.reliable_packets_sent + self.packets_resent;
+
+    if attempts == 0 {
+      return 0.0;
+    }
+
+    self.packets_resent as f32 / attempts as f32
+  }
+
+  pub(super) fn record_sent(&mut self, bytes: usize) {
+    self.packets_sent += 1;
+    self.bytes_sent += bytes as u64;
+  }
+
+  pub(super) fn record_resent(&mut self, bytes: usize) {
+    self.record_sent(bytes);
+    self.packets_resent += 1;
+  }
+
+  pub(super) fn record_received(&mut self, bytes: usize) {
+    self.packets_received += 1;
+    self.bytes_received += bytes as u64;
+  }
+
+  pub(crate) fn record_compression_savings(&mut self, bytes: usize) {
+    self.compression_bytes_saved += bytes as u64;
+  }
+
+  // smoothing from RFC 6298
+  pub(super) fn record_rtt_sample(&mut self, sample: Duration) {
+    let rtt = match self.rtt {
+      Some(rtt) => rtt,
+      None => {
+        self.rtt = Some(sample);
+        self.jitter = sample / 2;
+        return;
+      }
+    };
+
+    let deviation = rtt.abs_diff(sample);
+
+    self.jitter = (self.jitter * 3 + deviation) / 4;
+    self.rtt = Some((rtt * 7 + sample) / 8);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rtt_smoothing() {
+    let mut stats = NetworkStats::default();
+
+    stats.record_rtt_sample(Duration::from_millis(100));
+    assert_eq!(stats.rtt, Some(Duration::from_millis(100)));
+    assert_eq!(stats.jitter, Duration::from_millis(50));
+
+    stats.record_rtt_sample(Duration::from_millis(180));
+    assert_eq!(stats.rtt, Some(Duration::from_millis(110)));
+    assert_eq!(
+      stats.jitter,
+      Duration::from_millis(57) + Duration::from_micros(500)
+    );
+
+    stats.reliable_packets_sent = 3;
+    stats.packets_resent = 1;
+    assert_eq!(stats.estimated_loss(), 0.25);
+  }
+}
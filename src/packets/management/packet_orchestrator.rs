@@ -1,35 +1,99 @@
-use crate::packets::{PacketShipper, Reliability, ServerPacket};
+use crate::packets::{
+  EncryptedSessions, NetworkConditions, NetworkSimulator, NetworkStats, PacketShipper, Reliability,
+  ServerPacket,
+};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::Arc;
 
 pub struct PacketOrchestrator {
   socket: Rc<std::net::UdpSocket>,
+  max_payload_size: usize,
   resend_budget: usize,
+  rate_limit: usize,
   client_room_map: HashMap<std::net::SocketAddr, Vec<String>>,
   shipper_map: HashMap<std::net::SocketAddr, Rc<RefCell<PacketShipper>>>,
   rooms: HashMap<String, Vec<Rc<RefCell<PacketShipper>>>>,
   client_id_map: HashMap<String, Rc<RefCell<PacketShipper>>>,
+  network_simulator: Option<Rc<NetworkSimulator>>,
+  encrypted_sessions: Option<Arc<EncryptedSessions>>,
 }
 
 impl PacketOrchestrator {
-  pub fn new(socket: Rc<std::net::UdpSocket>, resend_budget: usize) -> PacketOrchestrator {
+  pub fn new(
+    socket: Rc<std::net::UdpSocket>,
+    max_payload_size: usize,
+    resend_budget: usize,
+    rate_limit: usize,
+  ) -> PacketOrchestrator {
     PacketOrchestrator {
       socket,
+      max_payload_size,
       resend_budget,
+      rate_limit,
       client_room_map: HashMap::new(),
       shipper_map: HashMap::new(),
       rooms: HashMap::new(),
       client_id_map: HashMap::new(),
+      network_simulator: None,
+      encrypted_sessions: None,
     }
   }
 
-  pub fn add_client(&mut self, socket_address: std::net::SocketAddr, id: String) {
+  /// Applies the conditions to packets sent to clients added after this call
+  pub fn simulate_outgoing_conditions(
+    &mut self,
+    conditions: NetworkConditions,
+  ) -> std::io::Result<()> {
+    let socket = self.socket.try_clone()?;
+
+    self.network_simulator = Some(Rc::new(NetworkSimulator::new(socket, conditions)));
+
+    Ok(())
+  }
+
+  /// Set by simulate_outgoing_conditions, shared with PacketSorters so acks are affected too
+  pub fn get_network_simulator(&self) -> Option<Rc<NetworkSimulator>> {
+    self.network_simulator.clone()
+  }
+
+  /// Clients added after this call use the session stored for their address, if there is one
+  pub fn set_encrypted_sessions(&mut self, encrypted_sessions: Arc<EncryptedSessions>) {
+    self.encrypted_sessions = Some(encrypted_sessions);
+  }
+
+  pub fn add_client(
+    &mut self,
+    socket_address: std::net::SocketAddr,
+    id: String,
+    network_stats: Rc<RefCell<NetworkStats>>,
+  ) {
     let shipper = Rc::new(RefCell::new(PacketShipper::new(
       socket_address,
+      self.max_payload_size,
       self.resend_budget,
+      self.rate_limit,
+      network_stats,
     )));
 
+    if let Some(network_simulator) = &self.network_simulator {
+      shipper
+        .borrow_mut()
+        .set_network_simulator(network_simulator.clone());
+    }
+
+    let encrypted_session = self
+      .encrypted_sessions
+      .as_ref()
+      .and_then(|encrypted_sessions| encrypted_sessions.get(socket_address));
+
+    if let Some(encrypted_session) = encrypted_session {
+      shipper
+        .borrow_mut()
+        .set_encrypted_session(encrypted_session);
+    }
+
     self.client_id_map.insert(id, shipper.clone());
 
     self.shipper_map.insert(socket_address, shipper);
@@ -46,7 +110,12 @@ impl PacketOrchestrator {
     }
 
     self.client_room_map.remove(&socket_address);
-    self.shipper_map.remove(&socket_address);
+
+    if let Some(shipper) = self.shipper_map.remove(&socket_address) {
+      self
+        .client_id_map
+        .retain(|_, id_shipper| !Rc::ptr_eq(id_shipper, &shipper));
+    }
   }
 
   pub fn join_room(&mut self, socket_address: std::net::SocketAddr, room_id: String) {
@@ -288,11 +357,36 @@ impl PacketOrchestrator {
     }
   }
 
+  pub fn path_mtu_probe_received(&mut self, socket_address: std::net::SocketAddr, size: u16) {
+    if let Some(shipper) = self.shipper_map.get_mut(&socket_address) {
+      shipper.borrow_mut().path_mtu_probe_received(size)
+    }
+  }
+
+  pub fn has_backed_up_packets(&self) -> bool {
+    self
+      .shipper_map
+      .values()
+      .any(|shipper| shipper.borrow().has_backed_up_packets())
+  }
+
   pub fn resend_backed_up_packets(&mut self) {
     for shipper in self.shipper_map.values_mut() {
       shipper.borrow_mut().resend_backed_up_packets(&self.socket);
     }
   }
+
+  pub fn send_queued_packets(&mut self) {
+    for shipper in self.shipper_map.values_mut() {
+      shipper.borrow_mut().send_queued_packets(&self.socket);
+    }
+  }
+
+  pub fn probe_path_mtus(&mut self) {
+    for shipper in self.shipper_map.values_mut() {
+      shipper.borrow_mut().probe_path_mtu(&self.socket);
+    }
+  }
 }
 
 #[cfg(test)]
@@ -303,7 +397,7 @@ mod tests {
   fn create_orchestrator() -> PacketOrchestrator {
     let socket = UdpSocket::bind("127.0.0.1:8765").unwrap();
     socket.take_error().unwrap();
-    PacketOrchestrator::new(Rc::new(socket), 0)
+    PacketOrchestrator::new(Rc::new(socket), 1400, 0, 0)
   }
 
   #[test]
@@ -316,7 +410,7 @@ mod tests {
     let room_c = String::from("C");
 
     orchestrator.join_room(addr, room_c.clone());
-    orchestrator.add_client(addr, String::new());
+    orchestrator.add_client(addr, String::new(), Default::default());
     orchestrator.join_room(addr, room_a.clone());
     orchestrator.join_room(addr, room_b.clone());
 
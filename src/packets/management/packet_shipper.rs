@@ -1,41 +1,145 @@
 use super::super::bytes::write_u64;
 use super::super::server_packets::*;
+use super::super::{EncryptedSession, ENCRYPTION_OVERHEAD};
 use super::reliability::Reliability;
+use super::{Dependency, NetworkSimulator, NetworkStats, PathMtu, Priority};
 use log::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::net::UdpSocket;
+use std::rc::Rc;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+// used until the first round trip is measured
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(100);
+// backoff stops doubling here
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3);
+// enough doublings to reach MAX_RETRY_DELAY from MIN_RETRY_DELAY
+const MAX_BACKOFF: u32 = 5;
+
+// reliability + reliability id + sequence channel
+const TRANSPORT_HEADER_SIZE: usize = 1 + 8 + 8;
+// packet type + fragment id + index + count
+const FRAGMENT_HEADER_SIZE: usize = 2 + 8 + 4 + 4;
+// unreliable header + packet type + size
+const PATH_MTU_PROBE_HEADER_SIZE: usize = 1 + 2 + 2;
+
 struct BackedUpPacket {
   pub id: u64,
   pub creation_time: std::time::Instant,
   pub send_time: std::time::Instant,
+  /// doubles after every resend
+  pub retry_delay: Duration,
+  pub resends: u32,
+  /// only used by ReliableSequenced
+  pub channel: u64,
   pub data: Vec<u8>,
 }
 
+struct QueuedPacket {
+  /// position in the order packets were queued across every priority
+  order: u64,
+  reliability: Reliability,
+  dependency: Dependency,
+  connects_actor: bool,
+  data: Vec<u8>,
+}
+
+struct Schedule {
+  priority: Priority,
+  dependency: Dependency,
+  connects_actor: bool,
+}
+
 pub struct PacketShipper {
   socket_address: std::net::SocketAddr,
+  path_mtu: PathMtu,
   resend_budget: isize,
   remaining_budget: isize,
+  /// bytes per second, 0 for unlimited
+  rate_limit: usize,
+  /// bytes that can be sent before packets are held in the queues
+  rate_allowance: f64,
+  last_allowance_update: Instant,
+  /// indexed by priority
+  queues: [VecDeque<QueuedPacket>; Priority::COUNT],
+  /// order of queued ActorConnected packets, indexed by priority
+  queued_actor_connections: [VecDeque<u64>; Priority::COUNT],
+  next_queue_order: u64,
   next_unreliable_sequenced: u64,
   next_reliable: u64,
+  next_reliable_sequenced: u64,
   next_reliable_ordered: u64,
+  next_fragmented: u64,
   backed_up_reliable: Vec<BackedUpPacket>,
+  backed_up_reliable_sequenced: Vec<BackedUpPacket>,
   backed_up_reliable_ordered: Vec<BackedUpPacket>,
-  retry_delay: Duration,
+  /// doublings applied to the retry delay of new packets, reset by the next round trip sample
+  backoff: u32,
+  network_stats: Rc<RefCell<NetworkStats>>,
+  network_simulator: Option<Rc<NetworkSimulator>>,
+  encrypted_session: Option<Arc<EncryptedSession>>,
 }
 
 impl PacketShipper {
-  pub fn new(socket_address: std::net::SocketAddr, resend_budget: usize) -> PacketShipper {
+  pub fn new(
+    socket_address: std::net::SocketAddr,
+    max_payload_size: usize,
+    resend_budget: usize,
+    rate_limit: usize,
+    network_stats: Rc<RefCell<NetworkStats>>,
+  ) -> PacketShipper {
+    let path_mtu = PathMtu::new(max_payload_size);
+    let rate_allowance = calculate_max_rate_allowance(rate_limit, path_mtu.get_payload_size());
+
     PacketShipper {
       socket_address,
+      path_mtu,
       resend_budget: resend_budget as isize,
       remaining_budget: resend_budget as isize,
+      rate_limit,
+      rate_allowance,
+      last_allowance_update: Instant::now(),
+      queues: Default::default(),
+      queued_actor_connections: Default::default(),
+      next_queue_order: 0,
       next_unreliable_sequenced: 0,
       next_reliable: 0,
+      next_reliable_sequenced: 0,
       next_reliable_ordered: 0,
+      next_fragmented: 0,
       backed_up_reliable: Vec::new(),
+      backed_up_reliable_sequenced: Vec::new(),
       backed_up_reliable_ordered: Vec::new(),
-      retry_delay: Duration::from_secs(1),
+      backoff: 0,
+      network_stats,
+      network_simulator: None,
+      encrypted_session: None,
+    }
+  }
+
+  /// Routes every packet sent to the client through the simulator
+  pub fn set_network_simulator(&mut self, network_simulator: Rc<NetworkSimulator>) {
+    self.network_simulator = Some(network_simulator);
+  }
+
+  /// Encrypts every packet sent to the client
+  pub fn set_encrypted_session(&mut self, encrypted_session: Arc<EncryptedSession>) {
+    self.encrypted_session = Some(encrypted_session);
+  }
+
+  /// Largest payload size confirmed to reach the client, packets past this size are fragmented
+  pub fn get_max_payload_size(&self) -> usize {
+    self.path_mtu.get_payload_size() - self.encryption_overhead()
+  }
+
+  fn encryption_overhead(&self) -> usize {
+    if self.encrypted_session.is_some() {
+      ENCRYPTION_OVERHEAD
+    } else {
+      0
     }
   }
 
@@ -43,7 +147,140 @@ impl PacketShipper {
     self.send_bytes(socket, reliability, &build_packet(packet));
   }
 
+  /// Packets larger than the max payload size are split into fragments for the client to reassemble,
+  /// except for sequenced packets which are dropped, as reassembly could complete out of sequence.
+  /// Packets are held in queues by priority while the client is over the rate limit
   pub fn send_bytes(&mut self, socket: &UdpSocket, reliability: Reliability, bytes: &[u8]) {
+    let schedule = Schedule {
+      priority: get_packet_priority(bytes),
+      dependency: get_packet_dependency(bytes),
+      connects_actor: is_actor_connected_packet(bytes),
+    };
+    let max_payload_size = self.get_max_payload_size();
+
+    if TRANSPORT_HEADER_SIZE + bytes.len() <= max_payload_size {
+      self.queue_packet(socket, &schedule, reliability, bytes);
+      return;
+    }
+
+    if matches!(
+      reliability,
+      Reliability::UnreliableSequenced | Reliability::ReliableSequenced(_)
+    ) {
+      error!(
+        "Dropped a {} byte sequenced packet for {}, sequenced packets must fit in {} bytes",
+        bytes.len(),
+        self.socket_address,
+        max_payload_size - TRANSPORT_HEADER_SIZE
+      );
+      return;
+    }
+
+    let id = self.next_fragmented;
+    self.next_fragmented += 1;
+
+    let fragment_size = max_payload_size - TRANSPORT_HEADER_SIZE - FRAGMENT_HEADER_SIZE;
+    let count = bytes.len().div_ceil(fragment_size) as u32;
+
+    for (index, data) in bytes.chunks(fragment_size).enumerate() {
+      let fragment = build_packet(ServerPacket::Fragment {
+        id,
+        index: index as u32,
+        count,
+        data,
+      });
+
+      self.queue_packet(socket, &schedule, reliability, &fragment);
+    }
+  }
+
+  fn queue_packet(
+    &mut self,
+    socket: &UdpSocket,
+    schedule: &Schedule,
+    reliability: Reliability,
+    bytes: &[u8],
+  ) {
+    if self.rate_limit == 0 {
+      self.send_unfragmented(socket, reliability, bytes);
+      return;
+    }
+
+    let priority = schedule.priority as usize;
+    let order = self.next_queue_order;
+    self.next_queue_order += 1;
+
+    if schedule.connects_actor {
+      self.queued_actor_connections[priority].push_back(order);
+    }
+
+    self.queues[priority].push_back(QueuedPacket {
+      order,
+      reliability,
+      dependency: schedule.dependency,
+      connects_actor: schedule.connects_actor,
+      data: bytes.to_vec(),
+    });
+
+    self.send_queued_packets(socket);
+  }
+
+  /// Sends queued packets, highest priority first, until the rate limit is reached
+  pub fn send_queued_packets(&mut self, socket: &UdpSocket) {
+    let elapsed = self.last_allowance_update.elapsed();
+    self.last_allowance_update = Instant::now();
+
+    let max_rate_allowance =
+      calculate_max_rate_allowance(self.rate_limit, self.get_max_payload_size());
+
+    self.rate_allowance = (self.rate_allowance + elapsed.as_secs_f64() * self.rate_limit as f64)
+      .min(max_rate_allowance);
+
+    while let Some(priority) = self.next_sendable_queue() {
+      let queued_packet = &self.queues[priority][0];
+      let size = TRANSPORT_HEADER_SIZE + queued_packet.data.len();
+
+      if self.rate_allowance < size as f64 {
+        return;
+      }
+
+      let queued_packet = self.queues[priority].pop_front().unwrap();
+
+      if queued_packet.connects_actor {
+        self.queued_actor_connections[priority].pop_front();
+      }
+
+      self.send_unfragmented(socket, queued_packet.reliability, &queued_packet.data);
+    }
+  }
+
+  /// Highest priority queue with a packet that doesn't depend on packets queued before it in lower queues.
+  /// The lowest queue is always sendable, so packets can't be held forever
+  fn next_sendable_queue(&self) -> Option<usize> {
+    (0..Priority::COUNT).rev().find(|&priority| {
+      let queued_packet = match self.queues[priority].front() {
+        Some(queued_packet) => queued_packet,
+        None => return false,
+      };
+
+      (0..priority).all(|lower_priority| {
+        // queues are in order, so checking the oldest dependency is enough
+        let oldest_dependency = match queued_packet.dependency {
+          Dependency::None => return true,
+          Dependency::Actors => self.queued_actor_connections[lower_priority]
+            .front()
+            .copied(),
+          Dependency::All => self.queues[lower_priority]
+            .front()
+            .map(|lower_packet| lower_packet.order),
+        };
+
+        !matches!(oldest_dependency, Some(order) if order < queued_packet.order)
+      })
+    })
+  }
+
+  fn send_unfragmented(&mut self, socket: &UdpSocket, reliability: Reliability, bytes: &[u8]) {
     match reliability {
       Reliability::Unreliable => {
         let mut data = vec![0];
@@ -62,86 +299,161 @@ impl PacketShipper {
         self.next_unreliable_sequenced += 1;
       }
       Reliability::Reliable => {
-        let mut data = vec![2];
-        write_u64(&mut data, self.next_reliable);
-        data.extend(bytes);
-
-        let creation_time = Instant::now();
-        let send_time = if self.send_with_silenced_errors(socket, &data) {
-          creation_time
-        } else {
-          creation_time - self.retry_delay
-        };
-
-        self.backed_up_reliable.push(BackedUpPacket {
-          id: self.next_reliable,
-          creation_time,
-          send_time,
-          data,
-        });
+        let backed_up_packet = self.send_reliable(socket, 2, self.next_reliable, bytes);
+        self.backed_up_reliable.push(backed_up_packet);
 
         self.next_reliable += 1;
       }
-      // stalls until packets arrive in order (if client gets packet 0 + 3 + 2, it processes 0, and waits for 1)
-      Reliability::ReliableOrdered => {
-        let mut data = vec![4];
-        write_u64(&mut data, self.next_reliable_ordered);
+      // resent until acknowledged or a newer packet on the same channel is acknowledged,
+      // the client drops packets older than the newest on each channel.
+      // ids are shared between channels, so acks don't need to include the channel
+      Reliability::ReliableSequenced(channel) => {
+        let mut data = Vec::with_capacity(8 + bytes.len());
+        write_u64(&mut data, channel);
         data.extend(bytes);
 
-        let creation_time = Instant::now();
-        let send_time = if self.send_with_silenced_errors(socket, &data) {
-          creation_time
-        } else {
-          creation_time - self.retry_delay
-        };
+        let mut backed_up_packet =
+          self.send_reliable(socket, 3, self.next_reliable_sequenced, &data);
+        backed_up_packet.channel = channel;
+        self.backed_up_reliable_sequenced.push(backed_up_packet);
 
-        self.backed_up_reliable_ordered.push(BackedUpPacket {
-          id: self.next_reliable_ordered,
-          creation_time,
-          send_time,
-          data,
-        });
+        self.next_reliable_sequenced += 1;
+      }
+      // stalls until packets arrive in order (if client gets packet 0 + 3 + 2, it processes 0, and waits for 1)
+      Reliability::ReliableOrdered => {
+        let backed_up_packet = self.send_reliable(socket, 4, self.next_reliable_ordered, bytes);
+        self.backed_up_reliable_ordered.push(backed_up_packet);
 
         self.next_reliable_ordered += 1;
       }
     }
   }
 
+  fn send_reliable(
+    &mut self,
+    socket: &UdpSocket,
+    reliability_byte: u8,
+    id: u64,
+    bytes: &[u8],
+  ) -> BackedUpPacket {
+    let mut data = vec![reliability_byte];
+    write_u64(&mut data, id);
+    data.extend(bytes);
+
+    let creation_time = Instant::now();
+    let retry_delay = self.retry_delay();
+    let send_time = if self.send_with_silenced_errors(socket, &data) {
+      creation_time
+    } else {
+      creation_time - retry_delay
+    };
+
+    self.network_stats.borrow_mut().reliable_packets_sent += 1;
+
+    BackedUpPacket {
+      id,
+      creation_time,
+      send_time,
+      retry_delay,
+      resends: 0,
+      channel: 0,
+      data,
+    }
+  }
+
   pub fn resend_backed_up_packets(&mut self, socket: &UdpSocket) {
     use itertools::Itertools;
 
     self.remaining_budget = self.resend_budget;
 
+    // backoff makes retry delays differ between packets, older packets may not be due yet
     let reliable_iter = self
       .backed_up_reliable
       .iter_mut()
-      .take_while(|backed_up_packet| backed_up_packet.send_time.elapsed() >= self.retry_delay);
+      .filter(|backed_up_packet| backed_up_packet.is_due());
+
+    let reliable_sequenced_iter = self
+      .backed_up_reliable_sequenced
+      .iter_mut()
+      .filter(|backed_up_packet| backed_up_packet.is_due());
 
     let reliable_ordered_iter = self
       .backed_up_reliable_ordered
       .iter_mut()
-      .take_while(|backed_up_packet| backed_up_packet.send_time.elapsed() >= self.retry_delay);
+      .filter(|backed_up_packet| backed_up_packet.is_due());
 
     let current_time = Instant::now();
+    let mut network_stats = self.network_stats.borrow_mut();
+    let mut resent = false;
+
+    let backed_up_iter = reliable_iter
+      .interleave(reliable_sequenced_iter)
+      .interleave(reliable_ordered_iter);
 
-    for backed_up_packet in reliable_iter.interleave(reliable_ordered_iter) {
+    for backed_up_packet in backed_up_iter {
       if self.remaining_budget < 0 {
         break;
       }
 
-      backed_up_packet.send_time = current_time;
-
       let buf = &backed_up_packet.data;
 
-      if socket.send_to(buf, self.socket_address).is_err() {
+      let sent = send_datagram(
+        socket,
+        self.socket_address,
+        self.network_simulator.as_deref(),
+        self.encrypted_session.as_deref(),
+        buf,
+      );
+
+      if !sent {
         // socket buffer is probably full
         break;
       }
 
+      network_stats.record_resent(buf.len());
       self.remaining_budget -= buf.len() as isize;
+      self.rate_allowance -= buf.len() as f64;
+
+      backed_up_packet.send_time = current_time;
+      backed_up_packet.retry_delay = (backed_up_packet.retry_delay * 2).min(MAX_RETRY_DELAY);
+      backed_up_packet.resends += 1;
+      resent = true;
+    }
+
+    if resent {
+      // round trip samples from resent packets are ambiguous and skipped,
+      // new packets wait longer until a sample shows the real round trip time
+      self.backoff = (self.backoff + 1).min(MAX_BACKOFF);
     }
   }
 
+  /// Sends the next probe in the search for the path MTU, probes are sent unreliably and resent after the retry delay
+  pub fn probe_path_mtu(&mut self, socket: &UdpSocket) {
+    let size = match self.path_mtu.next_probe(self.retry_delay()) {
+      Some(size) => size,
+      None => return,
+    };
+
+    // the probe should be the size being tested after encryption
+    let probe = build_packet(ServerPacket::PathMtuProbe {
+      size: size as u16,
+      padding: size - PATH_MTU_PROBE_HEADER_SIZE - self.encryption_overhead(),
+    });
+
+    self.send_unfragmented(socket, Reliability::Unreliable, &probe);
+  }
+
+  pub fn path_mtu_probe_received(&mut self, size: u16) {
+    self.path_mtu.confirm(size.into());
+  }
+
+  pub fn has_backed_up_packets(&self) -> bool {
+    !self.backed_up_reliable.is_empty()
+      || !self.backed_up_reliable_sequenced.is_empty()
+      || !self.backed_up_reliable_ordered.is_empty()
+      || self.queues.iter().any(|queue| !queue.is_empty())
+  }
+
   pub fn acknowledged(&mut self, reliability: Reliability, id: u64) {
     let acknowledged_packet = match reliability {
       Reliability::Unreliable | Reliability::UnreliableSequenced => {
@@ -149,18 +461,28 @@ impl PacketShipper {
         None
       }
       Reliability::Reliable => self.acknowledged_reliable(id),
+      Reliability::ReliableSequenced(_) => self.acknowledged_reliable_sequenced(id),
       Reliability::ReliableOrdered => self.acknowledged_reliable_ordered(id),
     };
 
     if let Some(packet) = acknowledged_packet {
-      let half_ack_speed = packet.creation_time.elapsed() / 2;
-
-      if half_ack_speed < self.retry_delay {
-        self.retry_delay = half_ack_speed;
+      if packet.resends == 0 {
+        // resent packets are skipped, the ack could be for any of the copies
+        self
+          .network_stats
+          .borrow_mut()
+          .record_rtt_sample(packet.creation_time.elapsed());
+
+        self.backoff = 0;
       }
     }
   }
 
+  /// Retransmission timeout for newly sent packets, based on the smoothed round trip time and recent timeouts
+  pub fn retry_delay(&self) -> Duration {
+    calculate_retry_delay(&self.network_stats.borrow(), self.backoff)
+  }
+
   fn acknowledged_reliable(&mut self, id: u64) -> Option<BackedUpPacket> {
     self
       .backed_up_reliable
@@ -169,6 +491,22 @@ impl PacketShipper {
       .map(|position| self.backed_up_reliable.remove(position))
   }
 
+  fn acknowledged_reliable_sequenced(&mut self, id: u64) -> Option<BackedUpPacket> {
+    let position = self
+      .backed_up_reliable_sequenced
+      .iter()
+      .position(|backed_up| backed_up.id == id)?;
+
+    let packet = self.backed_up_reliable_sequenced.remove(position);
+
+    // the client will drop older packets on this channel, no need to resend them
+    self
+      .backed_up_reliable_sequenced
+      .retain(|backed_up| backed_up.channel != packet.channel || backed_up.id > id);
+
+    Some(packet)
+  }
+
   fn acknowledged_reliable_ordered(&mut self, id: u64) -> Option<BackedUpPacket> {
     self
       .backed_up_reliable_ordered
@@ -186,7 +524,387 @@ impl PacketShipper {
     }
 
     self.remaining_budget -= buf.len() as isize;
+    self.rate_allowance -= buf.len() as f64;
+
+    if !self.send_datagram(socket, buf) {
+      return false;
+    }
+
+    self.network_stats.borrow_mut().record_sent(buf.len());
+
+    true
+  }
+
+  fn send_datagram(&self, socket: &UdpSocket, buf: &[u8]) -> bool {
+    send_datagram(
+      socket,
+      self.socket_address,
+      self.network_simulator.as_deref(),
+      self.encrypted_session.as_deref(),
+      buf,
+    )
+  }
+}
+
+impl BackedUpPacket {
+  fn is_due(&self) -> bool {
+    self.send_time.elapsed() >= self.retry_delay
+  }
+}
+
+// free function so resends can borrow the shipper's fields separately, also used for acks by the PacketSorter
+pub(super) fn send_datagram(
+  socket: &UdpSocket,
+  socket_address: std::net::SocketAddr,
+  network_simulator: Option<&NetworkSimulator>,
+  encrypted_session: Option<&EncryptedSession>,
+  buf: &[u8],
+) -> bool {
+  let sealed;
+
+  let buf = match encrypted_session {
+    Some(encrypted_session) => {
+      sealed = encrypted_session.seal(buf);
+      &sealed
+    }
+    None => buf,
+  };
+
+  match network_simulator {
+    Some(network_simulator) => network_simulator.send_to(socket, buf, socket_address),
+    None => socket.send_to(buf, socket_address).is_ok(),
+  }
+}
+
+// a tenth of a second worth of data, with room for at least a couple of full sized packets
+fn calculate_max_rate_allowance(rate_limit: usize, max_payload_size: usize) -> f64 {
+  (rate_limit as f64 / 10.0).max((max_payload_size * 2) as f64)
+}
+
+// TCP style retransmission timeout from RFC 6298, doubled for each backoff
+fn calculate_retry_delay(network_stats: &NetworkStats, backoff: u32) -> Duration {
+  let retry_delay = match network_stats.rtt {
+    Some(rtt) => (rtt + network_stats.jitter * 4).clamp(MIN_RETRY_DELAY, MAX_RETRY_DELAY),
+    None => INITIAL_RETRY_DELAY,
+  };
+
+  (retry_delay * 2u32.pow(backoff)).min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::packets::sequence_channel;
+
+  #[test]
+  fn retry_delay() {
+    let mut network_stats = NetworkStats::default();
+    assert_eq!(
+      calculate_retry_delay(&network_stats, 0),
+      INITIAL_RETRY_DELAY
+    );
+
+    network_stats.rtt = Some(Duration::from_millis(200));
+    network_stats.jitter = Duration::from_millis(25);
+    assert_eq!(
+      calculate_retry_delay(&network_stats, 0),
+      Duration::from_millis(300)
+    );
+    assert_eq!(
+      calculate_retry_delay(&network_stats, 2),
+      Duration::from_millis(1200)
+    );
+    assert_eq!(
+      calculate_retry_delay(&network_stats, MAX_BACKOFF),
+      MAX_RETRY_DELAY
+    );
+
+    network_stats.rtt = Some(Duration::from_millis(5));
+    network_stats.jitter = Duration::ZERO;
+    assert_eq!(calculate_retry_delay(&network_stats, 0), MIN_RETRY_DELAY);
+
+    network_stats.rtt = Some(Duration::from_secs(10));
+    assert_eq!(calculate_retry_delay(&network_stats, 0), MAX_RETRY_DELAY);
+  }
+
+  #[test]
+  fn sequence_channels() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    let mut shipper = PacketShipper::new(
+      socket.local_addr().unwrap(),
+      1400,
+      65536,
+      0,
+      Rc::new(RefCell::new(NetworkStats::default())),
+    );
+
+    let emotion_channel = Reliability::ReliableSequenced(sequence_channel("emotion"));
+    let money_channel = Reliability::ReliableSequenced(sequence_channel("money"));
+
+    shipper.send(
+      &socket,
+      emotion_channel,
+      ServerPacket::Emotion { emotion: 0 },
+    );
+    shipper.send(&socket, money_channel, ServerPacket::Money { money: 5 });
+    shipper.send(&socket, money_channel, ServerPacket::Money { money: 10 });
+    shipper.acknowledged(money_channel, 2);
+
+    assert_eq!(
+      shipper
+        .backed_up_reliable_sequenced
+        .iter()
+        .map(|backed_up| backed_up.id)
+        .collect::<Vec<_>>(),
+      vec![0],
+      "acks should only replace older packets on the same channel"
+    );
+  }
+
+  #[test]
+  fn backoff() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    // nothing acknowledges packets sent to this socket
+    let client_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    let network_stats = Rc::new(RefCell::new(NetworkStats::default()));
+    network_stats.borrow_mut().rtt = Some(Duration::from_millis(100));
+
+    let mut shipper = PacketShipper::new(
+      client_socket.local_addr().unwrap(),
+      1400,
+      65536,
+      0,
+      network_stats,
+    );
+
+    shipper.send(&socket, Reliability::Reliable, ServerPacket::Heartbeat);
+    std::thread::sleep(shipper.retry_delay());
+    shipper.resend_backed_up_packets(&socket);
+
+    assert_eq!(
+      shipper.retry_delay(),
+      Duration::from_millis(200),
+      "timeouts should back off the retry delay for new packets"
+    );
+
+    shipper.send(&socket, Reliability::Reliable, ServerPacket::Heartbeat);
+    shipper.acknowledged(Reliability::Reliable, 0);
+    assert_eq!(
+      shipper.retry_delay(),
+      Duration::from_millis(200),
+      "acks for resent packets shouldn't reset the backoff"
+    );
+
+    shipper.acknowledged(Reliability::Reliable, 1);
+    assert!(shipper.retry_delay() < Duration::from_millis(200));
+  }
+  #[test]
+  fn fragmentation() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let socket_address = socket.local_addr().unwrap();
+    socket
+      .set_read_timeout(Some(Duration::from_secs(1)))
+      .unwrap();
+
+    let network_stats = Rc::new(RefCell::new(NetworkStats::default()));
+    let mut shipper = PacketShipper::new(socket_address, 1000, 65536, 0, network_stats);
+
+    shipper.send_bytes(&socket, Reliability::Unreliable, &[0; 500]);
+    shipper.send_bytes(&socket, Reliability::Unreliable, &[0; 3000]);
+
+    let mut buf = [0; 2000];
+    let mut sizes = Vec::new();
+
+    for _ in 0..5 {
+      sizes.push(socket.recv(&mut buf).unwrap());
+    }
+
+    assert_eq!(sizes[0], 1 + 500);
+    assert!(sizes[1..].iter().all(|size| *size <= 1000));
+    assert_eq!(
+      sizes[1..].iter().sum::<usize>(),
+      3000 + 4 * (1 + FRAGMENT_HEADER_SIZE)
+    );
+
+    shipper.send_bytes(&socket, Reliability::UnreliableSequenced, &[0; 3000]);
+    shipper.send_bytes(&socket, Reliability::ReliableSequenced(0), &[0; 3000]);
+
+    socket
+      .set_read_timeout(Some(Duration::from_millis(100)))
+      .unwrap();
+    assert!(
+      socket.recv(&mut buf).is_err(),
+      "oversized sequenced packets should be dropped instead of fragmented"
+    );
+    assert!(shipper.backed_up_reliable_sequenced.is_empty());
+  }
+
+  #[test]
+  fn priorities() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let socket_address = socket.local_addr().unwrap();
+    socket
+      .set_read_timeout(Some(Duration::from_secs(1)))
+      .unwrap();
+
+    let network_stats = Rc::new(RefCell::new(NetworkStats::default()));
+    let mut shipper = PacketShipper::new(socket_address, 1000, 65536, 1, network_stats);
+
+    let asset_stream = build_packet(ServerPacket::AssetStream { data: &[0; 3000] });
+    shipper.send_bytes(&socket, Reliability::ReliableOrdered, &asset_stream);
+    shipper.send(&socket, Reliability::Unreliable, ServerPacket::Heartbeat);
+
+    while shipper.queues.iter().any(|queue| !queue.is_empty()) {
+      shipper.rate_allowance = 1000.0;
+      shipper.send_queued_packets(&socket);
+    }
+
+    let mut buf = [0; 2000];
+    let mut reliability_bytes = Vec::new();
+
+    for _ in 0..5 {
+      socket.recv(&mut buf).unwrap();
+      reliability_bytes.push(buf[0]);
+    }
+
+    assert_eq!(
+      reliability_bytes,
+      [4, 4, 0, 4, 4],
+      "the heartbeat should skip the queued asset stream"
+    );
+  }
+
+  #[test]
+  fn dependencies() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let socket_address = socket.local_addr().unwrap();
+    socket
+      .set_read_timeout(Some(Duration::from_secs(1)))
+      .unwrap();
+
+    let network_stats = Rc::new(RefCell::new(NetworkStats::default()));
+    let mut shipper = PacketShipper::new(socket_address, 1000, 65536, 1, network_stats);
+
+    let packets = [
+      // message_player streams the mugshot before the message
+      build_packet(ServerPacket::AssetStream { data: &[0; 3000] }),
+      build_packet(ServerPacket::Message {
+        message: "hi",
+        mug_texture_path: "/server/assets/mug.png",
+        mug_animation_path: "/server/assets/mug.animation",
+      }),
+      build_packet(ServerPacket::Heartbeat),
+      build_packet(ServerPacket::ActorConnected {
+        ticket: "1",
+        name: "bot",
+        texture_path: "",
+        animation_path: "",
+        direction: crate::net::Direction::Down,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+        solid: false,
+        warp_in: false,
+        scale_x: 1.0,
+        scale_y: 1.0,
+        rotation: 0.0,
+        minimap_color: (0, 0, 0, 0),
+        animation: None,
+        handle: 0,
+      }),
+      build_packet(ServerPacket::ActorMove {
+        ticket: "1",
+        x: 1.0,
+        y: 0.0,
+        z: 0.0,
+        direction: crate::net::Direction::Down,
+      }),
+    ];
+
+    for packet in &packets {
+      shipper.send_bytes(&socket, Reliability::ReliableOrdered, packet);
+    }
+
+    while shipper.queues.iter().any(|queue| !queue.is_empty()) {
+      shipper.rate_allowance = 1000.0;
+      shipper.send_queued_packets(&socket);
+    }
+
+    let fragment_id = &build_packet(ServerPacket::Fragment {
+      id: 0,
+      index: 0,
+      count: 1,
+      data: &[],
+    })[..2];
+
+    let mut buf = [0; 2000];
+    let mut packet_ids = Vec::new();
+
+    for _ in 0..8 {
+      socket.recv(&mut buf).unwrap();
+      // reliability + reliability id
+      packet_ids.push(buf[9..11].to_vec());
+    }
+
+    let expected_ids: Vec<_> = [fragment_id, fragment_id, &packets[2][..2]]
+      .into_iter()
+      .chain([fragment_id, fragment_id])
+      .chain([&packets[1][..2], &packets[3][..2], &packets[4][..2]])
+      .map(|id| id.to_vec())
+      .collect();
+
+    assert_eq!(
+      packet_ids, expected_ids,
+      "the message should wait for the mugshot, and movement should wait for the actor"
+    );
+
+    // transfer_player followed by slide_player_camera and lock_player_input
+    let packets = [
+      build_packet(ServerPacket::AssetStream { data: &[0; 3000] }),
+      build_packet(ServerPacket::TransferStart),
+      build_packet(ServerPacket::Heartbeat),
+      build_packet(ServerPacket::SlideCamera {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+        duration: 1.0,
+      }),
+      build_packet(ServerPacket::LockInput),
+    ];
+
+    for packet in &packets {
+      shipper.send_bytes(&socket, Reliability::ReliableOrdered, packet);
+    }
+
+    while shipper.queues.iter().any(|queue| !queue.is_empty()) {
+      shipper.rate_allowance = 1000.0;
+      shipper.send_queued_packets(&socket);
+    }
+
+    let mut packet_ids = Vec::new();
+
+    for _ in 0..8 {
+      socket.recv(&mut buf).unwrap();
+      packet_ids.push(buf[9..11].to_vec());
+    }
+
+    let position = |packet: &[u8]| {
+      packet_ids
+        .iter()
+        .position(|id| id[..] == packet[..2])
+        .unwrap()
+    };
 
-    socket.send_to(buf, self.socket_address).is_ok()
+    assert!(
+      position(&packets[2]) < position(&packets[1]),
+      "independent packets should still overtake"
+    );
+    assert!(
+      position(&packets[1]) < position(&packets[3])
+        && position(&packets[3]) < position(&packets[4]),
+      "camera and input packets should wait for the transfer"
+    );
   }
 }
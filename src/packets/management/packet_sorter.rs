@@ -1,6 +1,17 @@
-use super::super::{build_packet, ClientPacket, PacketHeaders, ServerPacket};
-use super::{get_reliability_byte, Reliability};
+use super::super::{
+  build_packet, parse_client_packet_body, ClientPacket, EncryptedSession, PacketHeaders,
+  ServerPacket,
+};
+use super::packet_shipper::send_datagram;
+use super::{get_reliability_byte, FragmentAssembler, NetworkSimulator, NetworkStats, Reliability};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::net::UdpSocket;
+use std::rc::Rc;
+use std::sync::Arc;
+
+// clients choose channels, the least recently used channel is forgotten past this
+const MAX_SEQUENCED_CHANNELS: usize = 64;
 
 struct BackedUpPacket {
   pub id: u64,
@@ -11,37 +22,70 @@ pub struct PacketSorter {
   socket_address: std::net::SocketAddr,
   next_reliable: u64,
   next_unreliable_sequenced: u64,
+  /// next id for each channel
+  next_reliable_sequenced: HashMap<u64, u64>,
   next_reliable_ordered: u64,
   missing_reliable: Vec<u64>,
   backed_up_ordered_packets: Vec<BackedUpPacket>,
   last_message_time: std::time::Instant,
+  network_stats: Rc<RefCell<NetworkStats>>,
+  fragment_assembler: FragmentAssembler,
+  encrypted_session: Option<Arc<EncryptedSession>>,
+  network_simulator: Option<Rc<NetworkSimulator>>,
 }
 
 impl PacketSorter {
-  pub fn new(socket_address: std::net::SocketAddr) -> PacketSorter {
+  /// Fragmented packets larger than max_packet_size are dropped
+  pub fn new(socket_address: std::net::SocketAddr, max_packet_size: usize) -> PacketSorter {
     PacketSorter {
       socket_address,
       next_reliable: 0,
       next_unreliable_sequenced: 0,
+      next_reliable_sequenced: HashMap::new(),
       next_reliable_ordered: 0,
       missing_reliable: Vec::new(),
       backed_up_ordered_packets: Vec::new(),
       last_message_time: std::time::Instant::now(),
+      network_stats: Rc::new(RefCell::new(NetworkStats::default())),
+      fragment_assembler: FragmentAssembler::new(max_packet_size),
+      encrypted_session: None,
+      network_simulator: None,
     }
   }
 
+  /// Shared with the PacketShipper for this client
+  pub fn get_network_stats(&self) -> Rc<RefCell<NetworkStats>> {
+    self.network_stats.clone()
+  }
+
+  /// Encrypts acks sent to the client
+  pub fn set_encrypted_session(&mut self, encrypted_session: Arc<EncryptedSession>) {
+    self.encrypted_session = Some(encrypted_session);
+  }
+
+  /// Applies simulated outgoing conditions to acks, see [`super::PacketOrchestrator::get_network_simulator`]
+  pub fn set_network_simulator(&mut self, network_simulator: Rc<NetworkSimulator>) {
+    self.network_simulator = Some(network_simulator);
+  }
+
   pub fn get_last_message_time(&self) -> &std::time::Instant {
     &self.last_message_time
   }
 
+  /// `time` is the server's time from [`crate::net::Net::get_time`], used for silence checks
   #[allow(clippy::comparison_chain)]
   pub fn sort_packet(
     &mut self,
     socket: &UdpSocket,
+    time: std::time::Instant,
     headers: PacketHeaders,
     packet: ClientPacket,
   ) -> Vec<ClientPacket> {
-    self.last_message_time = std::time::Instant::now();
+    self.last_message_time = time;
+    self
+      .network_stats
+      .borrow_mut()
+      .record_received(headers.size);
 
     let packets = match headers.reliability {
       Reliability::Unreliable => vec![packet],
@@ -81,6 +125,24 @@ impl PacketSorter {
           vec![]
         }
       }
+      Reliability::ReliableSequenced(channel) => {
+        // acknowledge stale packets too, otherwise the client would keep resending them
+        self.send_ack(socket, &headers);
+
+        let next_id = self
+          .next_reliable_sequenced
+          .get(&channel)
+          .copied()
+          .unwrap_or_default();
+
+        if headers.id < next_id {
+          // ignore old packets
+          vec![]
+        } else {
+          self.track_sequenced_channel(channel, headers.id + 1);
+          vec![packet]
+        }
+      }
       Reliability::ReliableOrdered => {
         self.send_ack(socket, &headers);
 
@@ -140,7 +202,41 @@ impl PacketSorter {
       }
     };
 
+    // reassembling after sorting to keep fragmented packets in order
     packets
+      .into_iter()
+      .filter_map(|packet| match packet {
+        ClientPacket::Fragment {
+          id,
+          index,
+          count,
+          data,
+        } => self
+          .fragment_assembler
+          .add_fragment(id, index, count, data)
+          .and_then(|data| parse_client_packet_body(&data)),
+        packet => Some(packet),
+      })
+      .collect()
+  }
+
+  fn track_sequenced_channel(&mut self, channel: u64, next_id: u64) {
+    if self.next_reliable_sequenced.len() >= MAX_SEQUENCED_CHANNELS
+      && !self.next_reliable_sequenced.contains_key(&channel)
+    {
+      // ids are shared between channels, the lowest belongs to the least recently used channel
+      let least_recent_channel = self
+        .next_reliable_sequenced
+        .iter()
+        .min_by_key(|(_, next_id)| **next_id)
+        .map(|(channel, _)| *channel);
+
+      if let Some(least_recent_channel) = least_recent_channel {
+        self.next_reliable_sequenced.remove(&least_recent_channel);
+      }
+    }
+
+    self.next_reliable_sequenced.insert(channel, next_id);
   }
 
   fn send_ack(&self, socket: &UdpSocket, headers: &PacketHeaders) {
@@ -151,6 +247,87 @@ impl PacketSorter {
       id: headers.id,
     }));
 
-    let _ = socket.send_to(&buf, self.socket_address);
+    let sent = send_datagram(
+      socket,
+      self.socket_address,
+      self.network_simulator.as_deref(),
+      self.encrypted_session.as_deref(),
+      &buf,
+    );
+
+    if sent {
+      self.network_stats.borrow_mut().record_sent(buf.len());
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Instant;
+
+  fn sort_sequenced(sorter: &mut PacketSorter, socket: &UdpSocket, channel: u64, id: u64) -> usize {
+    let headers = PacketHeaders {
+      reliability: Reliability::ReliableSequenced(channel),
+      id,
+      size: 0,
+    };
+
+    sorter
+      .sort_packet(socket, Instant::now(), headers, ClientPacket::Heartbeat)
+      .len()
+  }
+
+  #[test]
+  fn reliable_sequenced() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut sorter = PacketSorter::new(socket.local_addr().unwrap(), 1024);
+
+    assert_eq!(sort_sequenced(&mut sorter, &socket, 0, 0), 1);
+    assert_eq!(sort_sequenced(&mut sorter, &socket, 0, 2), 1);
+    assert_eq!(
+      sort_sequenced(&mut sorter, &socket, 0, 1),
+      0,
+      "stale packets should be dropped"
+    );
+    assert_eq!(sort_sequenced(&mut sorter, &socket, 0, 2), 0);
+    assert_eq!(sort_sequenced(&mut sorter, &socket, 0, 3), 1);
+    assert_eq!(
+      sort_sequenced(&mut sorter, &socket, 1, 1),
+      1,
+      "channels should be sequenced separately"
+    );
+    assert_eq!(
+      sorter.get_network_stats().borrow().packets_sent,
+      6,
+      "every packet should be acknowledged"
+    );
+  }
+
+  #[test]
+  fn simulated_acks() {
+    use super::super::NetworkConditions;
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+      .set_read_timeout(Some(std::time::Duration::from_millis(100)))
+      .unwrap();
+
+    let conditions = NetworkConditions {
+      drop_rate: 100.0,
+      ..NetworkConditions::default()
+    };
+
+    let mut sorter = PacketSorter::new(socket.local_addr().unwrap(), 1024);
+    sorter.set_network_simulator(Rc::new(NetworkSimulator::new(
+      socket.try_clone().unwrap(),
+      conditions,
+    )));
+
+    assert_eq!(sort_sequenced(&mut sorter, &socket, 0, 0), 1);
+    assert!(
+      socket.recv(&mut [0; 64]).is_err(),
+      "acks should be dropped by the simulated conditions"
+    );
   }
 }
@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+// QUIC's minimum datagram size, fits within IPv6's minimum MTU
+const MIN_PAYLOAD_SIZE: usize = 1200;
+// a lost probe could be congestion, only give up on a size after a few tries
+const MAX_PROBE_ATTEMPTS: u32 = 3;
+// stop searching once the range is this small
+const SEARCH_GRANULARITY: usize = 16;
+
+struct Probe {
+  size: usize,
+  send_time: Instant,
+  attempts: u32,
+}
+
+/// Binary searches for the largest payload size reaching a client, between MIN_PAYLOAD_SIZE and the configured max
+pub struct PathMtu {
+  confirmed_size: usize,
+  /// smallest size assumed to be too large
+  failed_size: usize,
+  probe: Option<Probe>,
+}
+
+impl PathMtu {
+  pub fn new(max_payload_size: usize) -> PathMtu {
+    PathMtu {
+      confirmed_size: max_payload_size.min(MIN_PAYLOAD_SIZE),
+      failed_size: max_payload_size + 1,
+      probe: None,
+    }
+  }
+
+  /// Largest payload size confirmed to reach the client
+  pub fn get_payload_size(&self) -> usize {
+    self.confirmed_size
+  }
+
+  pub fn is_searching(&self) -> bool {
+    self.failed_size - self.confirmed_size > SEARCH_GRANULARITY
+  }
+
+  /// Returns the size for the next probe, if a probe should be sent
+  pub fn next_probe(&mut self, timeout: Duration) -> Option<usize> {
+    if let Some(probe) = &mut self.probe {
+      if probe.send_time.elapsed() < timeout {
+        return None;
+      }
+
+      if probe.attempts < MAX_PROBE_ATTEMPTS {
+        probe.send_time = Instant::now();
+        probe.attempts += 1;
+        return Some(probe.size);
+      }
+
+      self.failed_size = probe.size;
+      self.probe = None;
+    }
+
+    if !self.is_searching() {
+      return None;
+    }
+
+    let size = (self.confirmed_size + self.failed_size) / 2;
+
+    self.probe = Some(Probe {
+      size,
+      send_time: Instant::now(),
+      attempts: 1,
+    });
+
+    Some(size)
+  }
+
+  pub fn confirm(&mut self, size: usize) {
+    if size <= self.confirmed_size || size >= self.failed_size {
+      // outdated or bogus
+      return;
+    }
+
+    self.confirmed_size = size;
+
+    if matches!(&self.probe, Some(probe) if probe.size <= size) {
+      self.probe = None;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn search() {
+    let mut path_mtu = PathMtu::new(1400);
+    assert_eq!(path_mtu.get_payload_size(), 1200);
+
+    let size = path_mtu.next_probe(Duration::ZERO).unwrap();
+    assert_eq!(size, 1300);
+    path_mtu.confirm(size);
+    assert_eq!(path_mtu.get_payload_size(), 1300);
+
+    let size = path_mtu.next_probe(Duration::ZERO).unwrap();
+    assert_eq!(size, 1350);
+
+    for _ in 1..MAX_PROBE_ATTEMPTS {
+      assert_eq!(path_mtu.next_probe(Duration::ZERO), Some(size));
+    }
+
+    // lost every attempt
+    assert_eq!(path_mtu.next_probe(Duration::ZERO), Some(1325));
+    path_mtu.confirm(1325);
+    path_mtu.confirm(1350);
+    assert_eq!(
+      path_mtu.get_payload_size(),
+      1325,
+      "sizes assumed too large should not be confirmed"
+    );
+
+    assert_eq!(path_mtu.next_probe(Duration::ZERO), Some(1337));
+    path_mtu.confirm(1337);
+    assert!(!path_mtu.is_searching());
+    assert_eq!(path_mtu.next_probe(Duration::ZERO), None);
+
+    let mut path_mtu = PathMtu::new(500);
+    assert_eq!(path_mtu.get_payload_size(), 500);
+    assert_eq!(path_mtu.next_probe(Duration::ZERO), None);
+  }
+}
@@ -0,0 +1,26 @@
+/// Scheduling class for outgoing packets, higher classes are sent first when a client's outgoing rate is limited.
+/// Packets within a class keep their order, packets in a higher class can overtake packets in a lower class
+/// unless they have a `Dependency` on them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+  /// Asset streams, actor connections, and anything not classified
+  Asset,
+  /// Textboxes, boards, shops, and other player facing state
+  Widget,
+  /// Actor movement, animation, and camera packets
+  Movement,
+}
+
+impl Priority {
+  pub const COUNT: usize = 3;
+}
+
+/// Packets queued in lower classes that a packet can't overtake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dependency {
+  None,
+  /// Packets naming an actor wait for queued ActorConnected packets
+  Actors,
+  /// Packets naming assets wait for every packet queued in a lower class
+  All,
+}
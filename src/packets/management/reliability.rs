@@ -1,15 +1,22 @@
+use std::hash::{Hash, Hasher};
+
 #[derive(Debug, Clone, Copy)]
 pub enum Reliability {
   Unreliable,
   UnreliableSequenced,
   Reliable,
-  // ReliableSequenced,
+  /// Resent until acknowledged, packets older than the newest received packet on the same channel are dropped.
+  /// Use a channel for each piece of state, see [`sequence_channel`]. May arrive before ordered packets sent earlier
+  ReliableSequenced(u64),
   ReliableOrdered,
 }
 
 impl Reliability {
   pub fn is_reliable(&self) -> bool {
-    matches!(self, Reliability::Reliable | Reliability::ReliableOrdered)
+    matches!(
+      self,
+      Reliability::Reliable | Reliability::ReliableSequenced(_) | Reliability::ReliableOrdered
+    )
   }
 }
 
@@ -17,7 +24,8 @@ pub fn get_reliability(reliability_byte: u8) -> Reliability {
   match reliability_byte {
     1 => Reliability::UnreliableSequenced,
     2 => Reliability::Reliable,
-    // 3 => Reliability::ReliableSequenced,
+    // the channel follows the id in headers
+    3 => Reliability::ReliableSequenced(0),
     4 => Reliability::ReliableOrdered,
     _ => Reliability::Unreliable,
   }
@@ -28,7 +36,15 @@ pub fn get_reliability_byte(reliability: &Reliability) -> u8 {
     Reliability::Unreliable => 0,
     Reliability::UnreliableSequenced => 1,
     Reliability::Reliable => 2,
-    // Reliability::ReliableSequenced => 3,
+    Reliability::ReliableSequenced(_) => 3,
     Reliability::ReliableOrdered => 4,
   }
 }
+
+/// Channel for ReliableSequenced packets, such as `("health", player_id)`
+pub fn sequence_channel(key: impl Hash) -> u64 {
+  // unkeyed, the same key always gives the same channel
+  let mut hasher = std::collections::hash_map::DefaultHasher::new();
+  key.hash(&mut hasher);
+  hasher.finish()
+}
@@ -3,6 +3,12 @@ pub mod bytes;
 mod client_packets;
 pub use client_packets::*;
 
+mod compression;
+pub use compression::Compression;
+
+mod encryption;
+pub use encryption::*;
+
 mod server_packets;
 pub use server_packets::*;
 
@@ -12,7 +18,9 @@ pub use management::*;
 pub struct PacketHeaders {
   pub reliability: Reliability,
   pub id: u64,
+  /// size of the whole packet in bytes
+  pub size: usize,
 }
 
 pub const VERSION_ID: &str = "https://github.com/ArthurCose/Scriptable-OpenNetBattle-Server";
-pub const VERSION_ITERATION: u64 = 42;
+pub const VERSION_ITERATION: u64 = 54;
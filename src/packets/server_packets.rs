@@ -1,10 +1,11 @@
 // Increment VERSION_ITERATION src/packets/mod.rs if packets are added or modified
 
 use super::bytes::*;
-use super::{VERSION_ID, VERSION_ITERATION};
+use super::{Compression, Dependency, Priority, VERSION_ID, VERSION_ITERATION};
 use crate::net::actor_property_animation::{ActorProperty, Ease, KeyFrame};
 use crate::net::{Asset, AssetData, BbsPost, Direction, PackageCategory, PackageInfo, ShopItem};
 
+#[derive(Clone, Copy)]
 #[repr(u16)]
 enum ServerPacketId {
   VersionInfo,
@@ -69,12 +70,52 @@ enum ServerPacketId {
   ActorPropertyKeyFrames,
   ActorMinimapColor,
   OfferPackage,
+  LoginQueue,
+  Fragment,
+  PathMtuProbe,
+  ActorMoveBatch,
+}
+
+/// Position in an ActorMoveBatch, in 1/64ths of a tile
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchedPosition {
+  Absolute {
+    x: i32,
+    y: i32,
+    z: i32,
+  },
+  /// Relative to the actor's position in the batch `baseline_age` batches before this one
+  Delta {
+    baseline_age: u8,
+    x: i16,
+    y: i16,
+    z: i16,
+  },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchedMove {
+  /// From ActorConnected
+  pub handle: u16,
+  pub direction: Direction,
+  pub position: BatchedPosition,
 }
 
 #[derive(Debug)]
 pub enum ServerPacket<'a> {
   VersionInfo {
     max_payload_size: usize,
+    maintenance_message: Option<&'a str>,
+    /// Compression flags the client can advertise in Login
+    supported_compression: u8,
+    /// Echoed in Connect, connections aren't tracked until the cookie is verified
+    cookie: u64,
+    /// Echoed in Connect and mixed into session keys, unique to each VersionInfo
+    server_random: [u8; 16],
+    /// X25519 key for encrypting the session, None if encryption is disabled
+    public_key: Option<&'a [u8; 32]>,
+    /// Connect is ignored unless it includes a public key
+    encryption_required: bool,
   },
   Ack {
     reliability: u8,
@@ -93,6 +134,8 @@ pub enum ServerPacket<'a> {
     spawn_y: f32,
     spawn_z: f32,
     spawn_direction: Direction,
+    /// Sent back in Resume to reconnect as this player after losing connection
+    resume_token: &'a str,
   },
   CompleteConnection,
   TransferWarp,
@@ -116,6 +159,9 @@ pub enum ServerPacket<'a> {
   AssetStreamStart {
     name: &'a str,
     asset: &'a Asset,
+    compression: Compression,
+    /// size of the data streamed through AssetStream packets, after compression
+    size: usize,
   },
   AssetStream {
     data: &'a [u8],
@@ -279,6 +325,8 @@ pub enum ServerPacket<'a> {
     rotation: f32,
     minimap_color: (u8, u8, u8, u8),
     animation: Option<&'a str>,
+    /// Identifies the actor in ActorMoveBatch, 0 if the actor's movement is sent with ActorMove
+    handle: u16,
   },
   ActorDisconnected {
     ticket: &'a str,
@@ -319,6 +367,26 @@ pub enum ServerPacket<'a> {
     ticket: &'a str,
     color: (u8, u8, u8, u8),
   },
+  LoginQueue {
+    position: usize,
+  },
+  Fragment {
+    id: u64,
+    index: u32,
+    count: u32,
+    data: &'a [u8],
+  },
+  /// Padded to test if packets of `size` bytes reach the client
+  PathMtuProbe {
+    size: u16,
+    padding: usize,
+  },
+  /// Movement for many actors, acknowledged with ActorMoveBatchAck.
+  /// Deltas are only sent against acknowledged batches, clients keep each actor's positions from the last 255 batches
+  ActorMoveBatch {
+    id: u64,
+    moves: &'a [BatchedMove],
+  },
 }
 
 pub fn build_unreliable_packet(packet: ServerPacket) -> Vec<u8> {
@@ -332,11 +400,31 @@ pub fn build_packet(packet: ServerPacket) -> Vec<u8> {
   let buf = &mut vec;
 
   match packet {
-    ServerPacket::VersionInfo { max_payload_size } => {
+    ServerPacket::VersionInfo {
+      max_payload_size,
+      maintenance_message,
+      supported_compression,
+      cookie,
+      server_random,
+      public_key,
+      encryption_required,
+    } => {
       write_u16(buf, ServerPacketId::VersionInfo as u16);
       write_string_u16(buf, VERSION_ID);
       write_u64(buf, VERSION_ITERATION);
       write_u16(buf, max_payload_size as u16);
+      write_bool(buf, maintenance_message.is_some());
+      write_string_u16(buf, maintenance_message.unwrap_or_default());
+      buf.push(supported_compression);
+      write_u64(buf, cookie);
+      buf.extend(server_random);
+      write_bool(buf, public_key.is_some());
+
+      if let Some(public_key) = public_key {
+        buf.extend(public_key);
+      }
+
+      write_bool(buf, encryption_required);
     }
     ServerPacket::Ack { reliability, id } => {
       write_u16(buf, ServerPacketId::Ack as u16);
@@ -363,6 +451,7 @@ pub fn build_packet(packet: ServerPacket) -> Vec<u8> {
       spawn_y,
       spawn_z,
       spawn_direction,
+      resume_token,
     } => {
       write_u16(buf, ServerPacketId::Login as u16);
       write_string_u16(buf, ticket);
@@ -371,6 +460,7 @@ pub fn build_packet(packet: ServerPacket) -> Vec<u8> {
       write_f32(buf, spawn_y);
       write_f32(buf, spawn_z);
       buf.push(translate_direction(spawn_direction));
+      write_string_u8(buf, resume_token);
     }
     ServerPacket::CompleteConnection => {
       write_u16(buf, ServerPacketId::CompleteConnection as u16);
@@ -406,7 +496,12 @@ pub fn build_packet(packet: ServerPacket) -> Vec<u8> {
       write_u16(buf, ServerPacketId::RemoveAsset as u16);
       write_string_u16(buf, path);
     }
-    ServerPacket::AssetStreamStart { name, asset } => {
+    ServerPacket::AssetStreamStart {
+      name,
+      asset,
+      compression,
+      size,
+    } => {
       write_u16(buf, ServerPacketId::AssetStreamStart as u16);
       write_string_u16(buf, name);
       write_u64(buf, asset.last_modified);
@@ -422,6 +517,8 @@ pub fn build_packet(packet: ServerPacket) -> Vec<u8> {
       buf.push(data_type_byte);
 
       write_u64(buf, asset.len() as u64);
+      buf.push(compression as u8);
+      write_u64(buf, size as u64);
     }
     ServerPacket::AssetStream { data } => {
       write_u16(buf, ServerPacketId::AssetStream as u16);
@@ -739,6 +836,7 @@ pub fn build_packet(packet: ServerPacket) -> Vec<u8> {
       rotation,
       minimap_color: (r, g, b, a),
       animation,
+      handle,
     } => {
       write_u16(buf, ServerPacketId::ActorConnected as u16);
       write_string_u16(buf, ticket);
@@ -765,6 +863,8 @@ pub fn build_packet(packet: ServerPacket) -> Vec<u8> {
       if let Some(animation) = animation {
         write_string_u16(buf, animation);
       }
+
+      write_u16(buf, handle);
     }
     ServerPacket::ActorDisconnected { ticket, warp_out } => {
       write_u16(buf, ServerPacketId::ActorDisconnected as u16);
@@ -865,45 +965,191 @@ pub fn build_packet(packet: ServerPacket) -> Vec<u8> {
       buf.push(b);
       buf.push(a);
     }
+    ServerPacket::LoginQueue { position } => {
+      write_u16(buf, ServerPacketId::LoginQueue as u16);
+      write_u32(buf, position as u32);
+    }
+    ServerPacket::Fragment {
+      id,
+      index,
+      count,
+      data,
+    } => {
+      write_u16(buf, ServerPacketId::Fragment as u16);
+      write_u64(buf, id);
+      write_u32(buf, index);
+      write_u32(buf, count);
+      // rest of the packet
+      buf.extend(data);
+    }
+    ServerPacket::PathMtuProbe { size, padding } => {
+      write_u16(buf, ServerPacketId::PathMtuProbe as u16);
+      write_u16(buf, size);
+      buf.resize(buf.len() + padding, 0);
+    }
+    ServerPacket::ActorMoveBatch { id, moves } => {
+      write_u16(buf, ServerPacketId::ActorMoveBatch as u16);
+      write_u64(buf, id);
+      write_u16(buf, moves.len() as u16);
+
+      for batched_move in moves {
+        write_u16(buf, batched_move.handle);
+        buf.push(translate_direction(batched_move.direction));
+
+        match batched_move.position {
+          BatchedPosition::Absolute { x, y, z } => {
+            // a baseline age of 0 marks an absolute position
+            buf.push(0);
+            write_u32(buf, x as u32);
+            write_u32(buf, y as u32);
+            write_u32(buf, z as u32);
+          }
+          BatchedPosition::Delta {
+            baseline_age,
+            x,
+            y,
+            z,
+          } => {
+            buf.push(baseline_age);
+            write_u16(buf, x as u16);
+            write_u16(buf, y as u16);
+            write_u16(buf, z as u16);
+          }
+        }
+      }
+    }
   }
 
   vec
 }
 
-pub fn create_asset_stream<'a>(
-  max_payload_size: usize,
-  name: &'a str,
-  asset: &'a Asset,
-) -> Vec<ServerPacket<'a>> {
-  // reliability type + id + packet type + data size
-  const HEADER_SIZE: usize = 1 + 8 + 2 + 2 + 16;
+/// Classifies a built packet for the PacketShipper's scheduler
+pub fn get_packet_priority(bytes: &[u8]) -> Priority {
+  use ServerPacketId::*;
+
+  const MOVEMENT_PACKETS: [ServerPacketId; 13] = [
+    Heartbeat,
+    MoveCamera,
+    SlideCamera,
+    ShakeCamera,
+    FadeCamera,
+    TrackWithCamera,
+    UnlockCamera,
+    Teleport,
+    ActorMove,
+    ActorMoveBatch,
+    ActorEmote,
+    ActorAnimate,
+    ActorPropertyKeyFrames,
+  ];
+
+  const WIDGET_PACKETS: [ServerPacketId; 19] = [
+    Health,
+    Emotion,
+    Money,
+    AddItem,
+    RemoveItem,
+    LockInput,
+    UnlockInput,
+    Message,
+    Question,
+    Quiz,
+    Prompt,
+    OpenBoard,
+    PrependPosts,
+    AppendPosts,
+    RemovePost,
+    PostSelectionAck,
+    CloseBBS,
+    ShopInventory,
+    OpenShop,
+  ];
+
+  let id = match read_packet_id(bytes) {
+    Some(id) => id,
+    None => return Priority::Asset,
+  };
+
+  if MOVEMENT_PACKETS
+    .iter()
+    .any(|packet_id| *packet_id as u16 == id)
+  {
+    Priority::Movement
+  } else if WIDGET_PACKETS
+    .iter()
+    .any(|packet_id| *packet_id as u16 == id)
+  {
+    Priority::Widget
+  } else {
+    Priority::Asset
+  }
+}
+
+/// Packets queued before this packet that it must not overtake, see `get_packet_priority`.
+/// Packets not known to be independent wait for everything queued before them
+pub fn get_packet_dependency(bytes: &[u8]) -> Dependency {
+  use ServerPacketId::*;
+
+  const ACTOR_PACKETS: [ServerPacketId; 4] =
+    [TrackWithCamera, ActorMove, ActorMoveBatch, ActorEmote];
 
-  let mut packets = vec![ServerPacket::AssetStreamStart { name, asset }];
+  // state that scripts don't expect to line up with transfers, assets, or other widgets
+  const INDEPENDENT_PACKETS: [ServerPacketId; 6] =
+    [Heartbeat, Health, Emotion, Money, AddItem, RemoveItem];
 
-  let mut bytes = match &asset.data {
-    AssetData::Text(data) => data.as_bytes(),
-    AssetData::Texture(data) => data,
-    AssetData::Audio(data) => data,
-    AssetData::Data(data) => data,
+  let id = match read_packet_id(bytes) {
+    Some(id) => id,
+    None => return Dependency::All,
   };
 
-  let mut remaining_bytes = bytes.len();
+  if ACTOR_PACKETS
+    .iter()
+    .any(|packet_id| *packet_id as u16 == id)
+  {
+    Dependency::Actors
+  } else if INDEPENDENT_PACKETS
+    .iter()
+    .any(|packet_id| *packet_id as u16 == id)
+  {
+    Dependency::None
+  } else {
+    Dependency::All
+  }
+}
 
-  while remaining_bytes > 0 {
-    let available_room = max_payload_size - HEADER_SIZE;
-    let size = if remaining_bytes < available_room {
-      remaining_bytes
-    } else {
-      available_room
-    };
+/// Packets with a `Dependency::Actors` wait for these
+pub fn is_actor_connected_packet(bytes: &[u8]) -> bool {
+  read_packet_id(bytes) == Some(ServerPacketId::ActorConnected as u16)
+}
 
-    packets.push(ServerPacket::AssetStream {
-      data: &bytes[..size],
-    });
+fn read_packet_id(bytes: &[u8]) -> Option<u16> {
+  match bytes {
+    [a, b, ..] => Some(u16::from_le_bytes([*a, *b])),
+    _ => None,
+  }
+}
+
+/// `compressed_data` is streamed in place of the asset's data when set
+pub fn create_asset_stream<'a>(
+  name: &'a str,
+  asset: &'a Asset,
+  compressed_data: Option<(Compression, &'a [u8])>,
+) -> Vec<ServerPacket<'a>> {
+  let (compression, bytes) = match compressed_data {
+    Some((compression, data)) => (compression, data),
+    None => (Compression::None, asset.data.as_bytes()),
+  };
 
-    bytes = &bytes[size..];
+  let mut packets = vec![ServerPacket::AssetStreamStart {
+    name,
+    asset,
+    compression,
+    size: bytes.len(),
+  }];
 
-    remaining_bytes -= size;
+  // AssetStream stores its size as a u16, the PacketShipper handles fragmenting each chunk
+  for data in bytes.chunks(u16::MAX as usize) {
+    packets.push(ServerPacket::AssetStream { data });
   }
 
   packets
@@ -226,6 +226,44 @@ pub fn inject_dynamic(lua_api: &mut LuaApi) {
     }
   });
 
+  lua_api.add_dynamic_function(
+    "Net",
+    "get_area_interest_radius",
+    |api_ctx, lua_ctx, params| {
+      let area_id: mlua::String = lua_ctx.unpack_multi(params)?;
+      let area_id_str = area_id.to_str()?;
+
+      let net = api_ctx.net_ref.borrow();
+
+      if let Some(area) = net.get_area(area_id_str) {
+        lua_ctx.pack_multi(area.get_map().get_interest_radius())
+      } else {
+        Err(create_area_error(area_id_str))
+      }
+    },
+  );
+
+  lua_api.add_dynamic_function(
+    "Net",
+    "set_area_interest_radius",
+    |api_ctx, lua_ctx, params| {
+      let (area_id, radius): (mlua::String, Option<f32>) = lua_ctx.unpack_multi(params)?;
+      let area_id_str = area_id.to_str()?;
+
+      let mut net = api_ctx.net_ref.borrow_mut();
+
+      if let Some(area) = net.get_area_mut(area_id_str) {
+        let map = area.get_map_mut();
+
+        map.set_interest_radius(radius.filter(|radius| *radius > 0.0));
+
+        lua_ctx.pack_multi(())
+      } else {
+        Err(create_area_error(area_id_str))
+      }
+    },
+  );
+
   lua_api.add_dynamic_function("Net", "get_song", |api_ctx, lua_ctx, params| {
     let area_id: mlua::String = lua_ctx.unpack_multi(params)?;
     let area_id_str = area_id.to_str()?;
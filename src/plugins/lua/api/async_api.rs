@@ -66,10 +66,15 @@ pub fn inject_dynamic(lua_api: &mut LuaApi) {
             Some(mlua::Value::String(lua_string))
           }
           PromiseValue::Success(success) => Some(mlua::Value::Boolean(success)),
-          PromiseValue::ServerInfo { max_message_size } => {
+          PromiseValue::ServerInfo {
+            max_message_size,
+            maintenance_message,
+          } => {
             let table = lua_ctx.create_table()?;
 
             table.set("max_message_size", max_message_size)?;
+            table.set("maintenance", maintenance_message.is_some())?;
+            table.set("maintenance_message", maintenance_message)?;
 
             Some(mlua::Value::Table(table))
           }
@@ -0,0 +1,126 @@
+use super::LuaApi;
+use crate::net::{Ban, BanTarget};
+
+// TOML stores integers as i64, leaves room for the current time
+const MAX_BAN_DURATION: f64 = (i64::MAX / 2) as f64;
+
+pub fn inject_dynamic(lua_api: &mut LuaApi) {
+  lua_api.add_dynamic_function("Net", "ban", |api_ctx, lua_ctx, params| {
+    let table: mlua::Table = lua_ctx.unpack_multi(params)?;
+
+    let target = read_target(&table)?;
+    let reason: Option<String> = table.get("reason")?;
+    let duration: Option<f64> = table.get("duration")?;
+
+    let expires_at = match duration {
+      // too long to store, treated as permanent
+      Some(duration) if duration >= MAX_BAN_DURATION => None,
+      Some(duration) if duration > 0.0 => Some(crate::net::unix_time() + duration.ceil() as u64),
+      Some(_) => {
+        return Err(mlua::Error::RuntimeError(String::from(
+          "Ban duration must be greater than 0",
+        )))
+      }
+      None => None,
+    };
+
+    let mut net = api_ctx.net_ref.borrow_mut();
+
+    net.ban(Ban {
+      target,
+      reason: reason.unwrap_or_default(),
+      expires_at,
+    });
+
+    lua_ctx.pack_multi(())
+  });
+
+  lua_api.add_dynamic_function("Net", "unban", |api_ctx, lua_ctx, params| {
+    let table: mlua::Table = lua_ctx.unpack_multi(params)?;
+    let target = read_target(&table)?;
+
+    let mut net = api_ctx.net_ref.borrow_mut();
+
+    lua_ctx.pack_multi(net.get_ban_list_mut().unban(&target))
+  });
+
+  lua_api.add_dynamic_function("Net", "list_bans", |api_ctx, lua_ctx, _| {
+    let net = api_ctx.net_ref.borrow();
+
+    let result: mlua::Result<Vec<mlua::Table>> = net
+      .get_ban_list()
+      .bans()
+      .map(|ban| {
+        let table = create_target_table(lua_ctx, &ban.target)?;
+        table.set("reason", ban.reason.as_str())?;
+        table.set("expires_at", ban.expires_at)?;
+
+        Ok(table)
+      })
+      .collect();
+
+    lua_ctx.pack_multi(result?)
+  });
+
+  lua_api.add_dynamic_function("Net", "allow", |api_ctx, lua_ctx, params| {
+    let table: mlua::Table = lua_ctx.unpack_multi(params)?;
+    let target = read_target(&table)?;
+
+    let mut net = api_ctx.net_ref.borrow_mut();
+
+    net.get_ban_list_mut().allow(target);
+
+    lua_ctx.pack_multi(())
+  });
+
+  lua_api.add_dynamic_function("Net", "disallow", |api_ctx, lua_ctx, params| {
+    let table: mlua::Table = lua_ctx.unpack_multi(params)?;
+    let target = read_target(&table)?;
+
+    let mut net = api_ctx.net_ref.borrow_mut();
+
+    lua_ctx.pack_multi(net.get_ban_list_mut().disallow(&target))
+  });
+
+  lua_api.add_dynamic_function("Net", "list_allowed", |api_ctx, lua_ctx, _| {
+    let net = api_ctx.net_ref.borrow();
+
+    let result: mlua::Result<Vec<mlua::Table>> = net
+      .get_ban_list()
+      .allowed()
+      .map(|target| create_target_table(lua_ctx, target))
+      .collect();
+
+    lua_ctx.pack_multi(result?)
+  });
+}
+
+fn read_target(table: &mlua::Table) -> mlua::Result<BanTarget> {
+  let identity: Option<String> = table.get("identity")?;
+  let ip: Option<String> = table.get("ip")?;
+
+  match (identity, ip) {
+    (Some(identity), None) => Ok(BanTarget::Identity(identity)),
+    (None, Some(ip)) => ip
+      .parse()
+      .map(BanTarget::Ip)
+      .map_err(mlua::Error::RuntimeError),
+    _ => Err(mlua::Error::RuntimeError(String::from(
+      "Expected either an identity or an ip",
+    ))),
+  }
+}
+
+fn create_target_table<'lua>(
+  lua_ctx: &'lua mlua::Lua,
+  target: &BanTarget,
+) -> mlua::Result<mlua::Table<'lua>> {
+  let table = lua_ctx.create_table()?;
+
+  match target {
+    BanTarget::Identity(identity) => table.set("identity", identity.as_str())?,
+    BanTarget::Ip(ip_range) => table.set("ip", ip_range.to_string())?,
+  }
+
+  Ok(table)
+}
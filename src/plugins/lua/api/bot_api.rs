@@ -23,7 +23,6 @@ pub fn inject_dynamic(lua_api: &mut LuaApi) {
   });
 
   lua_api.add_dynamic_function("Net", "create_bot", |api_ctx, lua_ctx, params| {
-    use std::time::Instant;
     use uuid::Uuid;
 
     // (bot_id, table) or (table, nil)
@@ -80,7 +79,7 @@ pub fn inject_dynamic(lua_api: &mut LuaApi) {
         x: x.unwrap_or(spawn.0),
         y: y.unwrap_or(spawn.1),
         z: z.unwrap_or(spawn.2),
-        last_movement_time: Instant::now(),
+        last_movement_time: net.get_time(),
         scale_x: 1.0,
         scale_y: 1.0,
         rotation: 0.0,
@@ -0,0 +1,81 @@
+use super::LuaApi;
+
+pub fn inject_dynamic(lua_api: &mut LuaApi) {
+  lua_api.add_dynamic_function("Net", "get_player_count", |api_ctx, lua_ctx, _| {
+    let net = api_ctx.net_ref.borrow();
+
+    lua_ctx.pack_multi(net.get_player_count())
+  });
+
+  lua_api.add_dynamic_function("Net", "get_login_queue_length", |api_ctx, lua_ctx, _| {
+    let net = api_ctx.net_ref.borrow();
+
+    lua_ctx.pack_multi(net.get_login_queue().len())
+  });
+
+  lua_api.add_dynamic_function("Net", "get_login_queue", |api_ctx, lua_ctx, _| {
+    let net = api_ctx.net_ref.borrow();
+
+    let result: mlua::Result<Vec<mlua::Table>> = net
+      .get_login_queue()
+      .iter()
+      .map(|queued| {
+        let table = lua_ctx.create_table()?;
+        table.set("name", queued.username.as_str())?;
+        table.set("identity", queued.identity.as_str())?;
+
+        Ok(table)
+      })
+      .collect();
+
+    lua_ctx.pack_multi(result?)
+  });
+
+  lua_api.add_dynamic_function(
+    "Net",
+    "set_login_queue_position",
+    |api_ctx, lua_ctx, params| {
+      let (identity, position): (mlua::String, usize) = lua_ctx.unpack_multi(params)?;
+      let identity_str = identity.to_str()?;
+
+      if position == 0 {
+        return Err(mlua::Error::RuntimeError(String::from(
+          "Queue positions start at 1",
+        )));
+      }
+
+      let mut net = api_ctx.net_ref.borrow_mut();
+
+      let moved = net
+        .get_login_queue_mut()
+        .move_identity(identity_str, position - 1);
+
+      lua_ctx.pack_multi(moved)
+    },
+  );
+
+  lua_api.add_dynamic_function("Net", "grant_login_priority", |api_ctx, lua_ctx, params| {
+    let identity: String = lua_ctx.unpack_multi(params)?;
+
+    let mut net = api_ctx.net_ref.borrow_mut();
+
+    net.get_login_queue_mut().grant_priority(identity);
+
+    lua_ctx.pack_multi(())
+  });
+
+  lua_api.add_dynamic_function(
+    "Net",
+    "revoke_login_priority",
+    |api_ctx, lua_ctx, params| {
+      let identity: mlua::String = lua_ctx.unpack_multi(params)?;
+      let identity_str = identity.to_str()?;
+
+      let mut net = api_ctx.net_ref.borrow_mut();
+
+      net.get_login_queue_mut().revoke_priority(identity_str);
+
+      lua_ctx.pack_multi(())
+    },
+  );
+}
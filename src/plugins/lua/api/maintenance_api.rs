@@ -0,0 +1,52 @@
+use super::LuaApi;
+
+pub fn inject_dynamic(lua_api: &mut LuaApi) {
+  lua_api.add_dynamic_function("Net", "is_in_maintenance", |api_ctx, lua_ctx, _| {
+    let net = api_ctx.net_ref.borrow();
+
+    lua_ctx.pack_multi(net.is_in_maintenance())
+  });
+
+  lua_api.add_dynamic_function("Net", "get_maintenance_message", |api_ctx, lua_ctx, _| {
+    let net = api_ctx.net_ref.borrow();
+
+    lua_ctx.pack_multi(net.get_maintenance_message())
+  });
+
+  lua_api.add_dynamic_function("Net", "set_maintenance", |api_ctx, lua_ctx, params| {
+    let (enabled, message): (bool, Option<String>) = lua_ctx.unpack_multi(params)?;
+
+    let mut net = api_ctx.net_ref.borrow_mut();
+
+    net.set_maintenance(enabled, message);
+
+    lua_ctx.pack_multi(())
+  });
+
+  lua_api.add_dynamic_function(
+    "Net",
+    "add_maintenance_exemption",
+    |api_ctx, lua_ctx, params| {
+      let identity: String = lua_ctx.unpack_multi(params)?;
+
+      let mut net = api_ctx.net_ref.borrow_mut();
+
+      net.add_maintenance_exemption(identity);
+
+      lua_ctx.pack_multi(())
+    },
+  );
+
+  lua_api.add_dynamic_function(
+    "Net",
+    "remove_maintenance_exemption",
+    |api_ctx, lua_ctx, params| {
+      let identity: mlua::String = lua_ctx.unpack_multi(params)?;
+      let identity_str = identity.to_str()?;
+
+      let mut net = api_ctx.net_ref.borrow_mut();
+
+      lua_ctx.pack_multi(net.remove_maintenance_exemption(identity_str))
+    },
+  );
+}
@@ -2,10 +2,13 @@ mod actor_property_animation;
 mod area_api;
 mod asset_api;
 mod async_api;
+mod ban_api;
 mod bot_api;
 mod logging_api;
+mod login_queue_api;
 mod lua_errors;
 mod lua_helpers;
+mod maintenance_api;
 mod object_api;
 mod player_api;
 mod player_data_api;
@@ -62,6 +65,9 @@ impl LuaApi {
     object_api::inject_dynamic(&mut lua_api);
     player_api::inject_dynamic(&mut lua_api);
     player_data_api::inject_dynamic(&mut lua_api);
+    login_queue_api::inject_dynamic(&mut lua_api);
+    ban_api::inject_dynamic(&mut lua_api);
+    maintenance_api::inject_dynamic(&mut lua_api);
     widget_api::inject_dynamic(&mut lua_api);
     bot_api::inject_dynamic(&mut lua_api);
 
@@ -60,6 +60,45 @@ pub fn inject_dynamic(lua_api: &mut LuaApi) {
     }
   });
 
+  lua_api.add_dynamic_function("Net", "get_dropped_traffic", |api_ctx, lua_ctx, _| {
+    let net = api_ctx.net_ref.borrow();
+    let dropped_traffic = net.get_dropped_traffic();
+
+    let table = lua_ctx.create_table()?;
+    table.set("packets", dropped_traffic.packets())?;
+    table.set("bytes", dropped_traffic.bytes())?;
+
+    lua_ctx.pack_multi(table)
+  });
+
+  lua_api.add_dynamic_function(
+    "Net",
+    "get_player_network_stats",
+    |api_ctx, lua_ctx, params| {
+      let player_id: mlua::String = lua_ctx.unpack_multi(params)?;
+      let player_id_str = player_id.to_str()?;
+
+      let net = api_ctx.net_ref.borrow();
+
+      if let Some(stats) = net.get_player_network_stats(player_id_str) {
+        let table = lua_ctx.create_table()?;
+        table.set("rtt", stats.rtt.map(|rtt| rtt.as_secs_f32()))?;
+        table.set("jitter", stats.jitter.as_secs_f32())?;
+        table.set("packet_loss", stats.estimated_loss())?;
+        table.set("packets_sent", stats.packets_sent)?;
+        table.set("packets_received", stats.packets_received)?;
+        table.set("packets_resent", stats.packets_resent)?;
+        table.set("bytes_sent", stats.bytes_sent)?;
+        table.set("bytes_received", stats.bytes_received)?;
+        table.set("compression_bytes_saved", stats.compression_bytes_saved)?;
+
+        lua_ctx.pack_multi(table)
+      } else {
+        Err(create_player_error(player_id_str))
+      }
+    },
+  );
+
   lua_api.add_dynamic_function("Net", "get_player_name", |api_ctx, lua_ctx, params| {
     let player_id: mlua::String = lua_ctx.unpack_multi(params)?;
     let player_id_str = player_id.to_str()?;
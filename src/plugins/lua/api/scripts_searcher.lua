@@ -0,0 +1,20 @@
+-- resolves require("scripts/...") to the scripts folder, which may be outside of the working directory
+local scripts_path = ...
+
+local search_path = scripts_path.."/?.lua;"..scripts_path.."/?/init.lua"
+
+table.insert(package.searchers, 2, function(name)
+  local relative_name = name:match("^scripts/(.+)$")
+
+  if not relative_name then
+    return nil
+  end
+
+  local file_path, err = package.searchpath(relative_name, search_path)
+
+  if not file_path then
+    return err
+  end
+
+  return assert(loadfile(file_path)), file_path
+end)
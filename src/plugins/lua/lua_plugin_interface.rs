@@ -9,6 +9,7 @@ use std::collections::HashMap;
 use std::collections::VecDeque;
 
 pub struct LuaPluginInterface {
+  scripts_path: std::path::PathBuf,
   scripts: Vec<Lua>,
   all_scripts: Vec<usize>,
   widget_trackers: HashMap<String, WidgetTracker<usize>>,
@@ -18,8 +19,10 @@ pub struct LuaPluginInterface {
 }
 
 impl LuaPluginInterface {
-  pub fn new() -> LuaPluginInterface {
+  /// `scripts_path` is loaded as the scripts folder, `require("scripts/...")` resolves to files within it
+  pub fn new(scripts_path: std::path::PathBuf) -> LuaPluginInterface {
     LuaPluginInterface {
+      scripts_path,
       scripts: Vec::new(),
       all_scripts: Vec::new(),
       widget_trackers: HashMap::new(),
@@ -32,7 +35,7 @@ impl LuaPluginInterface {
   fn load_scripts(&mut self, net_ref: &mut Net) -> std::io::Result<()> {
     use std::fs::read_dir;
 
-    for wrapped_dir_entry in read_dir("./scripts")? {
+    for wrapped_dir_entry in read_dir(&self.scripts_path)? {
       let dir_path = wrapped_dir_entry?.path();
       let mut script_path = dir_path;
 
@@ -83,19 +86,29 @@ impl LuaPluginInterface {
 
     self.lua_api.inject_static(&lua_ctx)?;
 
+    let scripts_path_str = self.scripts_path.to_string_lossy().replace('\\', "/");
+
+    lua_ctx
+      .load(include_str!("api/scripts_searcher.lua"))
+      .set_name("internal: scripts_searcher.lua")?
+      .call::<_, ()>(scripts_path_str)?;
+
     self.lua_api.inject_dynamic(lua_ctx, api_ctx, |_| {
-      let parent_path = script_path
+      let relative_path = script_path
+        .strip_prefix(&self.scripts_path)
+        .unwrap_or(&script_path);
+      let parent_path = relative_path
         .parent()
         .unwrap_or_else(|| std::path::Path::new(""));
-      let stem = script_path.file_stem().unwrap_or_default();
-      let path = parent_path.join(stem);
-      let path_str = path.to_str().unwrap_or_default();
+      let stem = relative_path.file_stem().unwrap_or_default();
+      let path = std::path::Path::new("scripts").join(parent_path).join(stem);
 
-      let final_path = &path_str[2..]; // chop off the ./
+      // adjust windows paths
+      let final_path = path.to_string_lossy().replace('\\', "/");
 
       // using require to load the script for better error messages (logs the path of the file)
       let require: mlua::Function = globals.get("require")?;
-      require.call::<&str, ()>(final_path)?;
+      require.call::<&str, ()>(&final_path)?;
 
       Ok(())
     })?;
@@ -245,6 +258,24 @@ impl PluginInterface for LuaPluginInterface {
     );
   }
 
+  fn handle_player_resume(&mut self, net: &mut Net, player_id: &str) {
+    handle_event(
+      &mut self.scripts,
+      &self.all_scripts,
+      &mut self.widget_trackers,
+      &mut self.battle_trackers,
+      &mut self.promise_manager,
+      &mut self.lua_api,
+      net,
+      |lua_ctx, callback| {
+        let event = lua_ctx.create_table()?;
+        event.set("player_id", player_id)?;
+
+        callback.call(("player_resume", event))
+      },
+    );
+  }
+
   fn handle_player_disconnect(&mut self, net: &mut Net, player_id: &str) {
     handle_event(
       &mut self.scripts,
@@ -754,7 +785,9 @@ impl PluginInterface for LuaPluginInterface {
       net,
       |lua_ctx, callback| {
         let event = lua_ctx.create_table()?;
-        event.set("host", socket_address.ip().to_string())?;
+        let host = crate::helpers::canonical_ip(socket_address.ip());
+
+        event.set("host", host.to_string())?;
         event.set("port", socket_address.port())?;
         event.set("data", lua_ctx.create_string(data)?)?;
 
@@ -762,6 +795,27 @@ impl PluginInterface for LuaPluginInterface {
       },
     );
   }
+
+  fn handle_server_shutdown(&mut self, net: &mut Net) {
+    handle_event(
+      &mut self.scripts,
+      &self.all_scripts,
+      &mut self.widget_trackers,
+      &mut self.battle_trackers,
+      &mut self.promise_manager,
+      &mut self.lua_api,
+      net,
+      |lua_ctx, callback| {
+        let event = lua_ctx.create_table()?;
+
+        callback.call(("server_shutdown", event))
+      },
+    );
+  }
+
+  fn has_pending_jobs(&self) -> bool {
+    self.promise_manager.has_pending_promises()
+  }
 }
 
 #[allow(clippy::too_many_arguments)]
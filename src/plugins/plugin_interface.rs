@@ -15,6 +15,8 @@ pub trait PluginInterface {
   fn handle_player_connect(&mut self, net: &mut Net, player_id: &str);
   fn handle_player_join(&mut self, net: &mut Net, player_id: &str);
   fn handle_player_transfer(&mut self, net: &mut Net, player_id: &str);
+  /// Called when a suspended player reconnects with a resume token and is ready
+  fn handle_player_resume(&mut self, _net: &mut Net, _player_id: &str) {}
   fn handle_player_disconnect(&mut self, net: &mut Net, player_id: &str);
   fn handle_player_move(&mut self, net: &mut Net, player_id: &str, x: f32, y: f32, z: f32);
   fn handle_player_avatar_change(
@@ -67,4 +69,9 @@ pub trait PluginInterface {
     socket_address: std::net::SocketAddr,
     data: &[u8],
   );
+  fn handle_server_shutdown(&mut self, _net: &mut Net) {}
+  /// Polled during shutdown, the server waits for jobs such as file writes to complete before exiting
+  fn has_pending_jobs(&self) -> bool {
+    false
+  }
 }
@@ -4,10 +4,8 @@ use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
 
-pub const TICK_RATE: f64 = 20.0;
-
-pub fn create_clock_thread(tx: mpsc::Sender<ThreadMessage>) {
-  let target = std::time::Duration::from_secs_f64(1.0 / TICK_RATE);
+pub fn create_clock_thread(tx: mpsc::Sender<ThreadMessage>, tick_rate: f64) {
+  let target = std::time::Duration::from_secs_f64(1.0 / tick_rate);
   let behind_counter = Arc::new(AtomicU8::new(0));
 
   std::thread::spawn(move || loop {
@@ -26,6 +24,9 @@ pub fn create_clock_thread(tx: mpsc::Sender<ThreadMessage>) {
       counter_rc_copy.fetch_sub(1, Ordering::Relaxed);
     });
 
-    tx.send(ThreadMessage::Tick(start_callback)).unwrap();
+    if tx.send(ThreadMessage::Tick(start_callback)).is_err() {
+      // server stopped
+      break;
+    }
   });
 }
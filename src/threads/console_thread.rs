@@ -0,0 +1,26 @@
+use crate::threads::ThreadMessage;
+use std::io::BufRead;
+use std::sync::mpsc;
+
+pub fn create_console_thread(tx: mpsc::Sender<ThreadMessage>) {
+  std::thread::spawn(move || {
+    let stdin = std::io::stdin();
+
+    // stops when stdin closes or fails, such as when running as a service
+    for line in stdin.lock().lines().map_while(Result::ok) {
+      let command = line.trim();
+
+      if command.is_empty() {
+        continue;
+      }
+
+      if tx
+        .send(ThreadMessage::ConsoleCommand(command.to_string()))
+        .is_err()
+      {
+        // server stopped
+        break;
+      }
+    }
+  });
+}
@@ -0,0 +1,213 @@
+use log::*;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+// garbage packets and packets past the rate limit before an address is blocked
+const MAX_VIOLATIONS: u32 = 20;
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(10);
+// addresses past this share a bucket until the next cleanup, spoofed addresses could exhaust memory otherwise
+const MAX_ADDRESSES: usize = 65536;
+
+struct AddressState {
+  tokens: f32,
+  last_update: Instant,
+  violations: u32,
+  blocked_until: Option<Instant>,
+}
+
+impl AddressState {
+  fn new(capacity: f32, now: Instant) -> AddressState {
+    AddressState {
+      tokens: capacity,
+      last_update: now,
+      violations: 0,
+      blocked_until: None,
+    }
+  }
+
+  /// Refills the bucket and takes a token, returns false if the bucket is empty
+  fn take_token(&mut self, rate_limit: f32, now: Instant) -> bool {
+    // a second of packets can be sent in a burst
+    let capacity = rate_limit;
+
+    let elapsed = now - self.last_update;
+    self.tokens = (self.tokens + elapsed.as_secs_f32() * rate_limit).min(capacity);
+    self.last_update = now;
+
+    if self.tokens >= capacity {
+      // stayed under the limit long enough to be forgiven
+      self.violations = 0;
+    }
+
+    if self.tokens < 1.0 {
+      return false;
+    }
+
+    self.tokens -= 1.0;
+
+    true
+  }
+}
+
+/// Token bucket rate limits per ip address, addresses that keep sending garbage or exceeding limits are blocked for a while
+pub struct FloodGuard {
+  /// packets per second, 0 for unlimited
+  rate_limit: f32,
+  block_duration: Duration,
+  addresses: HashMap<IpAddr, AddressState>,
+  /// shared by addresses past MAX_ADDRESSES, never blocked
+  overflow: AddressState,
+  last_cleanup: Instant,
+}
+
+impl FloodGuard {
+  pub fn new(rate_limit: u32, block_duration: Duration) -> FloodGuard {
+    FloodGuard {
+      rate_limit: rate_limit as f32,
+      block_duration,
+      addresses: HashMap::new(),
+      overflow: AddressState::new(rate_limit as f32, Instant::now()),
+      last_cleanup: Instant::now(),
+    }
+  }
+
+  /// Takes a token for a packet, returns false if the packet should be dropped
+  pub fn allow(&mut self, ip: IpAddr) -> bool {
+    self.cleanup();
+
+    let now = Instant::now();
+    let capacity = self.rate_limit;
+
+    if !self.addresses.contains_key(&ip) && self.addresses.len() >= MAX_ADDRESSES {
+      return self.rate_limit == 0.0 || self.overflow.take_token(self.rate_limit, now);
+    }
+
+    let state = self
+      .addresses
+      .entry(ip)
+      .or_insert_with(|| AddressState::new(capacity, now));
+
+    if let Some(blocked_until) = state.blocked_until {
+      if now < blocked_until {
+        return false;
+      }
+
+      state.blocked_until = None;
+      state.violations = 0;
+      state.tokens = capacity;
+      state.last_update = now;
+    }
+
+    if self.rate_limit == 0.0 {
+      return true;
+    }
+
+    if !state.take_token(self.rate_limit, now) {
+      self.add_violation(ip);
+      return false;
+    }
+
+    true
+  }
+
+  /// Counts a packet that failed to parse towards a block
+  pub fn report_garbage(&mut self, ip: IpAddr) {
+    self.add_violation(ip);
+  }
+
+  fn add_violation(&mut self, ip: IpAddr) {
+    let state = match self.addresses.get_mut(&ip) {
+      Some(state) => state,
+      None => return,
+    };
+
+    state.violations += 1;
+
+    if state.violations >= MAX_VIOLATIONS {
+      warn!(
+        "Blocking {} for {}s, sending too many invalid or excess packets",
+        ip,
+        self.block_duration.as_secs_f32()
+      );
+
+      state.blocked_until = Some(Instant::now() + self.block_duration);
+    }
+  }
+
+  fn cleanup(&mut self) {
+    if self.last_cleanup.elapsed() < CLEANUP_INTERVAL {
+      return;
+    }
+
+    self.last_cleanup = Instant::now();
+
+    let rate_limit = self.rate_limit;
+
+    // forget addresses that would have a full bucket and no block
+    self.addresses.retain(|_, state| {
+      let refilled_tokens = state.tokens + state.last_update.elapsed().as_secs_f32() * rate_limit;
+
+      state.blocked_until.is_some() || (rate_limit > 0.0 && refilled_tokens < rate_limit)
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn blocking() {
+    let ip = IpAddr::from([203, 0, 113, 5]);
+    let other_ip = IpAddr::from([203, 0, 113, 6]);
+
+    let mut flood_guard = FloodGuard::new(10, Duration::from_secs(60));
+
+    let allowed = (0..10 + MAX_VIOLATIONS)
+      .filter(|_| flood_guard.allow(ip))
+      .count();
+
+    assert_eq!(allowed, 10, "burst should be limited to the rate limit");
+    assert!(!flood_guard.allow(ip));
+    assert!(flood_guard.allow(other_ip), "limits should be per address");
+
+    let mut flood_guard = FloodGuard::new(0, Duration::from_secs(60));
+
+    for _ in 0..MAX_VIOLATIONS {
+      assert!(flood_guard.allow(ip));
+      flood_guard.report_garbage(ip);
+    }
+
+    assert!(!flood_guard.allow(ip), "garbage should lead to a block");
+
+    let mut flood_guard = FloodGuard::new(0, Duration::ZERO);
+
+    for _ in 0..MAX_VIOLATIONS {
+      flood_guard.allow(ip);
+      flood_guard.report_garbage(ip);
+    }
+
+    assert!(flood_guard.allow(ip), "blocks should expire");
+  }
+
+  #[test]
+  fn address_limit() {
+    let mut flood_guard = FloodGuard::new(10, Duration::from_secs(60));
+
+    for i in 0..MAX_ADDRESSES as u128 {
+      assert!(flood_guard.allow(IpAddr::from(i.to_be_bytes())));
+    }
+
+    let allowed = (0..20)
+      .map(|i| IpAddr::from([203, 0, 113, i]))
+      .filter(|ip| flood_guard.allow(*ip))
+      .count();
+
+    assert_eq!(flood_guard.addresses.len(), MAX_ADDRESSES);
+    assert_eq!(
+      allowed, 10,
+      "addresses past the limit should share a bucket"
+    );
+  }
+}
@@ -1,34 +1,46 @@
-use crate::net::ServerConfig;
-use crate::packets::parse_client_packet;
+use super::flood_guard::FloodGuard;
+use crate::net::{DroppedTraffic, ServerConfig};
+use crate::packets::{parse_client_packet, ClientPacket, EncryptedSessions, ENCRYPTED_MARKER};
 use crate::threads::ThreadMessage;
 use log::*;
-use std::net::UdpSocket;
-use std::sync::mpsc;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
 
 pub fn create_listening_thread(
   tx: mpsc::Sender<ThreadMessage>,
   socket: UdpSocket,
   config: ServerConfig,
+  dropped_traffic: Arc<DroppedTraffic>,
+  encrypted_sessions: Arc<EncryptedSessions>,
 ) {
   let async_socket = async_std::net::UdpSocket::from(socket);
 
-  async_std::task::spawn(listen_loop(tx, async_socket, config));
+  async_std::task::spawn(listen_loop(
+    tx,
+    async_socket,
+    config,
+    dropped_traffic,
+    encrypted_sessions,
+  ));
 }
 
 async fn listen_loop(
   tx: mpsc::Sender<ThreadMessage>,
   async_socket: async_std::net::UdpSocket,
   config: ServerConfig,
+  dropped_traffic: Arc<DroppedTraffic>,
+  encrypted_sessions: Arc<EncryptedSessions>,
 ) {
-  loop {
-    let mut buf = vec![0; config.max_payload_size];
+  let mut flood_guard = FloodGuard::new(
+    config.packet_rate_limit,
+    Duration::from_secs_f32(config.flood_block_duration),
+  );
 
-    let wrapped_packet = async_socket.recv_from(&mut buf).await;
+  let mut buf = vec![0; config.max_payload_size];
 
-    if should_drop(config.receiving_drop_rate) {
-      // this must come after the recv_from
-      continue;
-    }
+  loop {
+    let wrapped_packet = async_socket.recv_from(&mut buf).await;
 
     if wrapped_packet.is_err() {
       // don't bring down the whole server over one "connection"
@@ -36,32 +48,102 @@ async fn listen_loop(
     }
 
     let (number_of_bytes, src_addr) = wrapped_packet.unwrap();
-    let filled_buf = &buf[..number_of_bytes];
+
+    if !flood_guard.allow(src_addr.ip()) {
+      dropped_traffic.record(number_of_bytes);
+      continue;
+    }
 
     if config.log_packets {
       debug!("Received packet from {}", src_addr);
     }
 
-    if let Some((headers, packet)) = parse_client_packet(filled_buf) {
+    let datagram = &buf[..number_of_bytes];
+
+    let data = match encrypted_sessions.open(src_addr, datagram) {
+      Some(data) => data,
+      None if is_handshake_resend(datagram) => datagram.to_vec(),
+      None => {
+        debug!("Received packet that failed to decrypt from {}", src_addr);
+
+        flood_guard.report_garbage(src_addr.ip());
+        dropped_traffic.record(number_of_bytes);
+        continue;
+      }
+    };
+
+    if data.first() == Some(&ENCRYPTED_MARKER) {
+      // the server hasn't processed Connect yet, the client will resend
+      dropped_traffic.record(number_of_bytes);
+      continue;
+    }
+
+    let (headers, packet) = match parse_client_packet(&data) {
+      Some(parsed) => parsed,
+      None => {
+        debug!("Received unknown packet from {}", src_addr);
+        debug!("{:?}", data);
+
+        flood_guard.report_garbage(src_addr.ip());
+        dropped_traffic.record(number_of_bytes);
+        continue;
+      }
+    };
+
+    if config.incoming_conditions.is_ideal() {
       tx.send(ThreadMessage::ClientPacket {
         socket_address: src_addr,
         headers,
         packet,
+        data,
       })
       .unwrap();
-    } else {
-      debug!("Received unknown packet from {}", src_addr);
-      debug!("{:?}", filled_buf);
+      continue;
+    }
+
+    for delay in config.incoming_conditions.roll_delays() {
+      let data = data.clone();
+
+      if delay.is_zero() {
+        forward_packet(&tx, src_addr, data);
+        continue;
+      }
+
+      let tx = tx.clone();
+
+      async_std::task::spawn(async move {
+        async_std::task::sleep(delay).await;
+        forward_packet(&tx, src_addr, data);
+      });
     }
   }
 }
 
-fn should_drop(rate: f32) -> bool {
-  if rate == 0.0 {
+/// Clients resend VersionRequest and Connect unencrypted until they receive a reply, even after the server created a session.
+/// Connect is only passed on as a duplicate, the PacketSorter acknowledges it without processing it again
+fn is_handshake_resend(datagram: &[u8]) -> bool {
+  if datagram.first() == Some(&ENCRYPTED_MARKER) {
     return false;
   }
 
-  let roll: f32 = rand::random();
+  match parse_client_packet(datagram) {
+    Some((_, ClientPacket::VersionRequest)) => true,
+    Some((headers, ClientPacket::Connect { .. })) => {
+      headers.id == 0 && headers.reliability.is_reliable()
+    }
+    _ => false,
+  }
+}
 
-  roll < rate / 100.0
+fn forward_packet(tx: &mpsc::Sender<ThreadMessage>, socket_address: SocketAddr, data: Vec<u8>) {
+  // ClientPacket can't be cloned for duplicates, the data was already validated so parsing again is safe
+  if let Some((headers, packet)) = parse_client_packet(&data) {
+    // the server may have stopped while the packet was delayed
+    let _ = tx.send(ThreadMessage::ClientPacket {
+      socket_address,
+      headers,
+      packet,
+      data,
+    });
+  }
 }
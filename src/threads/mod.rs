@@ -4,5 +4,13 @@ pub use thread_message::ThreadMessage;
 pub mod clock_thread;
 pub use clock_thread::create_clock_thread;
 
+mod console_thread;
+pub use console_thread::create_console_thread;
+
+mod flood_guard;
+
 mod listening_thread;
 pub use listening_thread::create_listening_thread;
+
+mod signal_handler;
+pub use signal_handler::create_signal_handler;
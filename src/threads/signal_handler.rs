@@ -0,0 +1,23 @@
+use crate::threads::ThreadMessage;
+use log::*;
+use std::sync::mpsc;
+
+pub fn create_signal_handler(tx: mpsc::Sender<ThreadMessage>) {
+  let mut received_signal = false;
+
+  // handles SIGINT + SIGTERM, or ctrl-c + closing the console on windows
+  let result = ctrlc::set_handler(move || {
+    if received_signal {
+      warn!("Forcing shutdown");
+      std::process::exit(1);
+    }
+
+    received_signal = true;
+
+    let _ = tx.send(ThreadMessage::Shutdown);
+  });
+
+  if let Err(err) = result {
+    warn!("Failed to listen for shutdown signals: {}", err);
+  }
+}
@@ -2,9 +2,21 @@ use crate::packets::{ClientPacket, PacketHeaders};
 
 pub enum ThreadMessage {
   Tick(Box<dyn FnOnce() + Send>),
+  ManualTick {
+    delta_time: f32,
+  },
   ClientPacket {
     socket_address: std::net::SocketAddr,
     headers: PacketHeaders,
     packet: ClientPacket,
+    /// the datagram the packet was decoded from
+    data: Vec<u8>,
+  },
+  ConsoleCommand(String),
+  /// Only sent while replaying, replaces the token issued to the player at this address with the recorded token
+  ResumeToken {
+    socket_address: std::net::SocketAddr,
+    token: String,
   },
+  Shutdown,
 }
@@ -0,0 +1,322 @@
+use net_battle_server::packets::PacketOrchestrator;
+use net_battle_server::{Net, ServerConfig};
+use std::cell::RefCell;
+use std::net::UdpSocket;
+use std::rc::Rc;
+
+#[test]
+fn net_loads_content() {
+  let socket = Rc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+  let config = Rc::new(ServerConfig::default());
+  let packet_orchestrator = Rc::new(RefCell::new(PacketOrchestrator::new(
+    socket.clone(),
+    config.max_payload_size,
+    0,
+    0,
+  )));
+
+  let net = Net::new(socket, packet_orchestrator, config);
+
+  assert!(net.get_area("default").is_some());
+  assert!(net.get_asset("/server/assets/prog.png").is_some());
+  assert!(net.get_asset("/server/maps/default.tmx").is_some());
+}
+
+#[test]
+fn manual_clock() {
+  use net_battle_server::{LuaPluginInterface, ServerBuilder};
+
+  let test_dir = std::env::temp_dir().join(format!("manual_clock_{}", std::process::id()));
+  let scripts_dir = test_dir.join("scripts");
+  let output_path = test_dir.join("output.txt");
+
+  std::fs::create_dir_all(&scripts_dir).unwrap();
+  std::fs::write(
+    scripts_dir.join("main.lua"),
+    format!(
+      r#"
+        local ticks = 0
+        local total_time = 0
+
+        Net:on("tick", function(event)
+          ticks = ticks + 1
+          total_time = total_time + event.delta_time
+        end)
+
+        Net:on("server_shutdown", function()
+          Async.write_file({:?}, ticks .. " " .. total_time)
+        end)
+      "#,
+      output_path.to_string_lossy()
+    ),
+  )
+  .unwrap();
+
+  let mut server = ServerBuilder::new(ServerConfig::default())
+    .plugin_interface(Box::new(LuaPluginInterface::new(scripts_dir)))
+    .socket(UdpSocket::bind("127.0.0.1:0").unwrap())
+    .build();
+
+  let clock = server.create_manual_clock();
+
+  std::thread::spawn(move || {
+    for _ in 0..10 {
+      clock.step(0.25);
+    }
+
+    clock.shutdown();
+  });
+
+  server.start().unwrap();
+
+  let output = std::fs::read_to_string(&output_path).unwrap();
+  let _ = std::fs::remove_dir_all(&test_dir);
+
+  assert_eq!(output, "10 2.5");
+}
+
+/// Speaks just enough of the protocol to log in and resume, without encryption or compression
+struct TestClient {
+  socket: UdpSocket,
+  next_reliable_id: u64,
+}
+
+impl TestClient {
+  const VERSION_INFO_ID: u16 = 0;
+  const ACK_ID: u16 = 1;
+  const LOGIN_ID: u16 = 4;
+  const KICK_ID: u16 = 10;
+
+  fn connect(server_address: std::net::SocketAddr) -> TestClient {
+    use net_battle_server::packets::bytes::*;
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.connect(server_address).unwrap();
+    socket
+      .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+      .unwrap();
+
+    let mut client = TestClient {
+      socket,
+      next_reliable_id: 0,
+    };
+
+    // VersionRequest
+    client.socket.send(&[0, 0, 0]).unwrap();
+
+    let version_info = client.receive(TestClient::VERSION_INFO_ID);
+    let mut work_buf = version_info.as_slice();
+    read_string_u16(&mut work_buf).unwrap();
+    read_u64(&mut work_buf).unwrap();
+    read_u16(&mut work_buf).unwrap();
+    read_bool(&mut work_buf).unwrap();
+    read_string_u16(&mut work_buf).unwrap();
+    read_byte(&mut work_buf).unwrap();
+    let cookie = read_u64(&mut work_buf).unwrap();
+    let server_random: [u8; 16] = read_bytes(&mut work_buf).unwrap();
+
+    let mut connect = Vec::new();
+    write_u16(&mut connect, 30);
+    write_u64(&mut connect, cookie);
+    connect.extend(server_random);
+    client.send_reliable(&connect);
+
+    client
+  }
+
+  /// Returns the resume token from the Login packet
+  fn login(&mut self) -> String {
+    use net_battle_server::packets::bytes::*;
+
+    let mut login = Vec::new();
+    write_u16(&mut login, 7);
+    write_string_u8(&mut login, "test");
+    login.push(4);
+    login.extend(b"test");
+    write_string_u16(&mut login, "");
+    login.push(0);
+    self.send_reliable(&login);
+
+    // RequestJoin
+    self.send_reliable(&[9, 0]);
+    self.join()
+  }
+
+  fn resume(&mut self, token: &str) -> String {
+    self.send_resume(token);
+    self.join()
+  }
+
+  fn send_resume(&mut self, token: &str) {
+    use net_battle_server::packets::bytes::*;
+
+    let mut resume = Vec::new();
+    write_u16(&mut resume, 32);
+    write_string_u8(&mut resume, token);
+    self.send_reliable(&resume);
+  }
+
+  // reads the resume token from Login and sends Ready
+  fn join(&mut self) -> String {
+    use net_battle_server::packets::bytes::*;
+
+    let login = self.receive(TestClient::LOGIN_ID);
+    let mut work_buf = login.as_slice();
+    read_string_u16(&mut work_buf).unwrap();
+    read_bool(&mut work_buf).unwrap();
+    read_f32(&mut work_buf).unwrap();
+    read_f32(&mut work_buf).unwrap();
+    read_f32(&mut work_buf).unwrap();
+    read_byte(&mut work_buf).unwrap();
+    let resume_token = read_string_u8(&mut work_buf).unwrap();
+
+    let mut ready = Vec::new();
+    write_u16(&mut ready, 10);
+    write_u64(&mut ready, 0);
+    self.send_reliable(&ready);
+
+    resume_token
+  }
+
+  /// Returns once the server acknowledges the packet, the server has handled it by then
+  fn send_reliable(&mut self, body: &[u8]) {
+    use net_battle_server::packets::bytes::*;
+
+    let id = self.next_reliable_id;
+    self.next_reliable_id += 1;
+
+    // ReliableOrdered
+    let mut buf = vec![4];
+    write_u64(&mut buf, id);
+    buf.extend(body);
+    self.socket.send(&buf).unwrap();
+
+    let mut expected_ack = vec![4];
+    write_u64(&mut expected_ack, id);
+
+    while self.receive(TestClient::ACK_ID) != expected_ack {}
+  }
+
+  /// Acknowledges reliable packets until a packet with the id is received, returns the body following the id
+  fn receive(&self, packet_id: u16) -> Vec<u8> {
+    use net_battle_server::packets::bytes::*;
+
+    let mut buf = [0; 65536];
+
+    loop {
+      let size = self
+        .socket
+        .recv(&mut buf)
+        .expect("timed out waiting for the server");
+      let mut work_buf = &buf[..size];
+
+      let reliability = read_byte(&mut work_buf).unwrap();
+
+      if reliability > 0 {
+        let id = read_u64(&mut work_buf).unwrap();
+
+        if reliability == 3 {
+          // channel
+          read_u64(&mut work_buf).unwrap();
+        }
+
+        let mut ack = vec![0];
+        write_u16(&mut ack, TestClient::ACK_ID);
+        ack.push(reliability);
+        write_u64(&mut ack, id);
+        self.socket.send(&ack).unwrap();
+      }
+
+      if read_u16(&mut work_buf) == Some(packet_id) {
+        return work_buf.to_vec();
+      }
+    }
+  }
+}
+
+#[test]
+fn resume() {
+  use net_battle_server::{LuaPluginInterface, ServerBuilder};
+
+  let test_dir = std::env::temp_dir().join(format!("resume_{}", std::process::id()));
+  let scripts_dir = test_dir.join("scripts");
+  let output_path = test_dir.join("output.txt");
+
+  std::fs::create_dir_all(&scripts_dir).unwrap();
+  std::fs::write(
+    scripts_dir.join("main.lua"),
+    format!(
+      r#"
+        local events = {{}}
+
+        for _, name in ipairs({{ "player_join", "player_resume", "player_disconnect" }}) do
+          Net:on(name, function()
+            events[#events + 1] = name
+          end)
+        end
+
+        Net:on("server_shutdown", function()
+          Async.write_file({:?}, table.concat(events, " "))
+        end)
+      "#,
+      output_path.to_string_lossy()
+    ),
+  )
+  .unwrap();
+
+  let config = ServerConfig {
+    resend_budget: usize::MAX / 2,
+    outgoing_rate_limit: 0,
+    packet_rate_limit: 0,
+    max_silence_duration: 5.0,
+    resume_grace_period: 10.0,
+    ..ServerConfig::default()
+  };
+
+  let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+  let server_address = socket.local_addr().unwrap();
+
+  let mut server = ServerBuilder::new(config)
+    .plugin_interface(Box::new(LuaPluginInterface::new(scripts_dir)))
+    .socket(socket)
+    .build();
+
+  let clock = server.create_manual_clock();
+
+  let client_thread = std::thread::spawn(move || {
+    let result = std::panic::catch_unwind(|| {
+      let resume_token = TestClient::connect(server_address).login();
+
+      // silent past max_silence_duration, suspended
+      clock.step(6.0);
+
+      let mut client = TestClient::connect(server_address);
+      let reissued_token = client.resume(&resume_token);
+      assert_ne!(reissued_token, resume_token, "tokens should be reissued");
+
+      // suspended again, then expiring past resume_grace_period
+      clock.step(6.0);
+      clock.step(11.0);
+
+      let mut client = TestClient::connect(server_address);
+      client.send_resume(&reissued_token);
+
+      let kick = client.receive(TestClient::KICK_ID);
+      assert_eq!(&kick[2..], b"Session expired");
+    });
+
+    clock.shutdown();
+    result
+  });
+
+  server.start().unwrap();
+
+  if let Err(panic) = client_thread.join().unwrap() {
+    std::panic::resume_unwind(panic);
+  }
+
+  let output = std::fs::read_to_string(&output_path).unwrap();
+  let _ = std::fs::remove_dir_all(&test_dir);
+
+  assert_eq!(output, "player_join player_resume player_disconnect");
+}